@@ -1,7 +1,7 @@
 // Complete CLI tool for file conversion
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use file_converter::{FileConverter, PdfConfig, FileType};
+use file_converter::{FileConverter, PdfConfig, FileType, TextExtractConfig};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -41,15 +41,46 @@ enum Commands {
         margins: u8,
     },
 
+    /// Merge several text files into one PDF, one section per file
+    Merge {
+        /// Input text file paths, in section order
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Output PDF file path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Document title
+        #[arg(short, long, default_value = "Merged Document")]
+        title: String,
+
+        /// Add a table-of-contents page
+        #[arg(long)]
+        toc: bool,
+
+        /// Write the sections that converted if others fail
+        #[arg(long)]
+        allow_partial: bool,
+
+        /// Stop converting sections after this many seconds (implies --allow-partial)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
+
     /// Extract text from PDF file
     PdfToText {
         /// Input PDF file path
         #[arg(short, long)]
         input: PathBuf,
 
-        /// Output text file path
+        /// Output text file path; with --split-pages, the directory for the page files
         #[arg(short, long)]
         output: PathBuf,
+
+        /// Write one text file per page (page-001.txt, page-002.txt, ...)
+        #[arg(long)]
+        split_pages: bool,
     },
 
     /// Detect file type
@@ -95,7 +126,51 @@ fn main() -> Result<()> {
             println!("✅ Success: {} → {}", input.display(), output.display());
         }
 
-        Commands::PdfToText { input, output } => {
+        Commands::Merge { inputs, output, title, toc, allow_partial, timeout } => {
+            let config = PdfConfig {
+                title,
+                include_toc: toc,
+                ..Default::default()
+            };
+
+            let mut sections = Vec::with_capacity(inputs.len());
+            for input in &inputs {
+                let section_title = input.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+                sections.push((section_title, std::fs::read_to_string(input)?));
+            }
+
+            println!("📝 → 📕 Merging {} text files into PDF...", inputs.len());
+            if allow_partial || timeout.is_some() {
+                let deadline = timeout.map(|secs| std::time::Instant::now() + std::time::Duration::from_secs(secs));
+                let merged = converter.texts_to_pdf_partial(sections, &config, deadline)?;
+                std::fs::write(&output, &merged.data)?;
+                if merged.partial {
+                    println!(
+                        "⚠️  Partial: {} of {} files → {}",
+                        merged.sections_converted, merged.total_sections, output.display()
+                    );
+                    for &index in &merged.missing {
+                        println!("   missing: {}", inputs[index].display());
+                    }
+                    std::process::exit(2);
+                }
+            } else {
+                let pdf_bytes = converter.texts_to_pdf(sections, &config)?;
+                std::fs::write(&output, pdf_bytes)?;
+            }
+            println!("✅ Success: {} files → {}", inputs.len(), output.display());
+        }
+
+        Commands::PdfToText { input, output, split_pages: true } => {
+            println!("📕 → 📝 Extracting text from PDF page by page...");
+            let pages = converter.pdf_file_to_text_pages(&input, &output, &TextExtractConfig::default())?;
+            println!("✅ Success: {} → {} pages in {}", input.display(), pages.len(), output.display());
+        }
+
+        Commands::PdfToText { input, output, split_pages: false } => {
             println!("📕 → 📝 Extracting text from PDF...");
             converter.pdf_file_to_text(&input, &output)?;
             println!("✅ Success: {} → {}", input.display(), output.display());
@@ -109,11 +184,13 @@ fn main() -> Result<()> {
         Commands::Detect { files } => {
             println!("🔍 File type detection:");
             for file in files {
-                match converter.detect_file_type(&file) {
+                match converter.detect_file_type_with_name(&file) {
                     Ok(file_type) => {
                         let icon = match file_type {
                             FileType::Pdf => "📕",
                             FileType::Text => "📝", 
+                            FileType::Image => "🖼️",
+                            FileType::Code => "💻",
                             FileType::Unknown => "❓",
                         };
                         println!("  {} {} → {}", icon, file.display(), file_type);
@@ -1,18 +1,74 @@
 use anyhow::{Context, Result};
+#[cfg(feature = "pdf")]
 use genpdf::{
-    elements::{Paragraph, Text, LinearLayout, TableLayout, StyledElement},
+    elements::{Break, PageBreak, Paragraph, Text, LinearLayout, TableLayout, StyledElement},
     fonts::{self, FontData, FontFamily},
-    style::{Color, Style},
+    style::Style,
     Document, Element, Alignment, SimplePageDecorator,
 };
-use pdf_extract::extract_text;
+#[cfg(feature = "pdf")]
+pub use genpdf::style::Color;
+#[cfg(feature = "pdf")]
+use pdf_extract::{extract_text, extract_text_from_mem_by_pages};
+#[cfg(feature = "code")]
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Style as SyntectStyle, Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
 use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tracing::{debug, error, info, warn};
 
+/// Read size used by the streaming conversion API
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Write `bytes` to a conversion sink in `STREAM_CHUNK_SIZE` pieces,
+/// returning how many were written
+async fn write_stream_chunks<W>(writer: &mut W, bytes: &[u8]) -> Result<u64>
+where
+    W: AsyncWrite + Unpin,
+{
+    for chunk in bytes.chunks(STREAM_CHUNK_SIZE) {
+        writer.write_all(chunk).await
+            .with_context(|| "Failed to write conversion output")?;
+    }
+    Ok(bytes.len() as u64)
+}
+
+/// Default cap on the resolution of images embedded in a PDF
+pub const DEFAULT_MAX_IMAGE_DPI: f64 = 300.0;
+
+/// genpdf's default paper size (A4), in millimetres
+pub const PAGE_SIZE_MM: (f64, f64) = (210.0, 297.0);
+
+/// Monospace font family used for source code listings
+pub const CODE_FONT_FAMILY: &str = "LiberationMono";
+
+/// Columns per code line when the config doesn't set a line width
+const DEFAULT_CODE_LINE_WIDTH: usize = 100;
+
+/// Columns a tab expands to in code listings
+const CODE_TAB_WIDTH: usize = 4;
+
+/// syntect theme used for highlighting; light, to suit a printed page
+#[cfg(feature = "code")]
+const CODE_THEME: &str = "InspiredGitHub";
+
+/// Extensions of files treated as source code, lower case. Each doubles as
+/// the language token passed to [`FileConverter::code_to_pdf`].
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "ts", "go", "c", "h", "cc", "cpp", "hpp", "java", "kt", "cs", "swift",
+    "rb", "php", "pl", "lua", "scala", "sh", "bash", "sql", "html", "css", "json", "yaml",
+    "yml", "toml", "xml",
+];
+
 /// Custom error types for file conversion operations
 #[derive(Error, Debug)]
 pub enum ConversionError {
@@ -37,17 +93,30 @@ pub enum ConversionError {
     #[error("Font loading failed: {0}")]
     FontLoadingFailed(String),
 
+    #[error("Text encoding failed: {0}")]
+    EncodingFailed(String),
+
+    #[error("Conversion output exceeded limit: {used} bytes (limit {limit} bytes)")]
+    MemoryLimit { used: u64, limit: u64 },
+
+    #[error("Unsupported format: {format} is not available in this build (supported: {supported:?})")]
+    UnsupportedFormat { format: String, supported: Vec<String> },
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
 /// Supported file types based on magic number detection
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FileType {
     /// PDF document (%PDF signature)
     Pdf,
     /// Plain text file (UTF-8, ASCII, or other text encoding)
     Text,
+    /// PNG or JPEG image
+    Image,
+    /// Source code: text with a known code extension
+    Code,
     /// Unknown or unsupported file type
     Unknown,
 }
@@ -57,6 +126,8 @@ impl std::fmt::Display for FileType {
         match self {
             FileType::Pdf => write!(f, "PDF"),
             FileType::Text => write!(f, "Text"),
+            FileType::Image => write!(f, "Image"),
+            FileType::Code => write!(f, "Code"),
             FileType::Unknown => write!(f, "Unknown"),
         }
     }
@@ -75,6 +146,10 @@ impl MagicNumbers {
         // PDF signatures - %PDF- (0x25, 0x50, 0x44, 0x46, 0x2D)
         signatures.insert(vec![0x25, 0x50, 0x44, 0x46], FileType::Pdf); // %PDF
 
+        // Images that can be placed in a PDF
+        signatures.insert(vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A], FileType::Image); // PNG
+        signatures.insert(vec![0xFF, 0xD8, 0xFF], FileType::Image); // JPEG
+
         Self { signatures }
     }
 
@@ -163,6 +238,14 @@ pub struct PdfConfig {
     pub font_family: String,
     /// Maximum characters per line (for text wrapping)
     pub max_chars_per_line: Option<usize>,
+    /// Add a table-of-contents page when merging multiple texts
+    pub include_toc: bool,
+    /// Heading of the table-of-contents page
+    pub toc_title: String,
+    /// Images with more detail than this are downscaled before embedding
+    pub max_image_dpi: f64,
+    /// Paper width and height in millimetres
+    pub page_size: (f64, f64),
 }
 
 impl Default for PdfConfig {
@@ -175,23 +258,398 @@ impl Default for PdfConfig {
             text_color: Color::Rgb(0, 0, 0), // Black
             font_family: "LiberationSans".to_string(),
             max_chars_per_line: Some(80),
+            include_toc: false,
+            toc_title: "Contents".to_string(),
+            max_image_dpi: DEFAULT_MAX_IMAGE_DPI,
+            page_size: PAGE_SIZE_MM,
+        }
+    }
+}
+
+/// A merged PDF that may be missing some sections
+#[derive(Debug, Clone)]
+pub struct MergedPdf {
+    /// Rendered PDF bytes
+    pub data: Vec<u8>,
+    /// Sections included
+    pub sections_converted: usize,
+    /// Sections in the input
+    pub total_sections: usize,
+    /// Input positions of the sections left out, in order
+    pub missing: Vec<usize>,
+    /// True when some sections were left out
+    pub partial: bool,
+}
+
+/// Character encoding for extracted text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 (default)
+    Utf8,
+    /// UTF-16, little endian
+    Utf16Le,
+    /// ISO-8859-1; characters above U+00FF cannot be encoded
+    Latin1,
+}
+
+/// Line ending style for extracted text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Unix style `\n`
+    Lf,
+    /// Windows style `\r\n`
+    CrLf,
+    /// Keep whatever the PDF produced
+    Preserve,
+}
+
+/// Configuration for PDF text extraction output
+#[derive(Debug, Clone)]
+pub struct TextExtractConfig {
+    /// Output character encoding
+    pub encoding: Encoding,
+    /// Line ending normalization
+    pub line_endings: LineEnding,
+    /// Prefix output with a byte order mark (ignored for Latin-1)
+    pub emit_bom: bool,
+}
+
+impl Default for TextExtractConfig {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf8,
+            line_endings: LineEnding::Lf,
+            emit_bom: false,
+        }
+    }
+}
+
+impl TextExtractConfig {
+    /// Normalize line endings and encode text according to this config
+    pub fn encode(&self, text: &str) -> std::result::Result<Vec<u8>, ConversionError> {
+        let text = match self.line_endings {
+            LineEnding::Preserve => text.to_string(),
+            LineEnding::Lf => text.replace("\r\n", "\n").replace('\r', "\n"),
+            LineEnding::CrLf => text
+                .replace("\r\n", "\n")
+                .replace('\r', "\n")
+                .replace('\n', "\r\n"),
+        };
+
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+
+        match self.encoding {
+            Encoding::Utf8 => {
+                if self.emit_bom {
+                    bytes.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+                }
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Encoding::Utf16Le => {
+                if self.emit_bom {
+                    bytes.extend_from_slice(&[0xFF, 0xFE]);
+                }
+                for unit in text.encode_utf16() {
+                    bytes.extend_from_slice(&unit.to_le_bytes());
+                }
+            }
+            Encoding::Latin1 => {
+                for (offset, c) in text.char_indices() {
+                    let code = c as u32;
+                    if code > 0xFF {
+                        return Err(ConversionError::EncodingFailed(format!(
+                            "character {:?} (U+{:04X}) at byte offset {} cannot be encoded as Latin-1",
+                            c, code, offset
+                        )));
+                    }
+                    bytes.push(code as u8);
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Limits protecting against inputs that expand enormously on conversion
+#[derive(Debug, Clone)]
+pub struct ConversionLimits {
+    /// Maximum ratio of output size to input size
+    pub max_expansion_ratio: f64,
+    /// Output always allowed regardless of ratio (covers fixed PDF overhead like fonts)
+    pub min_output_allowance: u64,
+    /// Largest image, in pixels, that will be decoded. Checked against the
+    /// header before decoding, since a few KB of PNG can claim gigapixels.
+    pub max_image_pixels: u64,
+}
+
+impl Default for ConversionLimits {
+    fn default() -> Self {
+        Self {
+            max_expansion_ratio: 100.0,
+            min_output_allowance: 8 * 1024 * 1024, // 8MB
+            max_image_pixels: 100_000_000,           // 100 megapixels
+        }
+    }
+}
+
+impl ConversionLimits {
+    /// Maximum output size allowed for an input of `input_len` bytes
+    pub fn output_limit(&self, input_len: usize) -> u64 {
+        let by_ratio = (input_len as f64 * self.max_expansion_ratio) as u64;
+        by_ratio.max(self.min_output_allowance)
+    }
+}
+
+/// EXIF orientation tag of a JPEG (1 to 8), or 1 when there is none
+#[cfg(feature = "images")]
+fn exif_orientation(data: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(data))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+/// Rotate/flip decoded pixels so they display the way the camera intended
+#[cfg(feature = "images")]
+fn apply_exif_orientation(image: image::DynamicImage, orientation: u32) -> image::DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Pixel size and DPI to embed a `width`x`height` image at so it fills as
+/// much of a `page_mm` area as possible without exceeding `max_dpi`.
+/// A `max_dpi` of zero or less means no limit.
+#[cfg(feature = "images")]
+fn fit_to_page(width: u32, height: u32, page_mm: (f64, f64), max_dpi: f64) -> (u32, u32, f64) {
+    let page_inches = (page_mm.0 / 25.4, page_mm.1 / 25.4);
+    // DPI at which the image exactly fills the page in its tighter dimension
+    let fit_dpi = (width as f64 / page_inches.0).max(height as f64 / page_inches.1);
+    if max_dpi <= 0.0 || fit_dpi <= max_dpi {
+        return (width, height, fit_dpi);
+    }
+
+    let scale = max_dpi / fit_dpi;
+    let scaled = |pixels: u32| ((pixels as f64 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height), max_dpi)
+}
+
+/// In-memory writer that fails once a byte limit would be exceeded
+#[cfg(feature = "pdf")]
+struct LimitedWriter {
+    buffer: Vec<u8>,
+    limit: u64,
+    attempted: u64,
+}
+
+#[cfg(feature = "pdf")]
+impl LimitedWriter {
+    fn new(limit: u64) -> Self {
+        Self {
+            buffer: Vec::new(),
+            limit,
+            attempted: 0,
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.attempted > self.limit
+    }
+}
+
+#[cfg(feature = "pdf")]
+impl std::io::Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.attempted = self.buffer.len() as u64 + buf.len() as u64;
+        if self.exceeded() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "conversion output limit exceeded",
+            ));
+        }
+
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Text colour for builds without the PDF backend, where nothing is rendered
+#[cfg(not(feature = "pdf"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Rgb(u8, u8, u8),
+}
+
+/// Language of a source file, taken from its extension, e.g. `"rs"` for
+/// `main.rs`. `None` for anything not in the list of code extensions.
+pub fn code_language(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    CODE_EXTENSIONS.iter().copied().find(|known| *known == extension)
+}
+
+/// Break a line of styled segments into rows of at most `width` characters.
+/// Breaks fall at exactly `width` characters rather than at spaces, so a
+/// listing wraps the same way whatever the line contains.
+#[cfg(feature = "pdf")]
+fn wrap_code_line<S: Clone>(segments: &[(S, String)], width: usize) -> Vec<Vec<(S, String)>> {
+    let width = width.max(1);
+    let mut rows = vec![Vec::new()];
+    let mut used = 0;
+    for (style, text) in segments {
+        let mut rest = text.as_str();
+        while !rest.is_empty() {
+            if used == width {
+                rows.push(Vec::new());
+                used = 0;
+            }
+            let split = rest.char_indices().nth(width - used).map_or(rest.len(), |(i, _)| i);
+            let (head, tail) = rest.split_at(split);
+            rows.last_mut().unwrap().push((style.clone(), head.to_string()));
+            used += head.chars().count();
+            rest = tail;
+        }
+    }
+    rows
+}
+
+/// Split code into lines of styled segments, highlighted when the language
+/// is known and plain otherwise
+#[cfg(feature = "pdf")]
+fn highlight_code(code: &str, language: Option<&str>, color: Color) -> Vec<Vec<(Style, String)>> {
+    if let Some(language) = language {
+        match syntax_highlight(code, language) {
+            Some(lines) => return lines,
+            None => debug!("No highlighting for language '{}', using plain monospace", language),
         }
     }
+
+    let plain = Style::new().with_color(color);
+    code.lines().map(|line| vec![(plain, line.to_string())]).collect()
+}
+
+/// Highlight code with syntect; `None` if the language is unknown or the
+/// highlighter fails
+#[cfg(all(feature = "pdf", feature = "code"))]
+fn syntax_highlight(code: &str, language: &str) -> Option<Vec<Vec<(Style, String)>>> {
+    static HIGHLIGHTING: std::sync::OnceLock<(SyntaxSet, Theme)> = std::sync::OnceLock::new();
+    let (syntaxes, theme) = HIGHLIGHTING.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = themes.themes.remove(CODE_THEME).expect("theme ships with syntect");
+        (SyntaxSet::load_defaults_newlines(), theme)
+    });
+
+    let syntax = syntaxes.find_syntax_by_token(language)?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(code) {
+        let ranges = highlighter.highlight_line(line, syntaxes).ok()?;
+        lines.push(
+            ranges
+                .into_iter()
+                .map(|(style, text)| (pdf_style(style), text.trim_end_matches(['\r', '\n']).to_string()))
+                .filter(|(_, text)| !text.is_empty())
+                .collect(),
+        );
+    }
+    Some(lines)
+}
+
+/// Builds without the `code` feature have no highlighter
+#[cfg(all(feature = "pdf", not(feature = "code")))]
+fn syntax_highlight(_code: &str, _language: &str) -> Option<Vec<Vec<(Style, String)>>> {
+    None
+}
+
+#[cfg(all(feature = "pdf", feature = "code"))]
+fn pdf_style(style: SyntectStyle) -> Style {
+    let fg = style.foreground;
+    let mut pdf = Style::new().with_color(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        pdf = pdf.bold();
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        pdf = pdf.italic();
+    }
+    pdf
+}
+
+/// Name of the text file for a page: `page-001.txt` for the first page.
+/// Numbers are padded to at least three digits, more if the document has
+/// over 999 pages, so the files sort in page order.
+pub fn page_text_filename(page_index: usize, page_count: usize) -> String {
+    let width = page_count.to_string().len().max(3);
+    format!("page-{:0width$}.txt", page_index + 1, width = width)
+}
+
+/// Error for a conversion whose backend was left out of this build
+fn backend_unavailable(format: &str) -> ConversionError {
+    ConversionError::UnsupportedFormat {
+        format: format.to_string(),
+        supported: FileConverter::supported_conversions()
+            .iter()
+            .map(|(source, target)| format!("{} to {}", source, target))
+            .collect(),
+    }
 }
 
 /// File converter with support for text-to-PDF and PDF-to-text
 pub struct FileConverter {
     magic_numbers: MagicNumbers,
+    #[cfg(feature = "pdf")]
     font_cache: HashMap<String, FontFamily<FontData>>,
+    limits: ConversionLimits,
 }
 
 impl FileConverter {
     /// Create a new file converter instance
     pub fn new() -> Self {
+        Self::with_limits(ConversionLimits::default())
+    }
+
+    /// Create a file converter with custom output limits
+    pub fn with_limits(limits: ConversionLimits) -> Self {
         Self {
             magic_numbers: MagicNumbers::new(),
+            #[cfg(feature = "pdf")]
             font_cache: HashMap::new(),
+            limits,
+        }
+    }
+
+    /// Conversions this build can run, as (source type, target format).
+    /// Depends on the `pdf` and `images` cargo features.
+    pub fn supported_conversions() -> Vec<(FileType, &'static str)> {
+        let mut conversions = Vec::new();
+        #[cfg(feature = "pdf")]
+        {
+            conversions.push((FileType::Text, "pdf"));
+            conversions.push((FileType::Code, "pdf"));
+            conversions.push((FileType::Pdf, "txt"));
         }
+        #[cfg(feature = "images")]
+        conversions.push((FileType::Image, "pdf"));
+        conversions
+    }
+
+    /// Output limits applied to conversions
+    pub fn limits(&self) -> &ConversionLimits {
+        &self.limits
     }
 
     /// Detect file type from file path
@@ -202,15 +660,337 @@ impl FileConverter {
         Ok(self.magic_numbers.detect_from_bytes(&bytes))
     }
 
+    /// Detect file type from content, then report text files with a code
+    /// extension as `FileType::Code`
+    pub fn detect_file_type_with_name<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        let path = path.as_ref();
+        match self.detect_file_type(path)? {
+            FileType::Text if code_language(path).is_some() => Ok(FileType::Code),
+            file_type => Ok(file_type),
+        }
+    }
+
     /// Detect file type from byte content
     pub fn detect_file_type_from_bytes(&self, bytes: &[u8]) -> FileType {
         self.magic_numbers.detect_from_bytes(bytes)
     }
 
     /// Convert text content to PDF bytes
+    #[cfg(feature = "pdf")]
     pub fn text_to_pdf(&mut self, text: &str, config: &PdfConfig) -> Result<Vec<u8>> {
         info!("Converting text to PDF with title: '{}'", config.title);
 
+        let mut doc = self.create_document(config)?;
+        self.push_text(&mut doc, text, config);
+
+        let buffer = self.render_document(doc, text.len())?;
+        info!("Successfully generated PDF with {} bytes", buffer.len());
+        Ok(buffer)
+    }
+
+    /// Render source code as a PDF listing in a monospace font, with line
+    /// numbers and, in builds with the `code` feature, syntax colours.
+    ///
+    /// `language` is a name or extension such as `"rust"` or `"py"`; code in
+    /// an unknown language is rendered as plain monospace text. Lines longer
+    /// than `config.max_chars_per_line` (100 when unset) are broken at
+    /// exactly that column and the continuation rows are left unnumbered,
+    /// so nothing is clipped.
+    ///
+    /// Listings use [`CODE_FONT_FAMILY`]. Where neither it nor the generic
+    /// fallback font can be loaded they use `config.font_family`, which
+    /// keeps the layout but isn't monospace.
+    #[cfg(feature = "pdf")]
+    pub fn code_to_pdf(&mut self, code: &str, language: Option<&str>, config: &PdfConfig) -> Result<Vec<u8>> {
+        info!(
+            "Converting {} code to PDF with title: '{}'",
+            language.unwrap_or("plain"), config.title
+        );
+
+        let font_family = match self.get_or_load_font(CODE_FONT_FAMILY) {
+            Ok(_) => CODE_FONT_FAMILY.to_string(),
+            Err(e) => {
+                warn!("{}; rendering code in '{}' instead", e, config.font_family);
+                config.font_family.clone()
+            }
+        };
+        let code_config = PdfConfig {
+            font_family,
+            ..config.clone()
+        };
+        let mut doc = self.create_document(&code_config)?;
+
+        let code = code.replace('\t', &" ".repeat(CODE_TAB_WIDTH));
+        let lines = highlight_code(&code, language, config.text_color);
+        let width = config.max_chars_per_line.unwrap_or(DEFAULT_CODE_LINE_WIDTH);
+        let gutter = lines.len().max(1).to_string().len();
+        let number_style = Style::new()
+            .with_font_size(config.font_size)
+            .with_color(Color::Rgb(128, 128, 128));
+
+        for (index, segments) in lines.iter().enumerate() {
+            for (row, row_segments) in wrap_code_line(segments, width).into_iter().enumerate() {
+                let number = if row == 0 {
+                    format!("{:>width$} ", index + 1, width = gutter)
+                } else {
+                    " ".repeat(gutter + 1)
+                };
+                let mut paragraph = Paragraph::default();
+                paragraph.push_styled(number, number_style);
+                for (style, text) in row_segments {
+                    paragraph.push_styled(text, style.with_font_size(config.font_size));
+                }
+                doc.push(paragraph);
+            }
+        }
+
+        let buffer = self.render_document(doc, code.len())?;
+        info!("Successfully generated code PDF with {} lines ({} bytes)", lines.len(), buffer.len());
+        Ok(buffer)
+    }
+
+    /// Merge several texts into one PDF, one section per (title, body) pair.
+    ///
+    /// Each section starts on a new page with its title as a heading. When
+    /// `config.include_toc` is set, a contents page listing the sections is
+    /// inserted first.
+    #[cfg(feature = "pdf")]
+    pub fn texts_to_pdf(&mut self, sections: Vec<(String, String)>, config: &PdfConfig) -> Result<Vec<u8>> {
+        if sections.is_empty() {
+            return Err(ConversionError::InvalidInput(
+                "No sections to merge into PDF".to_string()
+            ).into());
+        }
+
+        info!(
+            "Merging {} texts into PDF with title: '{}'",
+            sections.len(), config.title
+        );
+
+        let buffer = self.render_sections(&sections, config, None)?;
+        info!(
+            "Successfully generated merged PDF with {} sections ({} bytes)",
+            sections.len(), buffer.len()
+        );
+        Ok(buffer)
+    }
+
+    /// Merge texts like `texts_to_pdf`, keeping the sections that convert.
+    ///
+    /// All sections are rendered together once. Only if that render fails
+    /// is each section tried on its own, so every failing one is found and
+    /// left out rather than just the first; sections not tried by
+    /// `deadline` are left out too. A result missing sections is marked `partial`
+    /// and ends with a page listing them, so it can't be mistaken for the
+    /// whole document. Fails only when no section converts.
+    #[cfg(feature = "pdf")]
+    pub fn texts_to_pdf_partial(
+        &mut self,
+        sections: Vec<(String, String)>,
+        config: &PdfConfig,
+        deadline: Option<Instant>,
+    ) -> Result<MergedPdf> {
+        if sections.is_empty() {
+            return Err(ConversionError::InvalidInput(
+                "No sections to merge into PDF".to_string()
+            ).into());
+        }
+
+        let total_sections = sections.len();
+        let past_deadline = || deadline.map_or(false, |d| Instant::now() >= d);
+        let mut included: Vec<usize> = (0..total_sections).collect();
+        if past_deadline() {
+            included.clear();
+        }
+
+        let mut first_error = None;
+        if !included.is_empty() {
+            match self.render_sections(&sections, config, None) {
+                Ok(data) => {
+                    info!("Generated merged PDF with all {} sections ({} bytes)", total_sections, data.len());
+                    return Ok(MergedPdf {
+                        data,
+                        sections_converted: total_sections,
+                        total_sections,
+                        missing: Vec::new(),
+                        partial: false,
+                    });
+                }
+                Err(e) => {
+                    warn!("Merged PDF failed to render ({:#}); checking sections one by one", e);
+                    first_error = Some(e);
+                }
+            }
+
+            let section_config = PdfConfig { include_toc: false, ..config.clone() };
+            let mut checked = Vec::with_capacity(total_sections);
+            for index in included {
+                if past_deadline() {
+                    warn!("Merge deadline passed after checking {} of {} sections", index, total_sections);
+                    break;
+                }
+                let section = &sections[index];
+                match self.render_sections(std::slice::from_ref(section), &section_config, None) {
+                    Ok(_) => checked.push(index),
+                    Err(e) => warn!("Section '{}' failed to convert: {:#}", section.0, e),
+                }
+            }
+            included = checked;
+        }
+
+        if included.is_empty() {
+            return Err(first_error.unwrap_or_else(|| {
+                ConversionError::PdfGenerationFailed(
+                    "deadline passed before any section converted".to_string()
+                ).into()
+            }));
+        }
+
+        let missing: Vec<usize> = (0..total_sections).filter(|index| !included.contains(index)).collect();
+        let notice = format!(
+            "Incomplete document: {} of {} sections could not be converted: {}.",
+            missing.len(),
+            total_sections,
+            missing.iter().map(|&index| sections[index].0.as_str()).collect::<Vec<_>>().join(", ")
+        );
+        let kept: Vec<(String, String)> = included.iter().map(|&index| sections[index].clone()).collect();
+        let data = self.render_sections(&kept, config, Some(&notice))?;
+        info!(
+            "Generated merged PDF with {} of {} sections ({} bytes)",
+            kept.len(), total_sections, data.len()
+        );
+
+        Ok(MergedPdf {
+            data,
+            sections_converted: kept.len(),
+            total_sections,
+            missing,
+            partial: true,
+        })
+    }
+
+    /// Build and render a merged document, with an optional closing notice
+    #[cfg(feature = "pdf")]
+    fn render_sections(
+        &mut self,
+        sections: &[(String, String)],
+        config: &PdfConfig,
+        notice: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let mut doc = self.create_document(config)?;
+        let heading_style = Style::new()
+            .bold()
+            .with_font_size(config.font_size.saturating_add(6))
+            .with_color(config.text_color);
+
+        if config.include_toc {
+            doc.push(Paragraph::new(&config.toc_title).styled(heading_style));
+            doc.push(Break::new(1));
+
+            let entry_style = Style::new()
+                .with_font_size(config.font_size)
+                .with_color(config.text_color);
+            for (index, (title, _)) in sections.iter().enumerate() {
+                doc.push(Paragraph::new(format!("{}. {}", index + 1, title)).styled(entry_style));
+            }
+
+            doc.push(PageBreak::new());
+        }
+
+        for (index, (title, body)) in sections.iter().enumerate() {
+            if index > 0 {
+                doc.push(PageBreak::new());
+            }
+
+            doc.push(Paragraph::new(title.as_str()).styled(heading_style));
+            doc.push(Break::new(1));
+            self.push_text(&mut doc, body, config);
+        }
+
+        if let Some(notice) = notice {
+            doc.push(PageBreak::new());
+            doc.push(Paragraph::new(notice).styled(heading_style));
+        }
+
+        let input_len = sections.iter().map(|(title, body)| title.len() + body.len()).sum();
+        self.render_document(doc, input_len)
+    }
+
+    /// Bundle images (PNG or JPEG) into a PDF, one per page.
+    ///
+    /// Each image is rotated according to its EXIF orientation, then scaled
+    /// to fit the page inside the margins. Images that would be printed at
+    /// more than `config.max_image_dpi` are downscaled first, which keeps
+    /// phone-camera scans from producing huge PDFs.
+    #[cfg(feature = "images")]
+    pub fn images_to_pdf(&mut self, images: Vec<Vec<u8>>, config: &PdfConfig) -> Result<Vec<u8>> {
+        if images.is_empty() {
+            return Err(ConversionError::InvalidInput(
+                "No images to place in PDF".to_string()
+            ).into());
+        }
+
+        info!("Converting {} images to PDF with title: '{}'", images.len(), config.title);
+
+        let margin = config.margins as f64;
+        let page_mm = (config.page_size.0 - 2.0 * margin, config.page_size.1 - 2.0 * margin);
+        let mut doc = self.create_document(config)?;
+        let mut embedded_len = 0usize;
+
+        for (index, data) in images.iter().enumerate() {
+            if index > 0 {
+                doc.push(PageBreak::new());
+            }
+
+            let invalid = |e: &dyn std::fmt::Display| {
+                ConversionError::InvalidInput(format!("image {}: {}", index + 1, e))
+            };
+            let (width, height) = image::io::Reader::new(std::io::Cursor::new(data))
+                .with_guessed_format()
+                .map_err(|e| invalid(&e))?
+                .into_dimensions()
+                .map_err(|e| invalid(&e))?;
+            let pixels = width as u64 * height as u64;
+            if pixels > self.limits.max_image_pixels {
+                return Err(invalid(&format!(
+                    "{}x{} is {} pixels, over the limit of {}",
+                    width, height, pixels, self.limits.max_image_pixels
+                )).into());
+            }
+
+            let image = image::load_from_memory(data).map_err(|e| invalid(&e))?;
+            let image = apply_exif_orientation(image, exif_orientation(data));
+
+            let (width, height, dpi) = fit_to_page(image.width(), image.height(), page_mm, config.max_image_dpi);
+            let image = if (width, height) != (image.width(), image.height()) {
+                debug!(
+                    "Downscaling image {} from {}x{} to {}x{}",
+                    index + 1, image.width(), image.height(), width, height
+                );
+                image.resize(width, height, image::imageops::FilterType::Lanczos3)
+            } else {
+                image
+            };
+
+            // Pixels are embedded decoded, so they, not the compressed
+            // input, are what the output limit should be measured against
+            embedded_len += image.width() as usize * image.height() as usize * 3;
+
+            let element = genpdf::elements::Image::from_dynamic_image(image)
+                .map_err(|e| ConversionError::PdfGenerationFailed(e.to_string()))?
+                .with_dpi(dpi)
+                .with_alignment(Alignment::Center);
+            doc.push(element);
+        }
+
+        let buffer = self.render_document(doc, embedded_len)?;
+        info!("Successfully generated PDF from {} images ({} bytes)", images.len(), buffer.len());
+        Ok(buffer)
+    }
+
+    /// Create a document with fonts, metadata and margins applied
+    #[cfg(feature = "pdf")]
+    fn create_document(&mut self, config: &PdfConfig) -> Result<Document> {
         // Load or get cached font family
         let font_family = self.get_or_load_font(&config.font_family)?;
 
@@ -218,16 +998,21 @@ impl FileConverter {
         let mut doc = Document::new(font_family);
         doc.set_title(&config.title);
         doc.set_line_spacing(config.line_spacing);
+        doc.set_paper_size(genpdf::Size::new(config.page_size.0, config.page_size.1));
 
         // Set up page decorator with margins
         let mut decorator = SimplePageDecorator::new();
         decorator.set_margins(config.margins as i32);
         doc.set_page_decorator(decorator);
 
-        // Process text content
+        Ok(doc)
+    }
+
+    /// Add wrapped, styled paragraphs for a block of text
+    #[cfg(feature = "pdf")]
+    fn push_text(&self, doc: &mut Document, text: &str, config: &PdfConfig) {
         let processed_text = self.process_text_for_pdf(text, config);
 
-        // Add content to document
         for paragraph_text in processed_text {
             if paragraph_text.trim().is_empty() {
                 // Add empty paragraph for spacing
@@ -245,14 +1030,28 @@ impl FileConverter {
                 doc.push(paragraph);
             }
         }
+    }
 
-        // Render to bytes
-        let mut buffer = Vec::new();
-        doc.render(&mut buffer)
-            .map_err(|e| ConversionError::PdfGenerationFailed(e.to_string()))?;
+    /// Render a document to PDF bytes, enforcing the expansion limit
+    #[cfg(feature = "pdf")]
+    fn render_document(&self, doc: Document, input_len: usize) -> Result<Vec<u8>> {
+        let mut writer = LimitedWriter::new(self.limits.output_limit(input_len));
+
+        if let Err(e) = doc.render(&mut writer) {
+            if writer.exceeded() {
+                warn!(
+                    "Aborted PDF rendering: output exceeded {} bytes for {} bytes of input",
+                    writer.limit, input_len
+                );
+                return Err(ConversionError::MemoryLimit {
+                    used: writer.attempted,
+                    limit: writer.limit,
+                }.into());
+            }
+            return Err(ConversionError::PdfGenerationFailed(e.to_string()).into());
+        }
 
-        info!("Successfully generated PDF with {} bytes", buffer.len());
-        Ok(buffer)
+        Ok(writer.buffer)
     }
 
     /// Convert text file to PDF file
@@ -293,6 +1092,7 @@ impl FileConverter {
     }
 
     /// Extract text content from PDF bytes
+    #[cfg(feature = "pdf")]
     pub fn pdf_to_text(&self, pdf_bytes: &[u8]) -> Result<String> {
         info!("Extracting text from PDF ({} bytes)", pdf_bytes.len());
 
@@ -309,11 +1109,66 @@ impl FileConverter {
             .map_err(|e| ConversionError::PdfExtractionFailed(e.to_string()))?;
 
         let text = text.trim().to_string();
+
+        let limit = self.limits.output_limit(pdf_bytes.len());
+        if text.len() as u64 > limit {
+            return Err(ConversionError::MemoryLimit {
+                used: text.len() as u64,
+                limit,
+            }.into());
+        }
+
         info!("Successfully extracted {} characters of text from PDF", text.len());
 
         Ok(text)
     }
 
+    /// Extract the text of each page of a PDF, in page order.
+    ///
+    /// Blank pages are kept as empty strings so indexes always match page
+    /// numbers.
+    #[cfg(feature = "pdf")]
+    pub fn pdf_to_text_pages(&self, pdf_bytes: &[u8]) -> Result<Vec<String>> {
+        info!("Extracting text by page from PDF ({} bytes)", pdf_bytes.len());
+
+        let file_type = self.detect_file_type_from_bytes(pdf_bytes);
+        if file_type != FileType::Pdf {
+            return Err(ConversionError::UnsupportedFileType(
+                format!("Expected PDF file, found: {}", file_type)
+            ).into());
+        }
+
+        let pages: Vec<String> = extract_text_from_mem_by_pages(pdf_bytes)
+            .map_err(|e| ConversionError::PdfExtractionFailed(e.to_string()))?
+            .into_iter()
+            .map(|page| page.trim().to_string())
+            .collect();
+
+        let total: usize = pages.iter().map(String::len).sum();
+        let limit = self.limits.output_limit(pdf_bytes.len());
+        if total as u64 > limit {
+            return Err(ConversionError::MemoryLimit {
+                used: total as u64,
+                limit,
+            }.into());
+        }
+
+        info!("Successfully extracted {} characters of text from {} pages", total, pages.len());
+        Ok(pages)
+    }
+
+    /// Extract text from PDF bytes and encode it for output
+    pub fn pdf_to_text_bytes(&self, pdf_bytes: &[u8], config: &TextExtractConfig) -> Result<Vec<u8>> {
+        let text = self.pdf_to_text(pdf_bytes)?;
+        let bytes = config.encode(&text)?;
+
+        debug!(
+            "Encoded extracted text as {:?} ({:?} line endings, bom={}): {} bytes",
+            config.encoding, config.line_endings, config.emit_bom, bytes.len()
+        );
+        Ok(bytes)
+    }
+
     /// Extract text from PDF file to text file
     pub fn pdf_file_to_text<P: AsRef<Path>>(
         &self, 
@@ -342,6 +1197,40 @@ impl FileConverter {
         Ok(())
     }
 
+    /// Extract text from a PDF file into one text file per page in
+    /// `output_dir`, encoded according to `config`. Returns the paths
+    /// written, in page order.
+    pub fn pdf_file_to_text_pages<P: AsRef<Path>>(
+        &self,
+        input_path: P,
+        output_dir: P,
+        config: &TextExtractConfig,
+    ) -> Result<Vec<PathBuf>> {
+        let input_path = input_path.as_ref();
+        let output_dir = output_dir.as_ref();
+
+        info!("Converting PDF file {} to per-page text in {}",
+              input_path.display(), output_dir.display());
+
+        let pdf_bytes = fs::read(input_path)
+            .with_context(|| format!("Failed to read PDF file: {}", input_path.display()))?;
+        let pages = self.pdf_to_text_pages(&pdf_bytes)?;
+
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+
+        let mut written = Vec::with_capacity(pages.len());
+        for (index, page) in pages.iter().enumerate() {
+            let path = output_dir.join(page_text_filename(index, pages.len()));
+            fs::write(&path, config.encode(page)?)
+                .with_context(|| format!("Failed to write text file: {}", path.display()))?;
+            written.push(path);
+        }
+
+        info!("Successfully converted {} to {} page files", input_path.display(), written.len());
+        Ok(written)
+    }
+
     /// Generic file conversion - automatically detects input type and converts
     pub fn convert_file<P: AsRef<Path>>(
         &mut self,
@@ -353,7 +1242,7 @@ impl FileConverter {
         let output_path = output_path.as_ref();
 
         // Detect input file type
-        let input_type = self.detect_file_type(input_path)?;
+        let input_type = self.detect_file_type_with_name(input_path)?;
 
         // Determine output type from extension
         let output_extension = output_path
@@ -367,6 +1256,15 @@ impl FileConverter {
                 let config = config.unwrap_or(&PdfConfig::default());
                 self.text_file_to_pdf(input_path, output_path, config)
             }
+            (FileType::Code, "pdf") => {
+                let code = fs::read_to_string(input_path)
+                    .with_context(|| format!("Failed to read source file: {}", input_path.display()))?;
+                let config = config.unwrap_or(&PdfConfig::default());
+                let pdf_bytes = self.code_to_pdf(&code, code_language(input_path), config)?;
+                fs::write(output_path, pdf_bytes)
+                    .with_context(|| format!("Failed to write PDF file: {}", output_path.display()))?;
+                Ok(())
+            }
             (FileType::Pdf, "txt") => {
                 self.pdf_file_to_text(input_path, output_path)
             }
@@ -378,7 +1276,155 @@ impl FileConverter {
         }
     }
 
+    /// Convert from an async reader to an async writer using the default PDF config.
+    ///
+    /// Returns the number of bytes written.
+    pub async fn convert_stream<R, W>(
+        &mut self,
+        reader: R,
+        writer: W,
+        from: FileType,
+        to: &str,
+    ) -> Result<u64>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        self.convert_stream_with_config(reader, writer, from, to, &PdfConfig::default()).await
+    }
+
+    /// Convert from an async reader to an async writer.
+    ///
+    /// Text input is decoded and laid out chunk by chunk, so it is never held
+    /// as a single `String`; genpdf only produces bytes once the whole
+    /// document is rendered, so the PDF is written out as soon as that's done.
+    /// PDF input must be read fully because the parser needs random access;
+    /// the extracted text is then written page by page rather than joined
+    /// first. If the reader fails mid-stream the error is returned and
+    /// nothing is written.
+    pub async fn convert_stream_with_config<R, W>(
+        &mut self,
+        mut reader: R,
+        mut writer: W,
+        from: FileType,
+        to: &str,
+        config: &PdfConfig,
+    ) -> Result<u64>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let written = match (&from, to.to_lowercase().as_str()) {
+            (FileType::Text, "pdf") => {
+                let pdf = self.stream_text_to_pdf(&mut reader, config).await?;
+                write_stream_chunks(&mut writer, &pdf).await?
+            }
+            (FileType::Pdf, "txt") => {
+                let mut pdf_bytes = Vec::new();
+                reader.read_to_end(&mut pdf_bytes).await
+                    .with_context(|| format!("Failed to read PDF input stream after {} bytes", pdf_bytes.len()))?;
+                let pages = self.pdf_to_text_pages(&pdf_bytes)?;
+                drop(pdf_bytes);
+
+                let mut written = 0;
+                for page in pages.into_iter().filter(|page| !page.is_empty()) {
+                    if written > 0 {
+                        written += write_stream_chunks(&mut writer, b"\n\n").await?;
+                    }
+                    written += write_stream_chunks(&mut writer, page.as_bytes()).await?;
+                }
+                written
+            }
+            (from, to) => {
+                return Err(ConversionError::UnsupportedFileType(
+                    format!("Conversion from {} to {} is not supported", from, to)
+                ).into());
+            }
+        };
+
+        writer.flush().await
+            .with_context(|| "Failed to flush conversion output")?;
+
+        debug!("Streamed {} bytes of {} output", written, to);
+        Ok(written)
+    }
+
+    /// Lay out text from a reader into a PDF, one chunk of complete lines at a time
+    #[cfg(feature = "pdf")]
+    async fn stream_text_to_pdf<R>(&mut self, reader: &mut R, config: &PdfConfig) -> Result<Vec<u8>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut doc = self.create_document(config)?;
+        let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+        let mut pending: Vec<u8> = Vec::new();
+        let mut partial_line = String::new();
+        let mut total_read = 0usize;
+
+        loop {
+            let bytes_read = reader.read(&mut buffer).await
+                .map_err(ConversionError::IoError)
+                .with_context(|| format!("Failed to read text input stream after {} bytes", total_read))?;
+            if bytes_read == 0 {
+                break;
+            }
+
+            pending.extend_from_slice(&buffer[..bytes_read]);
+
+            // Keep an incomplete multi-byte sequence at the end for the next read
+            let valid_up_to = match std::str::from_utf8(&pending) {
+                Ok(_) => pending.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => {
+                    return Err(ConversionError::InvalidInput(format!(
+                        "Invalid UTF-8 in text stream at byte {}",
+                        total_read + e.valid_up_to()
+                    )).into());
+                }
+            };
+
+            let decoded = std::str::from_utf8(&pending[..valid_up_to])
+                .map_err(|e| ConversionError::InvalidInput(e.to_string()))?;
+            partial_line.push_str(decoded);
+            pending.drain(..valid_up_to);
+            total_read += valid_up_to;
+
+            if let Some(last_newline) = partial_line.rfind('\n') {
+                let complete_lines: String = partial_line.drain(..=last_newline).collect();
+                self.push_text(&mut doc, &complete_lines, config);
+            }
+        }
+
+        if !pending.is_empty() {
+            return Err(ConversionError::InvalidInput(format!(
+                "Text stream ended inside a UTF-8 sequence at byte {}",
+                total_read
+            )).into());
+        }
+
+        if !partial_line.is_empty() {
+            self.push_text(&mut doc, &partial_line, config);
+        }
+
+        info!("Streamed {} bytes of text into PDF", total_read);
+        self.render_document(doc, total_read)
+    }
+
+    /// Load the font family `config` renders with, so the first conversion
+    /// doesn't pay for it and a missing font is reported at startup.
+    ///
+    /// Loaded fonts are cached on the converter and shared by every
+    /// conversion it runs.
+    #[cfg(feature = "pdf")]
+    pub fn preload_fonts(&mut self, config: &PdfConfig) -> Result<()> {
+        let started = std::time::Instant::now();
+        self.get_or_load_font(&config.font_family)?;
+        info!("Preloaded font '{}' in {:?}", config.font_family, started.elapsed());
+        Ok(())
+    }
+
     /// Load or get cached font family
+    #[cfg(feature = "pdf")]
     fn get_or_load_font(&mut self, font_name: &str) -> Result<FontFamily<FontData>> {
         if let Some(font_family) = self.font_cache.get(font_name) {
             return Ok(font_family.clone());
@@ -392,6 +1438,7 @@ impl FileConverter {
     }
 
     /// Load font family (tries multiple approaches)
+    #[cfg(feature = "pdf")]
     fn load_font_family(&self, font_name: &str) -> Result<FontFamily<FontData>> {
         // Try to load from fonts directory
         if let Ok(font_family) = fonts::from_files("./fonts", font_name, None) {
@@ -429,6 +1476,7 @@ impl FileConverter {
     }
 
     /// Process text for PDF conversion (handle line wrapping, etc.)
+    #[cfg(feature = "pdf")]
     fn process_text_for_pdf(&self, text: &str, config: &PdfConfig) -> Vec<String> {
         let mut paragraphs = Vec::new();
 
@@ -450,6 +1498,7 @@ impl FileConverter {
     }
 
     /// Simple text wrapping at word boundaries
+    #[cfg(feature = "pdf")]
     fn wrap_text(&self, text: &str, max_chars: usize) -> Vec<String> {
         let mut result = Vec::new();
         let mut current_line = String::new();
@@ -474,6 +1523,66 @@ impl FileConverter {
     }
 }
 
+/// Stand-ins for builds without the PDF backend; each fails with
+/// `ConversionError::UnsupportedFormat`
+#[cfg(not(feature = "pdf"))]
+impl FileConverter {
+    /// Convert text content to PDF bytes
+    pub fn text_to_pdf(&mut self, _text: &str, _config: &PdfConfig) -> Result<Vec<u8>> {
+        Err(backend_unavailable("pdf").into())
+    }
+
+    /// Merge several texts into one PDF
+    pub fn texts_to_pdf(&mut self, _sections: Vec<(String, String)>, _config: &PdfConfig) -> Result<Vec<u8>> {
+        Err(backend_unavailable("pdf").into())
+    }
+
+    /// Merge texts, keeping the sections that convert
+    pub fn texts_to_pdf_partial(
+        &mut self,
+        _sections: Vec<(String, String)>,
+        _config: &PdfConfig,
+        _deadline: Option<Instant>,
+    ) -> Result<MergedPdf> {
+        Err(backend_unavailable("pdf").into())
+    }
+
+    /// Extract text content from PDF bytes
+    pub fn pdf_to_text(&self, _pdf_bytes: &[u8]) -> Result<String> {
+        Err(backend_unavailable("txt").into())
+    }
+
+    /// Render source code as a PDF listing
+    pub fn code_to_pdf(&mut self, _code: &str, _language: Option<&str>, _config: &PdfConfig) -> Result<Vec<u8>> {
+        Err(backend_unavailable("pdf").into())
+    }
+
+    /// Extract the text of each page of a PDF
+    pub fn pdf_to_text_pages(&self, _pdf_bytes: &[u8]) -> Result<Vec<String>> {
+        Err(backend_unavailable("txt").into())
+    }
+
+    /// Nothing to load without the PDF backend
+    pub fn preload_fonts(&mut self, _config: &PdfConfig) -> Result<()> {
+        Ok(())
+    }
+
+    async fn stream_text_to_pdf<R>(&mut self, _reader: &mut R, _config: &PdfConfig) -> Result<Vec<u8>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        Err(backend_unavailable("pdf").into())
+    }
+}
+
+#[cfg(not(feature = "images"))]
+impl FileConverter {
+    /// Bundle images into a PDF
+    pub fn images_to_pdf(&mut self, _images: Vec<Vec<u8>>, _config: &PdfConfig) -> Result<Vec<u8>> {
+        Err(backend_unavailable("pdf").into())
+    }
+}
+
 impl Default for FileConverter {
     fn default() -> Self {
         Self::new()
@@ -547,6 +1656,64 @@ mod tests {
     }
 
     #[test]
+    fn test_image_magic_number_detection() {
+        let magic = MagicNumbers::new();
+
+        assert_eq!(magic.detect_from_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), FileType::Image);
+        assert_eq!(magic.detect_from_bytes(b"\xFF\xD8\xFF\xE0\0\x10JFIF"), FileType::Image);
+    }
+
+    #[test]
+    #[cfg(feature = "images")]
+    fn test_images_to_pdf() {
+        let mut converter = FileConverter::new();
+        let png = |width, height| {
+            let mut data = Vec::new();
+            image::DynamicImage::ImageRgb8(image::RgbImage::new(width, height))
+                .write_to(&mut data, image::ImageOutputFormat::Png)
+                .unwrap();
+            data
+        };
+
+        let pdf = converter.images_to_pdf(vec![png(40, 30), png(30, 40)], &PdfConfig::default()).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+
+        assert!(converter.images_to_pdf(Vec::new(), &PdfConfig::default()).is_err());
+        assert!(converter.images_to_pdf(vec![b"not an image".to_vec()], &PdfConfig::default()).is_err());
+
+        // Rejected from the header alone, before any pixels are decoded
+        let mut converter = FileConverter::with_limits(ConversionLimits {
+            max_image_pixels: 40 * 30 - 1,
+            ..Default::default()
+        });
+        let err = converter.images_to_pdf(vec![png(40, 30)], &PdfConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("over the limit"), "{}", err);
+    }
+
+    #[test]
+    #[cfg(feature = "images")]
+    fn test_image_orientation_and_fit() {
+        // Orientation 6 means the camera was rotated 90 degrees clockwise
+        let landscape = image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 2));
+        let upright = apply_exif_orientation(landscape.clone(), 6);
+        assert_eq!((upright.width(), upright.height()), (2, 4));
+        assert_eq!(apply_exif_orientation(landscape, 1).width(), 4);
+
+        // 254mm is 10 inches: 1000px fills it at 100 DPI, well under the cap
+        let (width, height, dpi) = fit_to_page(1000, 500, (254.0, 254.0), 300.0);
+        assert_eq!((width, height), (1000, 500));
+        assert!((dpi - 100.0).abs() < 1e-9);
+
+        // 6000px would need 600 DPI, so it is halved to stay at 300
+        let (width, height, dpi) = fit_to_page(6000, 3000, (254.0, 254.0), 300.0);
+        assert_eq!((width, height, dpi), (3000, 1500, 300.0));
+
+        // No cap
+        assert_eq!(fit_to_page(6000, 3000, (254.0, 254.0), 0.0).0, 6000);
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
     fn test_text_to_pdf_conversion() {
         let mut converter = FileConverter::new();
         let config = PdfConfig::default();
@@ -560,6 +1727,215 @@ mod tests {
         assert!(pdf_bytes.starts_with(b"%PDF"));
     }
 
+    #[test]
+    fn test_code_detection() {
+        assert_eq!(code_language(Path::new("src/main.RS")), Some("rs"));
+        assert_eq!(code_language(Path::new("notes.txt")), None);
+        assert_eq!(code_language(Path::new("Makefile")), None);
+
+        let mut file = tempfile::Builder::new().suffix(".py").tempfile().unwrap();
+        file.write_all(b"print('hello')\n").unwrap();
+        let converter = FileConverter::new();
+        assert_eq!(converter.detect_file_type_with_name(file.path()).unwrap(), FileType::Code);
+        // Content-only detection still sees text
+        assert_eq!(converter.detect_file_type(file.path()).unwrap(), FileType::Text);
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
+    fn test_code_to_pdf() {
+        let segments = vec![("kw", "fn main() {".to_string()), ("punct", "}".to_string())];
+        let rows = wrap_code_line(&segments, 5);
+        assert_eq!(rows, vec![
+            vec![("kw", "fn ma".to_string())],
+            vec![("kw", "in() ".to_string())],
+            vec![("kw", "{".to_string()), ("punct", "}".to_string())],
+        ]);
+        assert_eq!(wrap_code_line::<&str>(&[], 5), vec![Vec::new()]);
+
+        let mut converter = FileConverter::new();
+        let code = "fn main() {\n\tprintln!(\"hello\");\n}\n";
+        let pdf = converter.code_to_pdf(code, Some("rs"), &PdfConfig::default()).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+
+        // Unknown languages fall back to plain monospace
+        let plain = converter.code_to_pdf(code, Some("no-such-language"), &PdfConfig::default()).unwrap();
+        assert!(plain.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
+    fn test_preload_fonts() {
+        let mut converter = FileConverter::new();
+        let config = PdfConfig::default();
+
+        converter.preload_fonts(&config).unwrap();
+        assert!(converter.font_cache.contains_key(&config.font_family));
+
+        // Later conversions reuse the cached family
+        converter.preload_fonts(&config).unwrap();
+        assert_eq!(converter.font_cache.len(), 1);
+        assert!(converter.text_to_pdf("warm", &config).unwrap().starts_with(b"%PDF"));
+        assert_eq!(converter.font_cache.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
+    fn test_expansion_limit() {
+        let limits = ConversionLimits {
+            max_expansion_ratio: 1.0,
+            min_output_allowance: 0,
+            ..Default::default()
+        };
+        assert_eq!(limits.output_limit(10), 10);
+
+        // Any PDF is far larger than one byte per input character
+        let mut converter = FileConverter::with_limits(limits);
+        let err = converter.text_to_pdf("tiny", &PdfConfig::default()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConversionError>(),
+            Some(ConversionError::MemoryLimit { .. })
+        ));
+    }
+
+    /// Reader that yields one line and then fails
+    struct FailingReader {
+        sent: bool,
+    }
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if self.sent {
+                return std::task::Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "stream reset",
+                )));
+            }
+            self.sent = true;
+            buf.put_slice(b"first line\n");
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "pdf")]
+    async fn test_convert_stream() {
+        let mut converter = FileConverter::new();
+
+        let mut output = Vec::new();
+        let written = converter
+            .convert_stream(&b"streamed line one\nline two"[..], &mut output, FileType::Text, "pdf")
+            .await
+            .unwrap();
+        assert_eq!(written as usize, output.len());
+        assert!(output.starts_with(b"%PDF"));
+
+        // A reader failing mid-stream surfaces the error and writes nothing
+        let mut output = Vec::new();
+        let result = converter
+            .convert_stream(FailingReader { sent: false }, &mut output, FileType::Text, "pdf")
+            .await;
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
+    fn test_texts_to_pdf_merge() {
+        let mut converter = FileConverter::new();
+        let config = PdfConfig {
+            include_toc: true,
+            ..Default::default()
+        };
+
+        let sections = vec![
+            ("Chapter One".to_string(), "First file contents.".to_string()),
+            ("Chapter Two".to_string(), "Second file contents.\n\nMore text.".to_string()),
+        ];
+
+        let pdf_bytes = converter.texts_to_pdf(sections, &config).unwrap();
+        assert!(pdf_bytes.starts_with(b"%PDF"));
+
+        assert!(converter.texts_to_pdf(Vec::new(), &config).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
+    fn test_texts_to_pdf_partial() {
+        let mut converter = FileConverter::new();
+        let config = PdfConfig::default();
+        let sections = vec![
+            ("One".to_string(), "First section.".to_string()),
+            ("Two".to_string(), "Second section.".to_string()),
+        ];
+
+        let merged = converter.texts_to_pdf_partial(sections.clone(), &config, None).unwrap();
+        assert!(!merged.partial);
+        assert_eq!(merged.sections_converted, 2);
+        assert!(merged.data.starts_with(b"%PDF"));
+
+        // The output limit scales with input, so a one-byte section can't
+        // fit the embedded font on its own. Merged, it shares the font with
+        // the others and isn't dropped.
+        let mut converter = FileConverter::with_limits(ConversionLimits {
+            max_expansion_ratio: 1000.0,
+            min_output_allowance: 0,
+            ..Default::default()
+        });
+        let long = vec![
+            ("One".to_string(), "word ".repeat(2000)),
+            ("Two".to_string(), "word ".repeat(2000)),
+            (String::new(), "x".to_string()),
+            ("Four".to_string(), "word ".repeat(2000)),
+        ];
+        let merged = converter.texts_to_pdf_partial(long, &config, None).unwrap();
+        assert!(!merged.partial);
+        assert_eq!((merged.sections_converted, merged.total_sections), (4, 4));
+        assert!(merged.missing.is_empty());
+
+        // When the merge fails, every section is checked rather than
+        // stopping at the first failure; here none converts
+        let tiny = vec![
+            ("A".to_string(), "x".to_string()),
+            ("B".to_string(), "y".to_string()),
+        ];
+        let err = converter.texts_to_pdf_partial(tiny, &config, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConversionError>(),
+            Some(ConversionError::MemoryLimit { .. })
+        ));
+
+        // Past the deadline nothing converts, so there is nothing to return
+        let mut converter = FileConverter::new();
+        let passed = Instant::now();
+        assert!(converter.texts_to_pdf_partial(sections, &config, Some(passed)).is_err());
+    }
+
+    #[test]
+    fn test_supported_conversions() {
+        let conversions = FileConverter::supported_conversions();
+        assert_eq!(conversions.contains(&(FileType::Text, "pdf")), cfg!(feature = "pdf"));
+        assert_eq!(conversions.contains(&(FileType::Code, "pdf")), cfg!(feature = "pdf"));
+        assert_eq!(conversions.contains(&(FileType::Pdf, "txt")), cfg!(feature = "pdf"));
+        assert_eq!(conversions.contains(&(FileType::Image, "pdf")), cfg!(feature = "images"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "pdf"))]
+    fn test_disabled_backend_is_unsupported() {
+        let mut converter = FileConverter::new();
+        let err = converter.text_to_pdf("hello", &PdfConfig::default()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConversionError>(),
+            Some(ConversionError::UnsupportedFormat { .. })
+        ));
+        converter.preload_fonts(&PdfConfig::default()).unwrap();
+    }
+
     #[test]
     fn test_file_type_detection_from_file() -> Result<()> {
         // Create temporary text file
@@ -574,6 +1950,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "pdf")]
     fn test_text_wrapping() {
         let converter = FileConverter::new();
         let long_text = "This is a very long line that should be wrapped at word boundaries when it exceeds the maximum character limit";
@@ -588,6 +1965,58 @@ mod tests {
         // Should have multiple lines
         assert!(wrapped.len() > 1);
     }
+
+    #[test]
+    fn test_text_extract_encoding() {
+        let config = TextExtractConfig {
+            encoding: Encoding::Latin1,
+            line_endings: LineEnding::CrLf,
+            emit_bom: true,
+        };
+
+        // Latin-1 has no BOM, and line endings are normalized to CRLF
+        let bytes = config.encode("caf\u{e9}\nline\r\nend").unwrap();
+        assert_eq!(bytes, b"caf\xe9\r\nline\r\nend");
+
+        let err = config.encode("price: \u{20ac}5").unwrap_err();
+        assert!(matches!(err, ConversionError::EncodingFailed(_)));
+
+        let utf8 = TextExtractConfig { emit_bom: true, ..Default::default() };
+        assert_eq!(utf8.encode("a\r\nb").unwrap(), b"\xEF\xBB\xBFa\nb");
+    }
+
+    #[test]
+    fn test_page_text_filename() {
+        assert_eq!(page_text_filename(0, 12), "page-001.txt");
+        assert_eq!(page_text_filename(11, 12), "page-012.txt");
+        assert_eq!(page_text_filename(41, 1500), "page-0042.txt");
+        assert_eq!(page_text_filename(1499, 1500), "page-1500.txt");
+    }
+
+    #[test]
+    #[cfg(feature = "pdf")]
+    fn test_pdf_to_text_pages() {
+        let mut converter = FileConverter::new();
+        let sections = vec![
+            ("One".to_string(), "First page text.".to_string()),
+            ("Two".to_string(), "Second page text.".to_string()),
+        ];
+        let pdf_bytes = converter.texts_to_pdf(sections, &PdfConfig::default()).unwrap();
+
+        let pages = converter.pdf_to_text_pages(&pdf_bytes).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].contains("First page text."));
+        assert!(pages[1].contains("Second page text."));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("doc.pdf");
+        fs::write(&input, &pdf_bytes).unwrap();
+        let out_dir = temp_dir.path().join("pages");
+        let written = converter
+            .pdf_file_to_text_pages(&input, &out_dir, &TextExtractConfig::default())
+            .unwrap();
+        assert_eq!(written, vec![out_dir.join("page-001.txt"), out_dir.join("page-002.txt")]);
+    }
 }
 
 /// Example usage and CLI interface
@@ -20,11 +20,13 @@ fn main() -> anyhow::Result<()> {
     for file_path in &args[1..] {
         print!("📁 {:<30}", file_path);
 
-        match converter.detect_file_type(file_path) {
+        match converter.detect_file_type_with_name(file_path) {
             Ok(file_type) => {
                 let icon = match file_type {
                     FileType::Pdf => "📕",
                     FileType::Text => "📝",
+                    FileType::Image => "🖼️",
+                    FileType::Code => "💻",
                     FileType::Unknown => "❓",
                 };
                 println!(" → {} {}", icon, file_type);
@@ -20,6 +20,7 @@ benchmarks = []
 libp2p = { version = "0.56", features = [
     "tcp", 
     "noise", 
+    "plaintext",
     "yamux", 
     "swarm", 
     "request-response",
@@ -23,6 +23,9 @@ mod benchmarks {
             status: TransferStatus::Sending,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: file_sender::DEFAULT_PRIORITY,
         };
 
         let start = Instant::now();
@@ -97,6 +100,9 @@ mod benchmarks {
             status: TransferStatus::Sending,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: file_sender::DEFAULT_PRIORITY,
         };
 
         let start = Instant::now();
@@ -154,6 +160,9 @@ mod benchmarks {
             status: TransferStatus::Connecting,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: file_sender::DEFAULT_PRIORITY,
         };
 
         let statuses = vec![
@@ -31,6 +31,14 @@ struct Args {
     /// Connection timeout in seconds
     #[arg(long, default_value = "10")]
     timeout: u64,
+
+    /// Addresses of the target dialled in parallel
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u8).range(1..))]
+    dial_concurrency: u8,
+
+    /// Shared secret for receivers that require one
+    #[arg(long)]
+    auth_token: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -41,7 +49,8 @@ enum Commands {
         #[arg(short, long)]
         path: PathBuf,
 
-        /// Target conversion format
+        /// Target conversion format; "none" stores the file as is, even if
+        /// the receiver converts by default
         #[arg(short, long)]
         format: Option<String>,
 
@@ -60,7 +69,7 @@ enum Commands {
         #[arg(short, long, default_value = "*")]
         pattern: String,
 
-        /// Target conversion format for all files
+        /// Target conversion format for all files; "none" stores them as is
         #[arg(long)]
         format: Option<String>,
 
@@ -101,13 +110,17 @@ async fn main() -> Result<()> {
     let retry_config = RetryConfig {
         max_attempts: args.max_retries,
         connection_timeout: Duration::from_secs(args.timeout),
+        dial_concurrency: args.dial_concurrency,
         initial_delay: Duration::from_millis(500),
         max_delay: Duration::from_secs(30),
         backoff_multiplier: 2.0,
+        low_reputation_attempts: 2,
+        recover_lost_responses: true,
     };
 
     // Create file sender
     let mut sender = FileSender::new(Some(retry_config)).await?;
+    sender.set_auth_token(args.auth_token.clone());
 
     // Set up progress reporting  
     let mut progress_reporter = ProgressReporter::new(Duration::from_secs(1));
@@ -183,6 +196,9 @@ async fn send_single_file(
             if let Some(converted_filename) = response.converted_filename {
                 info!("🔄 Converted to: {}", converted_filename);
             }
+            if let Some(size) = response.withheld_result_size {
+                info!("📦 Converted result ({} bytes) is too large to return inline; kept on the receiver", size);
+            }
         }
     } else {
         warn!("❌ Transfer failed: {}", result.error.unwrap_or_else(|| "Unknown error".to_string()));
@@ -231,6 +247,9 @@ async fn send_batch_files(
 
     info!("📦 Found {} files to transfer", files.len());
 
+    // Transfers beyond the limit wait in the sender's queue with an ETA
+    sender.set_max_concurrent_sends(max_concurrent);
+
     // Process files with limited concurrency
     let results: Vec<Result<String>> = stream::iter(files)
         .map(|file_path| {
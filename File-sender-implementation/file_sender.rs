@@ -9,12 +9,13 @@ use libp2p::{
     core::ConnectedPoint,
     request_response::{self, Codec, OutboundRequestId, RequestId},
     swarm::{SwarmEvent, dial_opts::DialOpts},
-    Multiaddr, PeerId, Swarm, SwarmBuilder,
+    Multiaddr, PeerId, Swarm,
 };
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     io::SeekFrom,
+    num::NonZeroU8,
     path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
@@ -22,7 +23,7 @@ use std::{
 use tokio::{
     fs::File,
     io::{AsyncReadExt, AsyncSeekExt},
-    sync::{mpsc, Mutex, RwLock},
+    sync::{mpsc, oneshot, watch, Mutex, Notify, RwLock},
     time::{interval, sleep, timeout, Interval},
 };
 use tracing::{debug, error, info, warn};
@@ -31,9 +32,36 @@ use uuid::Uuid;
 // Re-use protocol definitions from stream handler
 use crate::p2p_stream_handler::{
     FileChunk, FileConversionCodec, FileTransferRequest, FileTransferResponse, 
-    FileType, PROTOCOL_NAME, MAX_CHUNK_SIZE, MAX_FILE_SIZE, TRANSFER_TIMEOUT
+    FileType, RequestKind, StatusResponse, TransferState, PeerReputationStore, ReputationConfig, Security, TransportConfig,
+    build_swarm, is_negotiation_failure, read_full, stream_protocols, validate_metadata, ProtocolVersion,
+    write_directory_archive,
+    Bandwidth, MemoryBudget, MemoryUsage, PrioritySlots, TransferDirection, clamp_priority,
+    DEFAULT_INLINE_TRANSFER_SIZE, DEFAULT_MAX_INLINE_RESULT, DEFAULT_MAX_RESPONSE_SIZE, MAX_INLINE_TRANSFER_SIZE, DEFAULT_MEMORY_BUDGET, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE, MAX_FILE_SIZE, MEMORY_BACKPRESSURE_TIMEOUT, TRANSFER_TIMEOUT
 };
 use crate::file_converter::FileConverter;
+pub use crate::p2p_stream_handler::{DEFAULT_PRIORITY, MAX_PRIORITY};
+use crate::error_handling::{cleanup::{discard_entry, CleanupManager}, recovery::Backoff, ConfigurationError, FileIOError, NetworkError, ProtocolError, TimeoutError};
+
+/// Transfers allowed to run at once; further sends wait in the queue
+const DEFAULT_MAX_CONCURRENT_SENDS: usize = 4;
+
+/// Timeout used by `wait_for_completion`; effectively unbounded
+const WAIT_FOREVER: Duration = Duration::MAX;
+
+/// How long a file must stay unchanged to count as fully written, since
+/// advisory locks miss writers that don't lock
+const STABILITY_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the event loop checks for sessions due a reconnect
+const SESSION_TICK: Duration = Duration::from_millis(250);
+
+/// How long to wait for a receiver to accept or reject an offer; long
+/// enough for an operator to answer an approval prompt
+const DEFAULT_ACCEPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a waiter sleeps between routing swarm events, with the lock
+/// released so other transfers can use the swarm
+const EVENT_PUMP_INTERVAL: Duration = Duration::from_millis(10);
 
 /// Connection retry configuration
 #[derive(Debug, Clone)]
@@ -46,8 +74,18 @@ pub struct RetryConfig {
     pub max_delay: Duration,
     /// Backoff multiplier
     pub backoff_multiplier: f64,
-    /// Connection timeout per attempt
+    /// How long each attempt may take to establish a connection, and to
+    /// check on a transfer whose response was lost. Sending the file isn't
+    /// covered; that is bounded by the request timeout.
     pub connection_timeout: Duration,
+    /// Addresses of a peer dialled in parallel when it has several
+    pub dial_concurrency: u8,
+    /// Maximum attempts for peers with a low reputation score
+    pub low_reputation_attempts: usize,
+    /// When the connection drops after the file was sent but before the
+    /// response arrived, reconnect and ask the receiver what became of the
+    /// transfer instead of sending it again
+    pub recover_lost_responses: bool,
 }
 
 impl Default for RetryConfig {
@@ -58,10 +96,168 @@ impl Default for RetryConfig {
             max_delay: Duration::from_secs(30),
             backoff_multiplier: 2.0,
             connection_timeout: Duration::from_secs(10),
+            dial_concurrency: 5,
+            low_reputation_attempts: 2,
+            recover_lost_responses: true,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delays between connection attempts
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(self.initial_delay, self.max_delay, self.backoff_multiplier)
+    }
+}
+
+/// How long finished transfers stay around for status checks
+#[derive(Debug, Clone)]
+pub struct CompletedRetention {
+    /// Forget a finished transfer this long after it finished
+    pub max_age: Duration,
+    /// Keep at most this many finished transfers, forgetting the oldest
+    /// first; running transfers don't count
+    pub max_count: usize,
+}
+
+impl Default for CompletedRetention {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(300),
+            max_count: 1000,
         }
     }
 }
 
+impl CompletedRetention {
+    /// Finished transfers to forget, given each one's id and finish time
+    pub fn expired<'a>(&self, finished: impl IntoIterator<Item = (&'a str, Instant)>, now: Instant) -> Vec<String> {
+        let mut finished: Vec<_> = finished.into_iter().collect();
+        // Newest first, so everything past `max_count` is the oldest
+        finished.sort_by(|a, b| b.1.cmp(&a.1));
+
+        finished
+            .into_iter()
+            .enumerate()
+            .filter(|(index, (_, finished_at))| {
+                *index >= self.max_count || now.saturating_duration_since(*finished_at) > self.max_age
+            })
+            .map(|(_, (id, _))| id.to_string())
+            .collect()
+    }
+}
+
+/// Reconnection settings for a persistent session to one receiver.
+///
+/// Independent of [`RetryConfig`]: a session keeps redialing for as long as
+/// it is open, and transfers to its peer wait for it instead of spending
+/// their own attempts on a link that is known to be down.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Delay before the first reconnect
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between reconnects
+    pub max_backoff: Duration,
+    /// Backoff multiplier
+    pub backoff_multiplier: f64,
+    /// Fraction of each delay that is randomised (0.0 to 1.0), so senders
+    /// cut off together don't redial in lockstep
+    pub jitter: f64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            backoff_multiplier: 2.0,
+            jitter: 0.3,
+        }
+    }
+}
+
+/// Connection state of a session
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionState {
+    /// Dial in progress
+    Connecting,
+    /// Connected since the given time
+    Connected { since: Instant },
+    /// Down since `since`; reconnect number `attempt` is due at `retry_at`
+    Reconnecting {
+        since: Instant,
+        attempt: u32,
+        retry_at: Instant,
+        last_error: Option<String>,
+    },
+}
+
+impl SessionState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, SessionState::Connected { .. })
+    }
+
+    /// Get human-readable status
+    pub fn status_string(&self) -> String {
+        match self {
+            SessionState::Connecting => "Connecting".to_string(),
+            SessionState::Connected { since } => {
+                format!("Connected for {:.0}s", since.elapsed().as_secs_f64())
+            }
+            SessionState::Reconnecting { since, attempt, retry_at, last_error } => format!(
+                "Down for {:.0}s, reconnect {} in {:.1}s{}",
+                since.elapsed().as_secs_f64(),
+                attempt,
+                retry_at.saturating_duration_since(Instant::now()).as_secs_f64(),
+                last_error.as_deref().map(|e| format!(" (last error: {})", e)).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Snapshot of one session for status displays
+#[derive(Debug, Clone)]
+pub struct SessionStatus {
+    pub peer_id: PeerId,
+    pub address: Multiaddr,
+    pub state: SessionState,
+    /// Times the session has been re-established after dropping
+    pub reconnects: u32,
+}
+
+/// Persistent session to a receiver
+#[derive(Debug)]
+struct PeerSession {
+    address: Multiaddr,
+    config: SessionConfig,
+    state: watch::Sender<SessionState>,
+    /// Consecutive failed reconnects; reset once connected
+    attempt: u32,
+    reconnects: u32,
+}
+
+impl PeerSession {
+    fn status(&self, peer_id: PeerId) -> SessionStatus {
+        SessionStatus {
+            peer_id,
+            address: self.address.clone(),
+            state: self.state.borrow().clone(),
+            reconnects: self.reconnects,
+        }
+    }
+}
+
+/// Delay before reconnect number `attempt` (1-based). `random` in [0, 1)
+/// picks where in the jitter window the delay lands.
+fn reconnect_delay(config: &SessionConfig, attempt: u32, random: f64) -> Duration {
+    let base = Backoff::new(config.initial_backoff, config.max_backoff, config.backoff_multiplier)
+        .delay(attempt)
+        .as_secs_f64();
+    let jitter = config.jitter.clamp(0.0, 1.0);
+    let delay = base * (1.0 + jitter * (2.0 * random - 1.0));
+    Duration::from_secs_f64(delay.clamp(0.0, config.max_backoff.as_secs_f64()))
+}
+
 /// Progress information for file sending
 #[derive(Debug, Clone)]
 pub struct SendProgress {
@@ -87,6 +283,156 @@ pub struct SendProgress {
     pub connection_attempts: usize,
     /// Last error encountered
     pub last_error: Option<String>,
+    /// When a queued transfer is expected to start
+    pub estimated_start: Option<Instant>,
+    /// When a queued transfer is expected to finish, including queue wait
+    pub estimated_completion: Option<Instant>,
+    /// Scheduling priority; higher-priority queued transfers start first
+    pub priority: u8,
+    /// Rates measured while chunks were being sent
+    pub throughput: ThroughputStats,
+    /// When the transfer reached a terminal status
+    pub finished_at: Option<Instant>,
+}
+
+/// Window over which peak throughput is measured
+const PEAK_WINDOW: Duration = Duration::from_millis(500);
+
+/// Average and peak send rate, built up chunk by chunk.
+///
+/// Elapsed times are floored at one millisecond so transfers that finish
+/// almost instantly still report a finite rate. Peaks are measured over
+/// `PEAK_WINDOW`; a transfer shorter than that reports its average as its
+/// peak.
+#[derive(Debug, Clone, Default)]
+pub struct ThroughputStats {
+    started_at: Option<Instant>,
+    last_at: Option<Instant>,
+    bytes: u64,
+    window_start: Option<Instant>,
+    window_bytes: u64,
+    peak_bps: f64,
+}
+
+impl ThroughputStats {
+    /// Start the clock, if it isn't already running
+    pub fn begin(&mut self, now: Instant) {
+        if self.started_at.is_none() {
+            self.started_at = Some(now);
+            self.window_start = Some(now);
+        }
+    }
+
+    /// Account for `bytes` sent at `now`
+    pub fn record(&mut self, bytes: u64, now: Instant) {
+        self.begin(now);
+        self.bytes += bytes;
+        self.window_bytes += bytes;
+        self.last_at = Some(now);
+
+        let window_start = self.window_start.unwrap_or(now);
+        let window = now.saturating_duration_since(window_start);
+        if window >= PEAK_WINDOW {
+            self.peak_bps = self.peak_bps.max(self.window_bytes as f64 / window.as_secs_f64());
+            self.window_start = Some(now);
+            self.window_bytes = 0;
+        }
+    }
+
+    /// Bytes per second from the first to the last recorded chunk
+    pub fn avg_bps(&self) -> f64 {
+        match (self.started_at, self.last_at) {
+            (Some(start), Some(last)) => {
+                let elapsed = last.saturating_duration_since(start).max(Duration::from_millis(1));
+                self.bytes as f64 / elapsed.as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Highest rate over any measurement window
+    pub fn peak_bps(&self) -> f64 {
+        self.peak_bps.max(self.avg_bps())
+    }
+}
+
+/// Chunk size adaptive sending starts from
+const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// How long one chunk should take to send: long enough that per-chunk
+/// overhead doesn't matter, short enough for steady progress updates
+const CHUNK_TARGET_TIME: Duration = Duration::from_millis(250);
+
+/// Weight of the newest measurement in the smoothed chunk time
+const CHUNK_TIME_SMOOTHING: f64 = 0.3;
+
+/// Picks the size of each chunk from how long recent chunks took.
+///
+/// The size doubles when chunks take under half of `CHUNK_TARGET_TIME` and
+/// halves when they take over twice it. A step moves the expected chunk time
+/// to the middle of that band, so the next step needs a real change in
+/// throughput rather than noise, and sizes don't flip back and forth.
+#[derive(Debug, Clone)]
+pub struct ChunkSizer {
+    min: usize,
+    max: usize,
+    current: usize,
+    /// Smoothed time to send a chunk of the current size
+    chunk_secs: Option<f64>,
+}
+
+impl ChunkSizer {
+    /// Adapt between `min` and `max`, starting at `initial`
+    pub fn adaptive(min: usize, max: usize, initial: usize) -> Self {
+        let max = max.max(min);
+        Self { min, max, current: initial.clamp(min, max), chunk_secs: None }
+    }
+
+    /// Always use `size`, for deterministic chunking
+    pub fn fixed(size: usize) -> Self {
+        Self::adaptive(size, size, size)
+    }
+
+    /// Size of the next chunk
+    pub fn chunk_size(&self) -> usize {
+        self.current
+    }
+
+    /// Whether the size can change at all
+    pub fn is_adaptive(&self) -> bool {
+        self.min < self.max
+    }
+
+    /// Account for a chunk of `bytes` that took `elapsed` to send
+    pub fn record(&mut self, bytes: usize, elapsed: Duration) {
+        if !self.is_adaptive() || bytes == 0 {
+            return;
+        }
+
+        // Scale short (final) chunks up to the time a full one would take
+        let sample = elapsed.as_secs_f64() * self.current as f64 / bytes as f64;
+        let smoothed = match self.chunk_secs {
+            Some(previous) => previous + CHUNK_TIME_SMOOTHING * (sample - previous),
+            None => sample,
+        };
+        self.chunk_secs = Some(smoothed);
+
+        let target = CHUNK_TARGET_TIME.as_secs_f64();
+        let next = if smoothed < target / 2.0 {
+            (self.current * 2).min(self.max)
+        } else if smoothed > target * 2.0 {
+            (self.current / 2).max(self.min)
+        } else {
+            self.current
+        };
+
+        if next != self.current {
+            debug!("Chunk size {} -> {} bytes ({:.0}ms per chunk)", self.current, next, smoothed * 1000.0);
+            // Expect the new size to take proportionally longer or shorter
+            self.chunk_secs = Some(smoothed * next as f64 / self.current as f64);
+            self.current = next;
+        }
+    }
 }
 
 impl SendProgress {
@@ -111,6 +457,11 @@ impl SendProgress {
 
     /// Estimate time remaining in seconds
     pub fn eta_seconds(&self) -> Option<f64> {
+        if matches!(self.status, TransferStatus::Queued) {
+            return self.estimated_completion
+                .map(|at| at.saturating_duration_since(Instant::now()).as_secs_f64());
+        }
+
         let speed = self.speed_bps();
         if speed > 0.0 && self.sent_bytes < self.total_size {
             let remaining = self.total_size - self.sent_bytes;
@@ -123,6 +474,13 @@ impl SendProgress {
     /// Get human-readable status
     pub fn status_string(&self) -> String {
         match &self.status {
+            TransferStatus::Queued => match self.estimated_start {
+                Some(at) => format!(
+                    "Queued (starts in ~{:.0}s)",
+                    at.saturating_duration_since(Instant::now()).as_secs_f64()
+                ),
+                None => "Queued".to_string(),
+            },
             TransferStatus::Connecting => format!("Connecting (attempt {})", self.connection_attempts),
             TransferStatus::Negotiating => "Negotiating protocol".to_string(),
             TransferStatus::Sending => format!("Sending chunk {}/{}", self.chunks_sent, self.total_chunks),
@@ -137,6 +495,7 @@ impl SendProgress {
 /// Transfer status enumeration
 #[derive(Debug, Clone)]
 pub enum TransferStatus {
+    Queued,
     Connecting,
     Negotiating,
     Sending,
@@ -146,6 +505,13 @@ pub enum TransferStatus {
     Cancelled,
 }
 
+impl TransferStatus {
+    /// Whether the transfer has finished, successfully or not
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, TransferStatus::Completed | TransferStatus::Failed(_) | TransferStatus::Cancelled)
+    }
+}
+
 /// File sending result
 #[derive(Debug)]
 pub struct SendResult {
@@ -153,18 +519,75 @@ pub struct SendResult {
     pub success: bool,
     pub bytes_sent: u64,
     pub duration: Duration,
+    /// Average send rate in bytes per second
+    pub avg_bps: f64,
+    /// Highest send rate in bytes per second; see [`ThroughputStats`]
+    pub peak_bps: f64,
+    /// Bytes sent more than once. Always zero until chunks are acknowledged
+    /// and resent.
+    pub retransmitted_bytes: u64,
     pub response: Option<FileTransferResponse>,
     pub error: Option<String>,
 }
 
+/// Where a transfer's data is read from
+#[derive(Debug)]
+enum SendSource {
+    /// An open file, streamed from disk
+    File(File),
+    /// A buffer handed to `send_bytes`
+    Bytes(std::io::Cursor<Vec<u8>>),
+}
+
+impl tokio::io::AsyncRead for SendSource {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            SendSource::File(file) => std::pin::Pin::new(file).poll_read(cx, buf),
+            SendSource::Bytes(cursor) => std::pin::Pin::new(cursor).poll_read(cx, buf),
+        }
+    }
+}
+
+/// A transfer's data and what the request says about it
+struct SendInput {
+    /// Shown in progress; its file name is sent as the filename
+    path: PathBuf,
+    size: u64,
+    file_type: FileType,
+    source: SendSource,
+    /// Staging directory of a directory archive, kept until the transfer ends
+    archive: Option<tempfile::TempDir>,
+}
+
 /// Active file transfer tracking
 #[derive(Debug)]
 struct ActiveSend {
     pub progress: SendProgress,
-    pub file: File,
+    pub file: SendSource,
     pub request_id: Option<OutboundRequestId>,
     pub response_receiver: Option<mpsc::Receiver<FileTransferResponse>>,
     pub cancel_sender: Option<mpsc::Sender<()>>,
+    /// Fired once when the transfer reaches a terminal status
+    pub completed: Arc<Notify>,
+}
+
+impl ActiveSend {
+    /// Update the status, waking completion waiters on the first terminal transition
+    fn set_status(&mut self, status: TransferStatus) {
+        let was_terminal = self.progress.status.is_terminal();
+        if let TransferStatus::Failed(ref error) = status {
+            self.progress.last_error = Some(error.clone());
+        }
+        self.progress.status = status;
+        if !was_terminal && self.progress.status.is_terminal() {
+            self.progress.finished_at = Some(Instant::now());
+            self.completed.notify_waiters();
+        }
+    }
 }
 
 /// File sender service
@@ -179,11 +602,81 @@ pub struct FileSender {
     retry_config: RetryConfig,
     /// Progress callback
     progress_callback: Option<Arc<dyn Fn(&SendProgress) + Send + Sync>>,
+    /// Peer reputation scores
+    reputation: Arc<PeerReputationStore>,
+    /// Transport security used for outgoing connections
+    security: Security,
+    /// Slots for concurrently running transfers, granted by priority
+    send_slots: Arc<PrioritySlots>,
+    /// Number of slots in `send_slots`
+    max_concurrent_sends: usize,
+    /// Tracks running transfers so aborted ones are cleaned up
+    cleanup: Arc<CleanupManager>,
+    /// Handles of spawned transfer tasks, for aborting on shutdown
+    transfer_tasks: Arc<Mutex<HashMap<String, tokio::task::JoinHandle<Result<()>>>>>,
+    /// Cap on chunk data held in memory by running transfers
+    memory_budget: Arc<MemoryBudget>,
+    /// Upload caps, possibly shared with a local conversion service
+    bandwidth: Arc<Bandwidth>,
+    /// Persistent sessions, kept connected by the event loop
+    sessions: Arc<RwLock<HashMap<PeerId, PeerSession>>>,
+    /// Largest converted result accepted inline, offered to receivers
+    max_inline_result: u64,
+    /// Conversion timeout asked of receivers; their default when `None`
+    conversion_timeout: Option<Duration>,
+    /// Shared secret sent to receivers that require one
+    auth_token: Option<String>,
+    /// How many finished transfers are kept, and for how long
+    completed_retention: CompletedRetention,
+    /// Size chunks by measured throughput instead of always `MAX_CHUNK_SIZE`
+    adaptive_chunks: bool,
+    /// Files up to this size go inside the request instead of as chunks
+    inline_threshold: u64,
+    /// How long a receiver has to accept an offer before the attempt fails
+    accept_timeout: Duration,
+    /// Address each peer was last dialed at, for error reports
+    dialed_addrs: HashMap<PeerId, Multiaddr>,
+    /// Waiters for replies, keyed by the request they answer
+    pending_replies: HashMap<OutboundRequestId, PendingReply>,
+    /// Why the last dial to each peer failed, until it is dialed again
+    dial_failures: HashMap<PeerId, anyhow::Error>,
+}
+
+/// A request whose reply someone is waiting for
+struct PendingReply {
+    peer: PeerId,
+    reply: oneshot::Sender<Result<FileTransferResponse>>,
 }
 
 impl FileSender {
-    /// Create a new file sender
+    /// Create a new file sender using Noise transport security
     pub async fn new(retry_config: Option<RetryConfig>) -> Result<Self> {
+        Self::with_security(retry_config, Security::Noise).await
+    }
+
+    /// Create a new file sender with explicit transport security
+    pub async fn with_security(retry_config: Option<RetryConfig>, security: Security) -> Result<Self> {
+        let transport = TransportConfig {
+            port_reuse: true,
+            ..TransportConfig::default()
+        };
+        Self::with_transport(retry_config, security, transport).await
+    }
+
+    /// Create a new file sender with explicit security and TCP socket options
+    pub async fn with_transport(
+        retry_config: Option<RetryConfig>,
+        security: Security,
+        transport: TransportConfig,
+    ) -> Result<Self> {
+        let retry_config = retry_config.unwrap_or_default();
+        let dial_concurrency = NonZeroU8::new(retry_config.dial_concurrency).ok_or_else(|| {
+            ConfigurationError::ValidationFailed {
+                section: "retry".to_string(),
+                reason: "dial_concurrency must be at least 1".to_string(),
+            }
+        })?;
+
         let local_key = libp2p::identity::Keypair::generate_ed25519();
         let local_peer_id = PeerId::from(local_key.public());
 
@@ -191,41 +684,321 @@ impl FileSender {
 
         // Create request-response behaviour
         let behaviour = request_response::Behaviour::new(
-            FileConversionCodec,
-            [libp2p::StreamProtocol::new(PROTOCOL_NAME)],
+            FileConversionCodec::default(),
+            // Offer every version, newest first; the first one the listener
+            // also supports is used
+            stream_protocols(&ProtocolVersion::ALL),
             request_response::Config::default()
                 .with_request_timeout(TRANSFER_TIMEOUT)
                 .with_max_concurrent_streams(10),
         );
 
         // Build swarm
-        let swarm = SwarmBuilder::with_existing_identity(local_key)
-            .with_tokio()
-            .with_tcp(
-                libp2p::tcp::Config::default()
-                    .port_reuse(true)
-                    .nodelay(true),
-                libp2p::noise::Config::new,
-                libp2p::yamux::Config::default,
-            )
-            .context("Failed to configure transport")?
-            .with_behaviour(|_| Ok(behaviour))
-            .context("Failed to configure behaviour")?
-            .with_swarm_config(|cfg| {
+        let swarm = build_swarm(
+            local_key,
+            security,
+            &transport,
+            behaviour,
+            |cfg| {
                 cfg.with_idle_connection_timeout(Duration::from_secs(30))
-                   .with_dial_concurrency_factor(5.try_into().unwrap())
-            })
-            .build();
+                   .with_dial_concurrency_factor(dial_concurrency)
+            },
+        )?;
 
         Ok(Self {
             swarm,
             active_sends: Arc::new(RwLock::new(HashMap::new())),
             converter: Arc::new(Mutex::new(FileConverter::new())),
-            retry_config: retry_config.unwrap_or_default(),
+            retry_config,
             progress_callback: None,
+            reputation: Arc::new(PeerReputationStore::load(ReputationConfig::default())?),
+            security,
+            send_slots: PrioritySlots::new(DEFAULT_MAX_CONCURRENT_SENDS),
+            max_concurrent_sends: DEFAULT_MAX_CONCURRENT_SENDS,
+            cleanup: Arc::new(CleanupManager::new()),
+            transfer_tasks: Arc::new(Mutex::new(HashMap::new())),
+            memory_budget: MemoryBudget::new(DEFAULT_MEMORY_BUDGET),
+            bandwidth: Bandwidth::unlimited(),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            max_inline_result: DEFAULT_MAX_INLINE_RESULT,
+            conversion_timeout: None,
+            auth_token: None,
+            completed_retention: CompletedRetention::default(),
+            adaptive_chunks: true,
+            inline_threshold: DEFAULT_INLINE_TRANSFER_SIZE,
+            accept_timeout: DEFAULT_ACCEPT_TIMEOUT,
+            dialed_addrs: HashMap::new(),
+            pending_replies: HashMap::new(),
+            dial_failures: HashMap::new(),
         })
     }
 
+    /// Dial `peer_id` at `address`, remembering the address for error reports
+    fn dial(&mut self, peer_id: PeerId, address: Multiaddr) -> std::result::Result<(), libp2p::swarm::DialError> {
+        self.dialed_addrs.insert(peer_id, address.clone());
+        self.dial_failures.remove(&peer_id);
+        self.swarm.dial(DialOpts::peer_id(peer_id).addresses(vec![address]).build())
+    }
+
+    /// Address `peer_id` was last dialed at, or an empty one if it wasn't
+    /// dialed by this sender
+    fn dialed_addr(&self, peer_id: &PeerId) -> Multiaddr {
+        self.dialed_addrs.get(peer_id).cloned().unwrap_or_else(Multiaddr::empty)
+    }
+
+    /// Send `request` to `peer` and register for its reply in the same
+    /// step, so the reply can't be routed before anyone is waiting for it
+    fn send_tracked(
+        &mut self,
+        peer: &PeerId,
+        request: FileTransferRequest,
+    ) -> (OutboundRequestId, oneshot::Receiver<Result<FileTransferResponse>>) {
+        let request_id = self.swarm.behaviour_mut().send_request(peer, request);
+        let (reply, rx) = oneshot::channel();
+        self.pending_replies.insert(request_id, PendingReply { peer: *peer, reply });
+        (request_id, rx)
+    }
+
+    /// Route every swarm event that is ready without waiting, so callers
+    /// hold the lock only for as long as that takes
+    fn pump_events(&mut self) {
+        while let Some(event) = self.swarm.select_next_some().now_or_never() {
+            self.route_event(event);
+        }
+    }
+
+    /// Hand a swarm event to whoever is waiting for it
+    fn route_event(&mut self, event: SwarmEvent<request_response::Event<FileTransferRequest, FileTransferResponse>>) {
+        match event {
+            SwarmEvent::Behaviour(request_response::Event::ResponseReceived { request_id, response, .. }) => {
+                match self.pending_replies.remove(&request_id) {
+                    Some(pending) => { let _ = pending.reply.send(Ok(response)); }
+                    None => debug!("Dropping response to request {:?} nobody is waiting for", request_id),
+                }
+            }
+            SwarmEvent::Behaviour(request_response::Event::OutboundFailure { peer, request_id, error, .. }) => {
+                warn!("Request to {} failed: {:?}", peer, error);
+                let error = outbound_failure_error(peer, self.dialed_addr(&peer), &error);
+                if let Some(pending) = self.pending_replies.remove(&request_id) {
+                    let _ = pending.reply.send(Err(error));
+                } else if !self.swarm.is_connected(&peer) {
+                    self.dial_failures.insert(peer, error);
+                }
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                info!("Connection established with peer: {}", peer_id);
+                self.dial_failures.remove(&peer_id);
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
+                if self.swarm.is_connected(&peer_id) {
+                    debug!("Extra dial to {} failed: {}", peer_id, error);
+                    return;
+                }
+                let error = if is_negotiation_failure(&error) {
+                    ProtocolError::NegotiationFailed {
+                        peer_id,
+                        expected: self.security.protocol_name().to_string(),
+                        actual: "no common security protocol (is the peer using a different --security?)".to_string(),
+                    }.into()
+                } else {
+                    warn!("Connection error to {}: {}", peer_id, error);
+                    anyhow::anyhow!("Connection failed: {}", error)
+                };
+                self.dial_failures.insert(peer_id, error);
+            }
+            SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                let lost: Vec<OutboundRequestId> = self.pending_replies
+                    .iter()
+                    .filter(|(_, pending)| pending.peer == peer_id)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for request_id in lost {
+                    if let Some(pending) = self.pending_replies.remove(&request_id) {
+                        let _ = pending.reply.send(Err(ProtocolError::StreamClosed { peer_id }.into()));
+                    }
+                }
+            }
+            _ => debug!("Received other swarm event"),
+        }
+    }
+
+    /// Limit how many transfers run at once; later sends are queued.
+    /// Only affects transfers started after the call.
+    pub fn set_max_concurrent_sends(&mut self, max: usize) {
+        let max = max.max(1);
+        self.send_slots = PrioritySlots::new(max);
+        self.max_concurrent_sends = max;
+    }
+
+    /// Largest converted result to accept inside a response. Receivers use
+    /// the smaller of this and their own limit and report bigger results by
+    /// size only.
+    pub fn set_max_inline_result_bytes(&mut self, bytes: u64) {
+        self.max_inline_result = bytes;
+    }
+
+    /// Ask receivers to give up converting after `timeout`. Receivers
+    /// clamp this to their own maximum.
+    pub fn set_conversion_timeout(&mut self, timeout: Option<Duration>) {
+        self.conversion_timeout = timeout;
+    }
+
+    /// Shared secret to include in transfer requests
+    pub fn set_auth_token(&mut self, token: Option<String>) {
+        self.auth_token = token;
+    }
+
+    /// How long, and how many, finished transfers are kept for status checks
+    pub fn set_completed_retention(&mut self, retention: CompletedRetention) {
+        self.completed_retention = retention;
+    }
+
+    /// Adapt chunk sizes to measured throughput (the default). When off,
+    /// every chunk but the last is `MAX_CHUNK_SIZE`, which keeps chunking
+    /// deterministic for tests.
+    pub fn set_adaptive_chunks(&mut self, enabled: bool) {
+        self.adaptive_chunks = enabled;
+    }
+
+    /// Send files of up to `bytes` inside the transfer request, skipping
+    /// the offer and chunking; 0 always sends chunks. Capped at
+    /// `MAX_INLINE_TRANSFER_SIZE`.
+    pub fn set_inline_transfer_threshold(&mut self, bytes: u64) {
+        self.inline_threshold = bytes.min(MAX_INLINE_TRANSFER_SIZE);
+    }
+
+    /// How long to wait for a receiver to accept a transfer before any
+    /// chunk is sent
+    pub fn set_accept_timeout(&mut self, timeout: Duration) {
+        self.accept_timeout = timeout;
+    }
+
+    /// Share a cleanup manager with the rest of the application
+    pub fn set_cleanup_manager(&mut self, cleanup: Arc<CleanupManager>) {
+        self.cleanup = cleanup;
+    }
+
+    /// Share a memory budget, e.g. with a local conversion service
+    pub fn set_memory_budget(&mut self, budget: Arc<MemoryBudget>) {
+        self.memory_budget = budget;
+    }
+
+    /// Share bandwidth caps, e.g. with a local conversion service, so
+    /// uploads from both count against one cap
+    pub fn set_bandwidth(&mut self, bandwidth: Arc<Bandwidth>) {
+        self.bandwidth = bandwidth;
+    }
+
+    /// Current and peak chunk data held by running transfers
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory_budget.usage()
+    }
+
+    /// Keep a connection to `peer_id` open, redialing with jittered backoff
+    /// whenever it drops. Transfers to the peer wait for the session while
+    /// it is down and carry on once it is back.
+    pub async fn open_session(&mut self, peer_id: PeerId, address: Multiaddr, config: SessionConfig) -> Result<()> {
+        let (state, _) = watch::channel(SessionState::Connecting);
+        self.sessions.write().await.insert(peer_id, PeerSession {
+            address: address.clone(),
+            config,
+            state,
+            attempt: 0,
+            reconnects: 0,
+        });
+        info!("Opening session to {} at {}", peer_id, address);
+
+        if let Err(e) = self.dial(peer_id, address) {
+            self.session_lost(peer_id, e.to_string()).await;
+        }
+        Ok(())
+    }
+
+    /// Stop maintaining the session to `peer_id`. Returns whether one was open.
+    pub async fn close_session(&mut self, peer_id: &PeerId) -> bool {
+        self.sessions.write().await.remove(peer_id).is_some()
+    }
+
+    /// State of the session to `peer_id`, if one is open
+    pub async fn session_status(&self, peer_id: &PeerId) -> Option<SessionStatus> {
+        self.sessions.read().await.get(peer_id).map(|session| session.status(*peer_id))
+    }
+
+    /// State of every open session
+    pub async fn sessions(&self) -> Vec<SessionStatus> {
+        self.sessions.read().await
+            .iter()
+            .map(|(peer_id, session)| session.status(*peer_id))
+            .collect()
+    }
+
+    async fn session_connected(&self, peer_id: PeerId) {
+        if let Some(session) = self.sessions.write().await.get_mut(&peer_id) {
+            if session.attempt > 0 {
+                info!("Session to {} restored after {} reconnect attempt(s)", peer_id, session.attempt);
+                session.reconnects += 1;
+            }
+            session.attempt = 0;
+            session.state.send_replace(SessionState::Connected { since: Instant::now() });
+        }
+    }
+
+    /// Mark the session down and schedule the next reconnect
+    async fn session_lost(&self, peer_id: PeerId, reason: String) {
+        if let Some(session) = self.sessions.write().await.get_mut(&peer_id) {
+            let since = match *session.state.borrow() {
+                SessionState::Reconnecting { since, .. } => since,
+                _ => Instant::now(),
+            };
+            session.attempt += 1;
+            let delay = reconnect_delay(&session.config, session.attempt, rand::random::<f64>());
+            warn!(
+                "Session to {} is down ({}); reconnect {} in {:?}",
+                peer_id, reason, session.attempt, delay
+            );
+            session.state.send_replace(SessionState::Reconnecting {
+                since,
+                attempt: session.attempt,
+                retry_at: Instant::now() + delay,
+                last_error: Some(reason),
+            });
+        }
+    }
+
+    /// Dial every session whose reconnect is due
+    async fn redial_due_sessions(&mut self) {
+        let now = Instant::now();
+        let due: Vec<(PeerId, Multiaddr)> = self.sessions.read().await
+            .iter()
+            .filter(|(_, session)| matches!(
+                *session.state.borrow(),
+                SessionState::Reconnecting { retry_at, .. } if retry_at <= now
+            ))
+            .map(|(peer_id, session)| (*peer_id, session.address.clone()))
+            .collect();
+
+        for (peer_id, address) in due {
+            if let Some(session) = self.sessions.read().await.get(&peer_id) {
+                session.state.send_replace(SessionState::Connecting);
+            }
+            debug!("Redialing session peer {} at {}", peer_id, address);
+            if let Err(e) = self.dial(peer_id, address) {
+                self.session_lost(peer_id, e.to_string()).await;
+            }
+        }
+    }
+
+    /// Abort every running transfer task. Their drop guards remove the
+    /// tracking entries and unregister them from the cleanup manager.
+    pub async fn abort_transfers(&self) {
+        let tasks: Vec<_> = self.transfer_tasks.lock().await.drain().collect();
+        for (transfer_id, task) in tasks {
+            task.abort();
+            let _ = task.await;
+            debug!("Aborted transfer task {}", transfer_id);
+        }
+    }
+
     /// Set progress callback function
     pub fn set_progress_callback<F>(&mut self, callback: F)
     where
@@ -234,6 +1007,16 @@ impl FileSender {
         self.progress_callback = Some(Arc::new(callback));
     }
 
+    /// Share a reputation store, e.g. the one owned by the conversion service
+    pub fn set_reputation_store(&mut self, store: Arc<PeerReputationStore>) {
+        self.reputation = store;
+    }
+
+    /// Get the current reputation score for a peer
+    pub async fn reputation(&self, peer_id: &PeerId) -> f64 {
+        self.reputation.reputation(peer_id).await
+    }
+
     /// Send file to target peer
     pub async fn send_file<P: AsRef<Path>>(
         &mut self,
@@ -243,22 +1026,206 @@ impl FileSender {
         target_format: Option<String>,
         return_result: bool,
     ) -> Result<String> {
-        let file_path = file_path.as_ref();
+        self.send_file_with_metadata(
+            target_peer,
+            target_addr,
+            file_path,
+            target_format,
+            return_result,
+            HashMap::new(),
+        ).await
+    }
+
+    /// Send file to target peer, tagged with metadata the receiver echoes back
+    pub async fn send_file_with_metadata<P: AsRef<Path>>(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        file_path: P,
+        target_format: Option<String>,
+        return_result: bool,
+        metadata: HashMap<String, String>,
+    ) -> Result<String> {
+        self.send_file_prioritized(
+            target_peer,
+            target_addr,
+            file_path,
+            target_format,
+            return_result,
+            metadata,
+            DEFAULT_PRIORITY,
+        ).await
+    }
+
+    /// Send file to target peer with a priority (0 to `MAX_PRIORITY`, higher
+    /// first). Priority orders the send queue and the receiver's conversion
+    /// queue; it never interrupts a transfer that is already running.
+    pub async fn send_file_prioritized<P: AsRef<Path>>(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        file_path: P,
+        target_format: Option<String>,
+        return_result: bool,
+        metadata: HashMap<String, String>,
+        priority: u8,
+    ) -> Result<String> {
+        self.start_send(
+            target_peer,
+            target_addr,
+            file_path.as_ref(),
+            target_format,
+            return_result,
+            metadata,
+            priority,
+            None,
+        ).await
+    }
+
+    /// Send an in-memory buffer as a file named `filename`, without writing
+    /// it to disk first. The file type is detected from the bytes when
+    /// `file_type` is `None`.
+    pub async fn send_bytes(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        bytes: Vec<u8>,
+        filename: &str,
+        file_type: Option<FileType>,
+        target_format: Option<String>,
+    ) -> Result<String> {
+        if filename.trim().is_empty() {
+            return Err(anyhow::anyhow!("A filename is required to send bytes"));
+        }
+        let file_type = match file_type {
+            Some(file_type) => file_type,
+            None => self.converter.lock().await.detect_file_type_from_bytes(&bytes),
+        };
+
+        let input = SendInput {
+            path: PathBuf::from(filename),
+            size: bytes.len() as u64,
+            file_type,
+            source: SendSource::Bytes(std::io::Cursor::new(bytes)),
+            archive: None,
+        };
+        self.queue_send(
+            target_peer,
+            target_addr,
+            input,
+            target_format,
+            false,
+            HashMap::new(),
+            DEFAULT_PRIORITY,
+        ).await
+    }
+
+    /// Send a whole directory as a single `.tar` transfer.
+    ///
+    /// The directory is tarred into a temporary archive, which is streamed
+    /// from disk like any other file and removed once the transfer finishes.
+    /// Archives are never converted; the receiver stores them and, if
+    /// configured to, unpacks them into their own subdirectory.
+    pub async fn send_directory<P: AsRef<Path>>(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        dir: P,
+        metadata: HashMap<String, String>,
+        priority: u8,
+    ) -> Result<String> {
+        let dir = tokio::fs::canonicalize(dir.as_ref()).await
+            .with_context(|| format!("Failed to resolve directory: {}", dir.as_ref().display()))?;
+        if !dir.is_dir() {
+            return Err(anyhow::anyhow!("Not a directory: {}", dir.display()));
+        }
+        let root = dir.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "archive".to_string());
+
+        // Staged in its own directory so the archive keeps a readable name
+        let staging = tempfile::tempdir().context("Failed to create archive staging directory")?;
+        let archive_path = staging.path().join(format!("{}.tar", root));
+
+        let (source, target) = (dir.clone(), archive_path.clone());
+        tokio::task::spawn_blocking(move || write_directory_archive(&source, &target, &root))
+            .await?
+            .with_context(|| format!("Failed to archive {}", dir.display()))?;
+
+        self.start_send(
+            target_peer,
+            target_addr,
+            &archive_path,
+            None,
+            false,
+            metadata,
+            priority,
+            Some(staging),
+        ).await
+    }
+
+    /// Queue a transfer of `file_path`. When `archive` is set the file is a
+    /// directory archive living in that staging directory, which is kept
+    /// until the transfer task ends.
+    #[allow(clippy::too_many_arguments)]
+    async fn start_send(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        file_path: &Path,
+        target_format: Option<String>,
+        return_result: bool,
+        metadata: HashMap<String, String>,
+        priority: u8,
+        archive: Option<tempfile::TempDir>,
+    ) -> Result<String> {
+        // Validate file, refusing ones still being written
+        let file = open_for_send(file_path, STABILITY_CHECK_INTERVAL).await?;
+
+        let file_metadata = file.metadata().await
+            .with_context(|| format!("Failed to read file metadata: {}", file_path.display()))?;
+
+        // Detect file type; archives would have to be read in full for nothing
+        let file_type = if archive.is_some() {
+            FileType::Unknown
+        } else {
+            self.converter.lock().await.detect_file_type(&file_path)?
+        };
+
+        let input = SendInput {
+            path: file_path.to_path_buf(),
+            size: file_metadata.len(),
+            file_type,
+            source: SendSource::File(file),
+            archive,
+        };
+        self.queue_send(target_peer, target_addr, input, target_format, return_result, metadata, priority).await
+    }
+
+    /// Queue a transfer of data from a file or buffer
+    #[allow(clippy::too_many_arguments)]
+    async fn queue_send(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        input: SendInput,
+        target_format: Option<String>,
+        return_result: bool,
+        metadata: HashMap<String, String>,
+        priority: u8,
+    ) -> Result<String> {
+        let SendInput { path: file_path, size: file_size, file_type, source: mut file, archive } = input;
+        let priority = clamp_priority(priority);
         let transfer_id = Uuid::new_v4().to_string();
 
+        // Oversized metadata is rejected rather than truncated
+        validate_metadata(&metadata)?;
+
         info!(
             "Starting file transfer {} to peer {} at {}",
             transfer_id, target_peer, target_addr
         );
 
-        // Validate file
-        let file = File::open(&file_path).await
-            .with_context(|| format!("Failed to open file: {}", file_path.display()))?;
-
-        let metadata = file.metadata().await
-            .with_context(|| format!("Failed to read file metadata: {}", file_path.display()))?;
-
-        let file_size = metadata.len();
         if file_size > MAX_FILE_SIZE {
             return Err(anyhow::anyhow!(
                 "File size {} exceeds maximum allowed size {}",
@@ -266,11 +1233,35 @@ impl FileSender {
             ));
         }
 
-        // Detect file type
-        let file_type = self.converter.lock().await.detect_file_type(&file_path)?;
+        // Small files go inside the request: per-transfer overhead would
+        // outweigh the data
+        let inline_data = if file_size > 0 && file_size <= self.inline_threshold {
+            let mut data = Vec::with_capacity(file_size as usize);
+            file.read_to_end(&mut data).await
+                .with_context(|| format!("Failed to read {}", file_path.display()))?;
+            if data.len() as u64 != file_size {
+                return Err(anyhow::anyhow!(
+                    "File {} changed while sending: read {} of {} bytes",
+                    file_path.display(), data.len(), file_size
+                ));
+            }
+            Some(data)
+        } else {
+            None
+        };
 
-        // Calculate chunks
-        let total_chunks = ((file_size + MAX_CHUNK_SIZE as u64 - 1) / MAX_CHUNK_SIZE as u64) as usize;
+        // Calculate chunks. Adaptive chunk sizes aren't known up front, so
+        // the request carries the most chunks the smallest size could need.
+        // An empty file is sent as one empty final chunk, and inline data
+        // counts as the only chunk.
+        let adaptive_chunks = self.adaptive_chunks && inline_data.is_none();
+        let chunks_of = |size: usize| file_size.div_ceil(size as u64) as usize;
+        let total_chunks = chunks_of(MAX_CHUNK_SIZE).max(1);
+        let chunk_count = if adaptive_chunks {
+            chunks_of(MIN_CHUNK_SIZE).max(1)
+        } else {
+            total_chunks
+        };
 
         // Create progress tracking
         let progress = SendProgress {
@@ -282,9 +1273,14 @@ impl FileSender {
             chunks_sent: 0,
             total_chunks,
             start_time: Instant::now(),
-            status: TransferStatus::Connecting,
+            status: TransferStatus::Queued,
             connection_attempts: 0,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority,
+            throughput: Default::default(),
+            finished_at: None,
         };
 
         // Create transfer request
@@ -298,7 +1294,19 @@ impl FileSender {
             file_type: file_type.to_string(),
             target_format,
             return_result,
-            chunk_count: total_chunks,
+            chunk_count,
+            protocol_version: Default::default(),
+            metadata,
+            priority,
+            is_archive: archive.is_some(),
+            max_inline_result: self.max_inline_result,
+            // Responses are read with the default codec limits
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE as u64,
+            conversion_timeout_ms: self.conversion_timeout.map(|t| t.as_millis() as u64),
+            auth_token: self.auth_token.clone(),
+            kind: RequestKind::Transfer,
+            adaptive_chunks,
+            inline_data,
         };
 
         // Create response channel
@@ -312,23 +1320,49 @@ impl FileSender {
             request_id: None,
             response_receiver: Some(response_rx),
             cancel_sender: Some(cancel_tx),
+            completed: Arc::new(Notify::new()),
         };
 
-        self.active_sends.write().await.insert(transfer_id.clone(), active_send);
+        {
+            let mut active_sends = self.active_sends.write().await;
+            active_sends.insert(transfer_id.clone(), active_send);
+            refresh_queue_estimates(&mut active_sends, self.max_concurrent_sends);
+        }
+
+        // If the task is aborted, drop its entry so it doesn't linger as in-flight
+        let guard = self.cleanup.guard(
+            format!("transfer:{}", transfer_id),
+            format!("Outgoing transfer {} to {}", file_path.display(), target_peer),
+            {
+                let active_sends = self.active_sends.clone();
+                let transfer_id = transfer_id.clone();
+                move || discard_entry(active_sends, transfer_id, |send| send.completed.notify_waiters())
+            },
+        );
+        let cleanup = self.cleanup.clone();
+        let transfer_tasks = self.transfer_tasks.clone();
 
         // Start the transfer process
         let sender_clone = Arc::new(Mutex::new(self));
+        let task_transfer_id = transfer_id.clone();
         let transfer_task = tokio::spawn(async move {
-            Self::perform_transfer(
+            let result = Self::perform_transfer(
                 sender_clone,
-                transfer_id.clone(),
+                task_transfer_id.clone(),
                 target_peer,
                 target_addr,
                 request,
                 response_tx,
                 cancel_rx,
-            ).await
+            ).await;
+
+            // Finished normally: keep the entry for status queries
+            let resource_id = guard.take();
+            cleanup.unregister_resource(&resource_id).await;
+            drop(archive);
+            result
         });
+        transfer_tasks.lock().await.insert(transfer_id.clone(), transfer_task);
 
         // Wait briefly to ensure transfer is started
         tokio::time::sleep(Duration::from_millis(100)).await;
@@ -346,11 +1380,80 @@ impl FileSender {
         response_tx: mpsc::Sender<FileTransferResponse>,
         mut cancel_rx: mpsc::Receiver<()>,
     ) -> Result<()> {
-        let retry_config = sender.lock().await.retry_config.clone();
-        let mut delay = retry_config.initial_delay;
+        let (mut retry_config, reputation, send_slots) = {
+            let sender_lock = sender.lock().await;
+            (
+                sender_lock.retry_config.clone(),
+                sender_lock.reputation.clone(),
+                sender_lock.send_slots.clone(),
+            )
+        };
+
+        // Wait for a free slot; the transfer can be cancelled while queued
+        let _slot = tokio::select! {
+            slot = send_slots.acquire(request.priority) => slot,
+            _ = cancel_rx.recv() => {
+                warn!("Transfer {} cancelled while queued", transfer_id);
+                Self::update_transfer_status(sender.clone(), &transfer_id, TransferStatus::Cancelled).await;
+                return Ok(());
+            }
+        };
+
+        // Restart the clock so speed and ETA reflect the transfer, not the wait
+        {
+            let sender_lock = sender.lock().await;
+            if let Some(active_send) = sender_lock.active_sends.write().await.get_mut(&transfer_id) {
+                active_send.progress.start_time = Instant::now();
+                active_send.progress.estimated_start = None;
+                active_send.progress.estimated_completion = None;
+            }
+        }
+        Self::update_transfer_status(sender.clone(), &transfer_id, TransferStatus::Connecting).await;
+
+        // Don't spend the full retry budget on peers that keep failing
+        if reputation.is_low(&target_peer).await {
+            retry_config.max_attempts = retry_config.max_attempts.min(retry_config.low_reputation_attempts);
+            info!(
+                "Peer {} has low reputation ({:.2}), limiting to {} attempts",
+                target_peer,
+                reputation.reputation(&target_peer).await,
+                retry_config.max_attempts
+            );
+        }
+
+        let backoff = retry_config.backoff();
         let mut last_error = None;
+        // Set once a response is lost; later attempts ask the receiver
+        // before sending anything again
+        let mut response_lost = false;
+        let mut session = sender.lock().await.sessions.read().await
+            .get(&target_peer)
+            .map(|session| session.state.subscribe());
+
+        let mut attempt = 0;
+        while attempt < retry_config.max_attempts {
+            attempt += 1;
+
+            // Attempts aren't spent while the session is down
+            if let Some(ref mut state) = session {
+                if !state.borrow().is_connected() {
+                    info!("Transfer {} waiting for session to {}", transfer_id, target_peer);
+                }
+                tokio::select! {
+                    result = state.wait_for(|state| state.is_connected()) => {
+                        // The session was closed; fall back to plain retries
+                        if result.is_err() {
+                            session = None;
+                        }
+                    }
+                    _ = cancel_rx.recv() => {
+                        warn!("Transfer {} cancelled while waiting for session", transfer_id);
+                        Self::update_transfer_status(sender.clone(), &transfer_id, TransferStatus::Cancelled).await;
+                        return Ok(());
+                    }
+                }
+            }
 
-        for attempt in 1..=retry_config.max_attempts {
             // Update progress
             {
                 let mut sender_lock = sender.lock().await;
@@ -363,25 +1466,62 @@ impl FileSender {
 
             info!("Connection attempt {}/{} for transfer {}", attempt, retry_config.max_attempts, transfer_id);
 
-            // Attempt connection with timeout
-            let connection_result = timeout(
-                retry_config.connection_timeout,
-                Self::attempt_connection_and_transfer(
-                    sender.clone(),
-                    transfer_id.clone(),
-                    target_peer,
-                    target_addr.clone(),
-                    request.clone(),
-                    response_tx.clone(),
-                )
-            ).await;
+            // The receiver may already have the file; only send it again
+            // if it has no record of it
+            let recovered = if response_lost {
+                Some(timeout(
+                    retry_config.connection_timeout,
+                    Self::recover_lost_response(
+                        sender.clone(),
+                        &request,
+                        target_peer,
+                        target_addr.clone(),
+                        response_tx.clone(),
+                    )
+                ).await)
+            } else {
+                None
+            };
+
+            let connection_result = match recovered {
+                Some(Ok(Ok(true))) => Ok(Ok(())),
+                Some(Ok(Err(e))) => Ok(Err(e)),
+                Some(Err(elapsed)) => Err(elapsed),
+                Some(Ok(Ok(false))) | None => {
+                    if response_lost {
+                        info!("Receiver has no record of transfer {}; sending it again", transfer_id);
+                        response_lost = false;
+                    }
+
+                    // The dial is bounded by the connection timeout; the
+                    // transfer after it by the request timeout
+                    Ok(Self::attempt_connection_and_transfer(
+                        sender.clone(),
+                        transfer_id.clone(),
+                        target_peer,
+                        target_addr.clone(),
+                        request.clone(),
+                        response_tx.clone(),
+                    ).await)
+                }
+            };
 
             match connection_result {
                 Ok(Ok(())) => {
-                    info!("Transfer {} completed successfully", transfer_id);
+                    info!("Transfer {} finished", transfer_id);
+                    reputation.record_success(&target_peer).await;
                     return Ok(());
                 }
                 Ok(Err(e)) => {
+                    if retry_config.recover_lost_responses && is_response_lost(&e) {
+                        response_lost = true;
+                    }
+                    // Protocol mismatches will not fix themselves, so don't retry them
+                    if !is_retryable(&e) {
+                        error!("Transfer {} aborted: {}", transfer_id, e);
+                        last_error = Some(e);
+                        break;
+                    }
                     last_error = Some(e);
                     warn!("Transfer attempt {} failed: {}", attempt, last_error.as_ref().unwrap());
                 }
@@ -403,19 +1543,26 @@ impl FileSender {
                 return Ok(());
             }
 
+            // Lost with the session: resume once it reconnects, without
+            // counting the attempt or backing off here
+            if session.as_ref().map_or(false, |state| !state.borrow().is_connected()) {
+                info!("Session to {} dropped; transfer {} resumes on reconnect", target_peer, transfer_id);
+                attempt -= 1;
+                continue;
+            }
+
             // Wait before retry (except on last attempt)
             if attempt < retry_config.max_attempts {
+                let delay = backoff.delay(attempt as u32);
                 info!("Retrying in {:?}...", delay);
                 sleep(delay).await;
-                delay = Duration::from_millis(
-                    ((delay.as_millis() as f64) * retry_config.backoff_multiplier).min(retry_config.max_delay.as_millis() as f64) as u64
-                );
             }
         }
 
         // All attempts failed
         let final_error = last_error.unwrap_or_else(|| anyhow::anyhow!("All connection attempts failed"));
         error!("Transfer {} failed after {} attempts: {}", transfer_id, retry_config.max_attempts, final_error);
+        reputation.record_failure(&target_peer).await;
 
         Self::update_transfer_status(
             sender.clone(),
@@ -436,14 +1583,7 @@ impl FileSender {
         response_tx: mpsc::Sender<FileTransferResponse>,
     ) -> Result<()> {
         // Connect to peer
-        {
-            let mut sender_lock = sender.lock().await;
-            sender_lock.swarm.dial(
-                DialOpts::peer_id(target_peer)
-                    .addresses(vec![target_addr.clone()])
-                    .build()
-            )?;
-        }
+        sender.lock().await.dial(target_peer, target_addr.clone())?;
 
         // Wait for connection establishment  
         let connection_established = Self::wait_for_connection(sender.clone(), target_peer).await?;
@@ -458,12 +1598,22 @@ impl FileSender {
             TransferStatus::Negotiating
         ).await;
 
+        // Offer the file first so a receiver that won't take it says so
+        // before any chunk is sent. Inline data is no bigger than an offer
+        // round trip is worth, so it goes straight away.
+        let inline_size = request.inline_data.as_ref().map(|data| data.len() as u64);
+        if inline_size.is_none()
+            && !Self::offer_transfer(sender.clone(), &request, target_peer, response_tx.clone()).await?
+        {
+            return Ok(());
+        }
+        if let Some(size) = inline_size {
+            let bandwidth = sender.lock().await.bandwidth.clone();
+            bandwidth.acquire(target_peer, TransferDirection::Sent, size).await;
+        }
+
         // Send the initial request
-        let request_id = {
-            let mut sender_lock = sender.lock().await;
-            sender_lock.swarm.behaviour_mut()
-                .send_request(&target_peer, request.clone())
-        };
+        let (request_id, reply) = sender.lock().await.send_tracked(&target_peer, request.clone());
 
         // Update request ID in active transfer
         {
@@ -473,53 +1623,150 @@ impl FileSender {
             }
         }
 
-        // Send file chunks
-        Self::send_file_chunks(sender.clone(), &transfer_id, target_peer).await?;
+        // Send file chunks, unless the request carried the file
+        match inline_size {
+            Some(size) => Self::record_inline_send(sender.clone(), &transfer_id, size).await,
+            None => Self::send_file_chunks(sender.clone(), &transfer_id, target_peer).await?,
+        }
 
         // Wait for response
-        Self::wait_for_response(sender.clone(), &transfer_id, response_tx).await?;
+        Self::wait_for_response(sender.clone(), &transfer_id, target_peer, reply, response_tx).await?;
 
         Ok(())
     }
 
-    /// Wait for connection to be established
+    /// Count a file sent inside its request as fully sent
+    async fn record_inline_send(sender: Arc<Mutex<&mut Self>>, transfer_id: &str, size: u64) {
+        Self::update_transfer_status(sender.clone(), transfer_id, TransferStatus::Sending).await;
+
+        let sender_lock = sender.lock().await;
+        if let Some(active_send) = sender_lock.active_sends.write().await.get_mut(transfer_id) {
+            active_send.progress.sent_bytes = size;
+            active_send.progress.chunks_sent = 1;
+            sender_lock.notify_progress(&active_send.progress);
+        }
+    }
+
+    /// Offer a transfer and wait for the receiver's answer.
+    ///
+    /// Returns `true` once it is accepted. A rejection is delivered as the
+    /// transfer's outcome and returns `false`; it isn't retried, since the
+    /// receiver has already decided. No answer within the accept timeout
+    /// fails the attempt.
+    async fn offer_transfer(
+        sender: Arc<Mutex<&mut Self>>,
+        request: &FileTransferRequest,
+        target_peer: PeerId,
+        response_tx: mpsc::Sender<FileTransferResponse>,
+    ) -> Result<bool> {
+        let transfer_id = &request.transfer_id;
+        let offer = FileTransferRequest { kind: RequestKind::Offer, ..request.clone() };
+        let (offer_reply, accept_timeout) = {
+            let mut sender_lock = sender.lock().await;
+            (sender_lock.send_tracked(&target_peer, offer).1, sender_lock.accept_timeout)
+        };
+
+        let answer = timeout(accept_timeout, Self::wait_for_reply(sender.clone(), offer_reply))
+            .await
+            .map_err(|_| TimeoutError::NetworkOperation {
+                operation: format!("offer of transfer {}", transfer_id),
+                peer_id: target_peer,
+                duration: accept_timeout,
+            })??;
+
+        if answer.success {
+            info!("Receiver {} accepted transfer {}", target_peer, transfer_id);
+            return Ok(true);
+        }
+
+        info!(
+            "Receiver {} rejected transfer {} before any data was sent: {}",
+            target_peer, transfer_id, answer.error_message.as_deref().unwrap_or("no reason given")
+        );
+        Self::deliver_response(sender, transfer_id, answer, response_tx).await;
+        Ok(false)
+    }
+
+    /// Wait for connection to be established, for at most the retry
+    /// config's connection timeout
     async fn wait_for_connection(
         sender: Arc<Mutex<&mut Self>>,
         target_peer: PeerId,
     ) -> Result<bool> {
-        let timeout_duration = Duration::from_secs(30);
-        let start_time = Instant::now();
+        let dial_timeout = sender.lock().await.retry_config.connection_timeout;
+        timeout(dial_timeout, Self::next_connection_outcome(sender, target_peer))
+            .await
+            .map_err(|_| TimeoutError::NetworkOperation {
+                operation: "dial".to_string(),
+                peer_id: target_peer,
+                duration: dial_timeout,
+            })?
+    }
 
-        while start_time.elapsed() < timeout_duration {
-            let event = {
+    /// Route swarm events until the connection to `target_peer` is
+    /// established or its dial fails. The lock is released between polls
+    /// so other transfers keep going meanwhile.
+    async fn next_connection_outcome(
+        sender: Arc<Mutex<&mut Self>>,
+        target_peer: PeerId,
+    ) -> Result<bool> {
+        loop {
+            {
                 let mut sender_lock = sender.lock().await;
-                sender_lock.swarm.select_next_some().await
-            };
-
-            match event {
-                SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == target_peer => {
-                    info!("Connection established with peer: {}", peer_id);
+                sender_lock.pump_events();
+                if sender_lock.swarm.is_connected(&target_peer) {
                     return Ok(true);
                 }
-                SwarmEvent::OutgoingConnectionError { peer_id, error, .. } 
-                    if peer_id == Some(target_peer) => {
-                    warn!("Connection error to {}: {}", target_peer, error);
-                    return Err(anyhow::anyhow!("Connection failed: {}", error));
-                }
-                SwarmEvent::Behaviour(request_response::Event::OutboundFailure { 
-                    peer, error, .. 
-                }) if peer == target_peer => {
-                    warn!("Request-response outbound failure to {}: {:?}", peer, error);
-                    return Err(anyhow::anyhow!("Request-response failure: {:?}", error));
-                }
-                _ => {
-                    // Continue waiting for other events
-                    debug!("Received other swarm event while waiting for connection");
+                if let Some(error) = sender_lock.dial_failures.remove(&target_peer) {
+                    return Err(error);
                 }
             }
+            sleep(EVENT_PUMP_INTERVAL).await;
         }
+    }
+
+    /// Push a local file through `send_file_chunks` with no peer attached,
+    /// for benchmarking the read, framing and progress path. Returns the
+    /// bytes sent.
+    #[doc(hidden)]
+    pub async fn bench_send_chunks(&mut self, file_path: &Path) -> Result<u64> {
+        let file = File::open(file_path).await
+            .with_context(|| format!("Failed to open file: {}", file_path.display()))?;
+        let total_size = file.metadata().await?.len();
+        let transfer_id = Uuid::new_v4().to_string();
+        let target_peer = PeerId::random();
+
+        let active_send = ActiveSend {
+            progress: SendProgress {
+                transfer_id: transfer_id.clone(),
+                file_path: file_path.to_path_buf(),
+                peer_id: target_peer,
+                total_size,
+                sent_bytes: 0,
+                chunks_sent: 0,
+                total_chunks: total_size.div_ceil(MAX_CHUNK_SIZE as u64).max(1) as usize,
+                start_time: Instant::now(),
+                status: TransferStatus::Queued,
+                connection_attempts: 0,
+                last_error: None,
+                estimated_start: None,
+                estimated_completion: None,
+                priority: DEFAULT_PRIORITY,
+                throughput: Default::default(),
+                finished_at: None,
+            },
+            file: SendSource::File(file),
+            request_id: None,
+            response_receiver: None,
+            cancel_sender: None,
+            completed: Arc::new(Notify::new()),
+        };
+        let active_sends = self.active_sends.clone();
+        active_sends.write().await.insert(transfer_id.clone(), active_send);
 
-        Err(anyhow::anyhow!("Connection timeout"))
+        let result = Self::send_file_chunks(Arc::new(Mutex::new(self)), &transfer_id, target_peer).await;
+        let sent = active_sends.write().await.remove(&transfer_id).map_or(0, |send| send.progress.sent_bytes);
+        result.map(|()| sent)
     }
 
     /// Send file chunks to peer
@@ -537,8 +1784,28 @@ impl FileSender {
 
         let mut buffer = vec![0u8; MAX_CHUNK_SIZE];
         let mut chunk_index = 0;
+        let mut sizer = if sender.lock().await.adaptive_chunks {
+            ChunkSizer::adaptive(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE, DEFAULT_CHUNK_SIZE)
+        } else {
+            ChunkSizer::fixed(MAX_CHUNK_SIZE)
+        };
+
+        if let Some(active_send) = sender.lock().await.active_sends.write().await.get_mut(transfer_id) {
+            active_send.progress.throughput.begin(Instant::now());
+        }
 
         loop {
+            let chunk_size = sizer.chunk_size();
+            let chunk_started = Instant::now();
+
+            // Hold budget for the chunk until it has been sent; waits while
+            // other transfers have too much data in flight
+            let budget = sender.lock().await.memory_budget.clone();
+            let _reservation = budget
+                .reserve(chunk_size as u64, MEMORY_BACKPRESSURE_TIMEOUT)
+                .await
+                .map_err(|e| anyhow::anyhow!("Transfer {} stalled: {}", transfer_id, e))?;
+
             // Read next chunk
             let bytes_read = {
                 let sender_lock = sender.lock().await;
@@ -546,19 +1813,30 @@ impl FileSender {
                 let active_send = active_sends.get_mut(transfer_id)
                     .ok_or_else(|| anyhow::anyhow!("Transfer not found: {}", transfer_id))?;
 
-                active_send.file.read(&mut buffer).await?
+                // Fill the whole chunk; short reads aren't EOF on pipes
+                read_full(&mut active_send.file, &mut buffer[..chunk_size]).await?
             };
 
-            if bytes_read == 0 {
+            // An empty file still gets its final chunk so the receiver
+            // knows it is complete
+            if bytes_read == 0 && chunk_index > 0 {
                 break; // End of file
             }
 
+            let bandwidth = sender.lock().await.bandwidth.clone();
+            bandwidth.acquire(target_peer, TransferDirection::Sent, bytes_read as u64).await;
+
             // Create chunk
             let is_final = {
                 let sender_lock = sender.lock().await;
                 let active_sends = sender_lock.active_sends.read().await;
-                let active_send = active_sends.get(transfer_id).unwrap();
-                chunk_index >= active_send.progress.total_chunks - 1
+                let progress = &active_sends.get(transfer_id).unwrap().progress;
+                let last_by_count = if sizer.is_adaptive() {
+                    progress.sent_bytes + bytes_read as u64 >= progress.total_size
+                } else {
+                    chunk_index + 1 >= progress.total_chunks
+                };
+                bytes_read < chunk_size || last_by_count
             };
 
             let chunk = FileChunk {
@@ -587,14 +1865,23 @@ impl FileSender {
 
                 active_send.progress.sent_bytes += bytes_read as u64;
                 active_send.progress.chunks_sent = chunk_index + 1;
+                active_send.progress.throughput.record(bytes_read as u64, Instant::now());
+                if sizer.is_adaptive() {
+                    // Re-estimate the total from what's left at the current size
+                    let remaining = active_send.progress.total_size.saturating_sub(active_send.progress.sent_bytes);
+                    active_send.progress.total_chunks =
+                        chunk_index + 1 + remaining.div_ceil(sizer.chunk_size() as u64) as usize;
+                }
 
                 sender_lock.notify_progress(&active_send.progress);
+                refresh_queue_estimates(&mut active_sends, sender_lock.max_concurrent_sends);
             }
 
             chunk_index += 1;
 
             // Simulate network delay
             tokio::time::sleep(Duration::from_millis(10)).await;
+            sizer.record(bytes_read, chunk_started.elapsed());
 
             if is_final {
                 break;
@@ -605,10 +1892,15 @@ impl FileSender {
         Ok(())
     }
 
-    /// Wait for response from peer
+    /// Wait for response from peer.
+    ///
+    /// Fails with `ProtocolError::ResponseLost` if the connection closes
+    /// first, since the receiver may have processed the file anyway.
     async fn wait_for_response(
         sender: Arc<Mutex<&mut Self>>,
         transfer_id: &str,
+        target_peer: PeerId,
+        reply: oneshot::Receiver<Result<FileTransferResponse>>,
         response_tx: mpsc::Sender<FileTransferResponse>,
     ) -> Result<()> {
         // Update status
@@ -618,32 +1910,144 @@ impl FileSender {
             TransferStatus::WaitingResponse
         ).await;
 
-        // In a real implementation, this would wait for the actual response
-        // For now, we'll simulate a successful response
-        tokio::time::sleep(Duration::from_secs(2)).await;
-
-        let response = FileTransferResponse {
-            transfer_id: transfer_id.to_string(),
-            success: true,
-            error_message: None,
-            converted_data: None,
-            converted_filename: None,
-            processing_time_ms: 1500,
+        let response = Self::wait_for_reply(sender.clone(), reply).await
+            .map_err(|e| match e.downcast_ref::<ProtocolError>() {
+                Some(ProtocolError::StreamClosed { .. }) => ProtocolError::ResponseLost {
+                    peer_id: target_peer,
+                    transfer_id: transfer_id.to_string(),
+                }.into(),
+                _ => e,
+            })?;
+
+        Self::deliver_response(sender, transfer_id, response, response_tx).await;
+        Ok(())
+    }
+
+    /// Wait for the reply registered by `send_tracked`, routing swarm
+    /// events until it arrives. The lock is held only while events are
+    /// routed, never while waiting for one. A closed connection is
+    /// reported as `StreamClosed`.
+    async fn wait_for_reply(
+        sender: Arc<Mutex<&mut Self>>,
+        mut reply: oneshot::Receiver<Result<FileTransferResponse>>,
+    ) -> Result<FileTransferResponse> {
+        loop {
+            sender.lock().await.pump_events();
+            match timeout(EVENT_PUMP_INTERVAL, &mut reply).await {
+                Ok(Ok(result)) => return result,
+                Ok(Err(_)) => return Err(anyhow::anyhow!("Reply waiter was dropped")),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Hand a response to the caller and record the outcome it reports
+    async fn deliver_response(
+        sender: Arc<Mutex<&mut Self>>,
+        transfer_id: &str,
+        response: FileTransferResponse,
+        response_tx: mpsc::Sender<FileTransferResponse>,
+    ) {
+        let status = if response.success {
+            info!("Transfer {} completed successfully", transfer_id);
+            TransferStatus::Completed
+        } else {
+            let error = response.error_message.clone().unwrap_or_else(|| "Receiver reported a failure".to_string());
+            warn!("Receiver failed transfer {}: {}", transfer_id, error);
+            TransferStatus::Failed(error)
         };
 
         if let Err(e) = response_tx.send(response).await {
             warn!("Failed to send response for transfer {}: {}", transfer_id, e);
         }
 
-        // Update status to completed
-        Self::update_transfer_status(
+        Self::update_transfer_status(sender, transfer_id, status).await;
+    }
+
+    /// Reconnect and ask the receiver what became of a transfer whose
+    /// response was lost.
+    ///
+    /// Returns `true` if the receiver finished it, in which case the
+    /// outcome is delivered as if the response had arrived, and `false` if
+    /// it has no record of the transfer and it must be sent again. A
+    /// transfer the receiver is still processing is an error, so it is
+    /// asked again after the usual retry delay.
+    async fn recover_lost_response(
+        sender: Arc<Mutex<&mut Self>>,
+        request: &FileTransferRequest,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        response_tx: mpsc::Sender<FileTransferResponse>,
+    ) -> Result<bool> {
+        let transfer_id = &request.transfer_id;
+        let (status, response) = Self::request_status(
             sender.clone(),
+            target_peer,
+            target_addr,
             transfer_id,
-            TransferStatus::Completed
-        ).await;
+            request.auth_token.clone(),
+        ).await?;
 
-        info!("Transfer {} completed successfully", transfer_id);
-        Ok(())
+        match status.status {
+            TransferState::Succeeded | TransferState::Failed => {
+                info!("Receiver finished transfer {} before the response was lost", transfer_id);
+                Self::deliver_response(sender, transfer_id, response, response_tx).await;
+                Ok(true)
+            }
+            TransferState::InProgress => Err(anyhow::anyhow!(
+                "Receiver is still processing transfer {} ({} bytes received)",
+                transfer_id, status.bytes_received
+            )),
+            TransferState::NotFound => Ok(false),
+        }
+    }
+
+    /// Ask a receiver for its record of one of our transfers, e.g. to
+    /// check on one whose outcome we missed. An id the receiver doesn't
+    /// know comes back as `TransferState::NotFound`, not as an error.
+    pub async fn query_transfer_status(
+        &mut self,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        transfer_id: &str,
+    ) -> Result<StatusResponse> {
+        let auth_token = self.auth_token.clone();
+        let sender = Arc::new(Mutex::new(self));
+        let (status, _) = Self::request_status(sender, target_peer, target_addr, transfer_id, auth_token).await?;
+        Ok(status)
+    }
+
+    /// Connect if needed and send a status query for `transfer_id`
+    async fn request_status(
+        sender: Arc<Mutex<&mut Self>>,
+        target_peer: PeerId,
+        target_addr: Multiaddr,
+        transfer_id: &str,
+        auth_token: Option<String>,
+    ) -> Result<(StatusResponse, FileTransferResponse)> {
+        info!("Asking {} for the status of transfer {}", target_peer, transfer_id);
+
+        let connected = sender.lock().await.swarm.is_connected(&target_peer);
+        if !connected {
+            sender.lock().await.dial(target_peer, target_addr)?;
+            if !Self::wait_for_connection(sender.clone(), target_peer).await? {
+                return Err(anyhow::anyhow!("Failed to establish connection to peer"));
+            }
+        }
+
+        let query = FileTransferRequest::status_query(transfer_id.to_string(), auth_token);
+        let (_, reply) = sender.lock().await.send_tracked(&target_peer, query);
+        let response = Self::wait_for_reply(sender, reply).await?;
+
+        match response.status.clone() {
+            Some(status) => Ok((status, response)),
+            // Rejected before it was looked up, e.g. a bad auth token
+            None => Err(anyhow::anyhow!(
+                "Status query for transfer {} failed: {}",
+                transfer_id,
+                response.error_message.unwrap_or_else(|| "no status in response".to_string())
+            )),
+        }
     }
 
     /// Update transfer status
@@ -656,12 +2060,17 @@ impl FileSender {
         let mut active_sends = sender_lock.active_sends.write().await;
 
         if let Some(active_send) = active_sends.get_mut(transfer_id) {
-            active_send.progress.status = status;
-            if let TransferStatus::Failed(ref error) = active_send.progress.status {
-                active_send.progress.last_error = Some(error.clone());
-            }
+            active_send.set_status(status);
             sender_lock.notify_progress(&active_send.progress);
         }
+
+        // Starting or finishing a transfer moves the queue along
+        refresh_queue_estimates(&mut active_sends, sender_lock.max_concurrent_sends);
+        for active_send in active_sends.values() {
+            if matches!(active_send.progress.status, TransferStatus::Queued) {
+                sender_lock.notify_progress(&active_send.progress);
+            }
+        }
     }
 
     /// Notify progress callback
@@ -700,70 +2109,82 @@ impl FileSender {
 
     /// Wait for transfer completion
     pub async fn wait_for_completion(&self, transfer_id: &str) -> Result<SendResult> {
+        self.wait_for_completion_timeout(transfer_id, WAIT_FOREVER).await
+    }
+
+    /// Wait for transfer completion, giving up with `TimeoutError::Operation`
+    /// if the transfer has not finished within `max_wait`
+    pub async fn wait_for_completion_timeout(
+        &self,
+        transfer_id: &str,
+        max_wait: Duration,
+    ) -> Result<SendResult> {
+        match timeout(max_wait, self.wait_for_terminal_status(transfer_id)).await {
+            Ok(result) => result,
+            Err(_) => Err(TimeoutError::Operation {
+                operation: format!("wait for transfer {}", transfer_id),
+                duration: max_wait,
+            }.into()),
+        }
+    }
+
+    /// Wait until the transfer reaches a terminal status
+    async fn wait_for_terminal_status(&self, transfer_id: &str) -> Result<SendResult> {
         let start_time = Instant::now();
+        let not_found = || anyhow::anyhow!("Transfer not found: {}", transfer_id);
 
         loop {
-            let progress = self.get_progress(transfer_id).await
-                .ok_or_else(|| anyhow::anyhow!("Transfer not found: {}", transfer_id))?;
-
-            match &progress.status {
-                TransferStatus::Completed => {
-                    return Ok(SendResult {
-                        transfer_id: transfer_id.to_string(),
-                        success: true,
-                        bytes_sent: progress.sent_bytes,
-                        duration: start_time.elapsed(),
-                        response: None, // Would include actual response in real implementation
-                        error: None,
-                    });
-                }
-                TransferStatus::Failed(error) => {
-                    return Ok(SendResult {
-                        transfer_id: transfer_id.to_string(),
-                        success: false,
-                        bytes_sent: progress.sent_bytes,
-                        duration: start_time.elapsed(),
-                        response: None,
-                        error: Some(error.clone()),
-                    });
-                }
-                TransferStatus::Cancelled => {
-                    return Ok(SendResult {
-                        transfer_id: transfer_id.to_string(),
-                        success: false,
-                        bytes_sent: progress.sent_bytes,
-                        duration: start_time.elapsed(),
-                        response: None,
-                        error: Some("Transfer was cancelled".to_string()),
-                    });
-                }
+            let completed = self.active_sends.read().await
+                .get(transfer_id)
+                .map(|send| send.completed.clone())
+                .ok_or_else(not_found)?;
+
+            // Register before checking so a transition in between isn't missed;
+            // the notification fires when the transfer first becomes terminal,
+            // or when an aborted task discards its entry
+            let finished = completed.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+
+            let progress = self.get_progress(transfer_id).await.ok_or_else(not_found)?;
+            let error = match &progress.status {
+                TransferStatus::Completed => None,
+                TransferStatus::Failed(error) => Some(error.clone()),
+                TransferStatus::Cancelled => Some("Transfer was cancelled".to_string()),
                 _ => {
-                    // Still in progress, wait a bit
-                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    finished.await;
+                    continue;
                 }
-            }
+            };
+
+            return Ok(SendResult {
+                transfer_id: transfer_id.to_string(),
+                success: error.is_none(),
+                bytes_sent: progress.sent_bytes,
+                duration: start_time.elapsed(),
+                avg_bps: progress.throughput.avg_bps(),
+                peak_bps: progress.throughput.peak_bps(),
+                retransmitted_bytes: 0,
+                response: None, // Would include actual response in real implementation
+                error,
+            });
         }
     }
 
-    /// Clean up completed transfers
+    /// Forget finished transfers past the retention limits
     pub async fn cleanup_completed_transfers(&self) {
         let mut active_sends = self.active_sends.write().await;
-        let mut to_remove = Vec::new();
-
-        for (transfer_id, active_send) in active_sends.iter() {
-            match &active_send.progress.status {
-                TransferStatus::Completed | TransferStatus::Failed(_) | TransferStatus::Cancelled => {
-                    // Keep transfers for a while after completion for status checking
-                    if active_send.progress.start_time.elapsed() > Duration::from_secs(300) {
-                        to_remove.push(transfer_id.clone());
-                    }
-                }
-                _ => {}
-            }
-        }
 
+        // Keep transfers for a while after completion for status checking
+        let finished = active_sends.iter().filter_map(|(transfer_id, active_send)| {
+            active_send.progress.finished_at.map(|finished_at| (transfer_id.as_str(), finished_at))
+        });
+        let to_remove = self.completed_retention.expired(finished, Instant::now());
+
+        let mut transfer_tasks = self.transfer_tasks.lock().await;
         for transfer_id in to_remove {
             active_sends.remove(&transfer_id);
+            transfer_tasks.remove(&transfer_id);
             info!("Cleaned up completed transfer: {}", transfer_id);
         }
     }
@@ -784,17 +2205,38 @@ impl FileSender {
     /// Run the swarm event loop
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting file sender event loop");
+        let mut session_tick = interval(SESSION_TICK);
 
         loop {
-            match self.swarm.select_next_some().await {
+            let event = tokio::select! {
+                event = self.swarm.select_next_some() => event,
+                _ = session_tick.tick() => {
+                    self.redial_due_sessions().await;
+                    continue;
+                }
+            };
+
+            match event {
                 SwarmEvent::NewListenAddr { address, .. } => {
                     info!("File sender listening on: {}", address);
                 }
                 SwarmEvent::ConnectionEstablished { peer_id, .. } => {
                     debug!("Connection established with: {}", peer_id);
+                    self.session_connected(peer_id).await;
                 }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
                     debug!("Connection closed with {}: {:?}", peer_id, cause);
+                    if num_established == 0 {
+                        let reason = cause.map_or("closed".to_string(), |cause| cause.to_string());
+                        self.session_lost(peer_id, reason).await;
+                    }
+                }
+                SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
+                    debug!("Outgoing connection to {} failed: {}", peer_id, error);
+                    // A failed extra dial doesn't take down a live session
+                    if !self.swarm.is_connected(&peer_id) {
+                        self.session_lost(peer_id, error.to_string()).await;
+                    }
                 }
                 SwarmEvent::Behaviour(request_response::Event::ResponseReceived { 
                     peer, response, .. 
@@ -836,26 +2278,80 @@ impl FileSender {
         peer: PeerId, 
         error: request_response::OutboundFailure
     ) {
-        warn!("Request to peer {} failed: {:?}", peer, error);
+        let error = outbound_failure_error(peer, self.dialed_addr(&peer), &error);
+        let retryable = is_retryable(&error);
+        warn!("Request to peer {} failed: {} (retryable: {})", peer, error, retryable);
 
-        // Find transfers to this peer and mark them as failed
+        // Find transfers to this peer; retryable failures are left to the
+        // retry loop, the rest fail now
         let mut active_sends = self.active_sends.write().await;
-        let failed_transfers: Vec<String> = active_sends
+        let affected: Vec<String> = active_sends
             .iter()
             .filter(|(_, send)| send.progress.peer_id == peer)
             .map(|(id, _)| id.clone())
             .collect();
 
-        for transfer_id in failed_transfers {
+        for transfer_id in affected {
             if let Some(active_send) = active_sends.get_mut(&transfer_id) {
-                active_send.progress.status = TransferStatus::Failed(format!("{:?}", error));
-                active_send.progress.last_error = Some(format!("{:?}", error));
+                if retryable {
+                    active_send.progress.last_error = Some(error.to_string());
+                } else {
+                    active_send.set_status(TransferStatus::Failed(error.to_string()));
+                }
                 self.notify_progress(&active_send.progress);
             }
         }
     }
 }
 
+/// Map a request-response failure to the matching error type.
+///
+/// Dial failures, timeouts and closed connections may succeed on retry;
+/// a peer that speaks none of our protocols never will.
+fn outbound_failure_error(
+    peer: PeerId,
+    address: Multiaddr,
+    failure: &request_response::OutboundFailure,
+) -> anyhow::Error {
+    use request_response::OutboundFailure;
+
+    match failure {
+        OutboundFailure::DialFailure => NetworkError::ConnectionFailed {
+            peer_id: Some(peer),
+            address,
+            reason: "dial failed".to_string(),
+        }.into(),
+        OutboundFailure::Timeout => TimeoutError::Operation {
+            operation: format!("file transfer request to {}", peer),
+            duration: TRANSFER_TIMEOUT,
+        }.into(),
+        OutboundFailure::ConnectionClosed => ProtocolError::StreamClosed { peer_id: peer }.into(),
+        OutboundFailure::UnsupportedProtocols => ProtocolError::UnsupportedVersion {
+            version: format!("none supported by {}", peer),
+            supported: stream_protocols(&ProtocolVersion::ALL)
+                .iter()
+                .map(|protocol| protocol.to_string())
+                .collect(),
+        }.into(),
+        OutboundFailure::Io(e) => NetworkError::Transport {
+            message: format!("stream to {} failed: {}", peer, e),
+        }.into(),
+    }
+}
+
+/// Whether `error` means the file was sent but its response never came
+fn is_response_lost(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<ProtocolError>(), Some(ProtocolError::ResponseLost { .. }))
+}
+
+/// Whether another attempt could succeed after `error`
+fn is_retryable(error: &anyhow::Error) -> bool {
+    !matches!(
+        error.downcast_ref::<ProtocolError>(),
+        Some(ProtocolError::NegotiationFailed { .. } | ProtocolError::UnsupportedVersion { .. })
+    )
+}
+
 /// Progress tracking utilities
 pub mod progress {
     use super::*;
@@ -925,6 +2421,116 @@ pub mod progress {
     }
 }
 
+/// Open a file for sending, refusing files another process is writing.
+///
+/// A shared advisory lock is taken and held while the returned file is open
+/// (`flock` on Unix, `LockFileEx` on Windows); if another process holds an
+/// exclusive lock this fails with [`FileIOError::FileLocked`]. Advisory locks
+/// only see writers that also lock, so on every platform the file must also
+/// keep the same size and modification time over `stability_interval`.
+async fn open_for_send(path: &Path, stability_interval: Duration) -> Result<File> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+
+    match file.try_lock_shared() {
+        Ok(()) => {}
+        Err(std::fs::TryLockError::WouldBlock) => {
+            return Err(FileIOError::FileLocked { path: path.to_path_buf() }.into());
+        }
+        Err(std::fs::TryLockError::Error(e)) if e.kind() == std::io::ErrorKind::Unsupported => {
+            debug!("Advisory locks unsupported for {}", path.display());
+        }
+        Err(std::fs::TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock file: {}", path.display()));
+        }
+    }
+
+    // Writers that don't lock are only caught by watching the file
+    ensure_stable(&file, path, stability_interval).await?;
+
+    Ok(File::from_std(file))
+}
+
+/// Fail with `FileLocked` if the file's size or modification time changes
+/// over `interval`
+async fn ensure_stable(file: &std::fs::File, path: &Path, interval: Duration) -> Result<()> {
+    let snapshot = |file: &std::fs::File| -> Result<(u64, Option<std::time::SystemTime>)> {
+        let metadata = file.metadata()
+            .with_context(|| format!("Failed to read file metadata: {}", path.display()))?;
+        Ok((metadata.len(), metadata.modified().ok()))
+    };
+
+    let before = snapshot(file)?;
+    sleep(interval).await;
+    if snapshot(file)? != before {
+        return Err(FileIOError::FileLocked { path: path.to_path_buf() }.into());
+    }
+    Ok(())
+}
+
+/// Recompute start and completion estimates for queued transfers.
+///
+/// Each running transfer frees its slot once its remaining bytes are sent at
+/// its current speed. Queued transfers then take the earliest free slot by
+/// priority, then arrival order, and are assumed to run at the average
+/// current speed. No
+/// estimate is made until at least one running transfer has a measured speed.
+fn refresh_queue_estimates(active_sends: &mut HashMap<String, ActiveSend>, slots: usize) {
+    let now = Instant::now();
+    let running: Vec<&SendProgress> = active_sends
+        .values()
+        .map(|send| &send.progress)
+        .filter(|progress| matches!(
+            progress.status,
+            TransferStatus::Connecting | TransferStatus::Negotiating
+                | TransferStatus::Sending | TransferStatus::WaitingResponse
+        ))
+        .collect();
+
+    let speeds: Vec<f64> = running.iter().map(|p| p.speed_bps()).filter(|bps| *bps > 0.0).collect();
+    let avg_bps = (!speeds.is_empty()).then(|| speeds.iter().sum::<f64>() / speeds.len() as f64);
+
+    // Seconds from now until each slot becomes free
+    let mut slot_free_at: Vec<f64> = match avg_bps {
+        Some(bps) => running
+            .iter()
+            .map(|p| p.eta_seconds().unwrap_or_else(|| p.total_size.saturating_sub(p.sent_bytes) as f64 / bps))
+            .collect(),
+        None => Vec::new(),
+    };
+    if slot_free_at.len() < slots.max(1) {
+        slot_free_at.resize(slots.max(1), 0.0);
+    }
+
+    let mut queued: Vec<(std::cmp::Reverse<u8>, Instant, String)> = active_sends
+        .iter()
+        .filter(|(_, send)| matches!(send.progress.status, TransferStatus::Queued))
+        .map(|(id, send)| (std::cmp::Reverse(send.progress.priority), send.progress.start_time, id.clone()))
+        .collect();
+    queued.sort();
+
+    for (_, _, id) in queued {
+        let progress = &mut active_sends.get_mut(&id).unwrap().progress;
+        let Some(bps) = avg_bps else {
+            progress.estimated_start = None;
+            progress.estimated_completion = None;
+            continue;
+        };
+
+        let (slot, wait) = slot_free_at
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        let duration = progress.total_size as f64 / bps;
+        slot_free_at[slot] = wait + duration;
+
+        progress.estimated_start = Some(now + Duration::from_secs_f64(wait));
+        progress.estimated_completion = Some(now + Duration::from_secs_f64(wait + duration));
+    }
+}
+
 /// Example usage and integration
 pub mod examples {
     use super::*;
@@ -1033,12 +2639,164 @@ mod tests {
             max_delay: Duration::from_secs(5),
             backoff_multiplier: 2.0,
             connection_timeout: Duration::from_secs(5),
+            dial_concurrency: 5,
+            low_reputation_attempts: 1,
         };
 
         let sender = FileSender::new(Some(config)).await;
         assert!(sender.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_dial_settings_from_retry_config() {
+        let zero = RetryConfig { dial_concurrency: 0, ..RetryConfig::default() };
+        let err = FileSender::new(Some(zero)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ConfigurationError>(), Some(ConfigurationError::ValidationFailed { .. })));
+
+        // A peer that never answers fails the dial after the connection
+        // timeout rather than a fixed one
+        let config = RetryConfig { connection_timeout: Duration::from_millis(200), ..RetryConfig::default() };
+        let mut sender = FileSender::new(Some(config)).await.unwrap();
+        let started = Instant::now();
+        let err = FileSender::wait_for_connection(Arc::new(Mutex::new(&mut sender)), PeerId::random())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<TimeoutError>(), Some(TimeoutError::NetworkOperation { .. })));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(is_retryable(&err));
+    }
+
+    #[test]
+    fn test_reconnect_delay_backoff_and_jitter() {
+        let config = SessionConfig {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            backoff_multiplier: 2.0,
+            jitter: 0.5,
+        };
+
+        // Midpoint of the jitter window is the plain exponential delay
+        assert_eq!(reconnect_delay(&config, 1, 0.5), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(&config, 3, 0.5), Duration::from_secs(4));
+        assert_eq!(reconnect_delay(&config, 10, 0.5), Duration::from_secs(10));
+
+        assert_eq!(reconnect_delay(&config, 3, 0.0), Duration::from_secs(2));
+        assert!(reconnect_delay(&config, 3, 0.999) < Duration::from_secs(6));
+        // Jitter never pushes past the cap
+        assert!(reconnect_delay(&config, 10, 0.999) <= config.max_backoff);
+    }
+
+    #[test]
+    fn test_throughput_stats() {
+        let start = Instant::now();
+
+        // Nothing sent yet
+        assert_eq!(ThroughputStats::default().avg_bps(), 0.0);
+
+        // A single chunk sent instantly still has a finite rate
+        let mut instant = ThroughputStats::default();
+        instant.record(1000, start);
+        assert!((instant.avg_bps() - 1_000_000.0).abs() < 1.0);
+        assert_eq!(instant.peak_bps(), instant.avg_bps());
+
+        // 1MB over the first second, then 1MB over the next four
+        let mut stats = ThroughputStats::default();
+        stats.begin(start);
+        stats.record(512 * 1024, start + Duration::from_millis(500));
+        stats.record(512 * 1024, start + Duration::from_secs(1));
+        stats.record(1024 * 1024, start + Duration::from_secs(5));
+        assert_eq!(stats.avg_bps(), (2 * 1024 * 1024) as f64 / 5.0);
+        assert_eq!(stats.peak_bps(), (1024 * 1024) as f64);
+    }
+
+    #[test]
+    fn test_completed_retention() {
+        let now = Instant::now();
+        let ago = |secs| now - Duration::from_secs(secs);
+        let retention = CompletedRetention {
+            max_age: Duration::from_secs(300),
+            max_count: 2,
+        };
+
+        // Over the count cap the oldest go first; anything too old goes regardless
+        let finished = vec![("new", ago(1)), ("stale", ago(400)), ("older", ago(20)), ("oldest", ago(30))];
+        let mut expired = retention.expired(finished, now);
+        expired.sort();
+        assert_eq!(expired, vec!["oldest".to_string(), "stale".to_string()]);
+
+        // Within both limits nothing is dropped
+        assert!(CompletedRetention::default().expired(vec![("a", ago(1)), ("b", ago(2))], now).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_sizer_adapts_without_oscillating() {
+        let target = CHUNK_TARGET_TIME;
+        let mut sizer = ChunkSizer::adaptive(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE, DEFAULT_CHUNK_SIZE);
+
+        // A fast link grows the chunks step by step up to the maximum
+        let mut previous = sizer.chunk_size();
+        for _ in 0..20 {
+            let size = sizer.chunk_size();
+            sizer.record(size, target / 10);
+            assert!(sizer.chunk_size() >= previous);
+            previous = sizer.chunk_size();
+        }
+        assert_eq!(sizer.chunk_size(), MAX_CHUNK_SIZE);
+
+        // A slow link shrinks them down to the minimum
+        for _ in 0..40 {
+            let size = sizer.chunk_size();
+            sizer.record(size, target * 10);
+        }
+        assert_eq!(sizer.chunk_size(), MIN_CHUNK_SIZE);
+
+        // Noisy but steady throughput around the target leaves the size alone
+        let mut steady = ChunkSizer::adaptive(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE, DEFAULT_CHUNK_SIZE);
+        for i in 0..50 {
+            let jitter = if i % 2 == 0 { 0.6 } else { 1.6 };
+            steady.record(DEFAULT_CHUNK_SIZE, target.mul_f64(jitter));
+            assert_eq!(steady.chunk_size(), DEFAULT_CHUNK_SIZE);
+        }
+
+        // Disabled adaptation never changes the size
+        let mut fixed = ChunkSizer::fixed(MAX_CHUNK_SIZE);
+        fixed.record(MAX_CHUNK_SIZE, Duration::from_millis(1));
+        fixed.record(MAX_CHUNK_SIZE, Duration::from_secs(60));
+        assert!(!fixed.is_adaptive());
+        assert_eq!(fixed.chunk_size(), MAX_CHUNK_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_session_state_transitions() {
+        let mut sender = FileSender::new(None).await.unwrap();
+        let peer = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/1".parse().unwrap();
+        sender.open_session(peer, address, SessionConfig::default()).await.unwrap();
+
+        sender.session_connected(peer).await;
+        let status = sender.session_status(&peer).await.unwrap();
+        assert!(status.state.is_connected());
+        assert_eq!(status.reconnects, 0);
+
+        sender.session_lost(peer, "connection reset".to_string()).await;
+        sender.session_lost(peer, "connection refused".to_string()).await;
+        match sender.session_status(&peer).await.unwrap().state {
+            SessionState::Reconnecting { attempt, last_error, .. } => {
+                assert_eq!(attempt, 2);
+                assert_eq!(last_error.as_deref(), Some("connection refused"));
+            }
+            other => panic!("expected Reconnecting, got {:?}", other),
+        }
+
+        sender.session_connected(peer).await;
+        let status = sender.session_status(&peer).await.unwrap();
+        assert!(status.state.is_connected());
+        assert_eq!(status.reconnects, 1);
+
+        assert!(sender.close_session(&peer).await);
+        assert!(sender.session_status(&peer).await.is_none());
+    }
+
     #[tokio::test]
     async fn test_progress_calculation() {
         let progress = SendProgress {
@@ -1053,6 +2811,11 @@ mod tests {
             status: TransferStatus::Sending,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: DEFAULT_PRIORITY,
+            throughput: Default::default(),
+            finished_at: None,
         };
 
         assert_eq!(progress.percentage(), 25.0);
@@ -1074,6 +2837,11 @@ mod tests {
             status: TransferStatus::Connecting,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: DEFAULT_PRIORITY,
+            throughput: Default::default(),
+            finished_at: None,
         };
 
         assert!(progress.status_string().contains("Connecting"));
@@ -1085,4 +2853,289 @@ mod tests {
         progress.status = TransferStatus::Completed;
         assert_eq!(progress.status_string(), "Completed successfully");
     }
+
+    #[tokio::test]
+    async fn test_queue_estimates() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let now = Instant::now();
+        let make_send = |status: TransferStatus, total_size: u64, sent_bytes: u64, start_time: Instant| {
+            let file = File::from_std(temp_file.reopen().unwrap());
+            ActiveSend {
+                progress: SendProgress {
+                    transfer_id: "test".to_string(),
+                    file_path: PathBuf::from("test.txt"),
+                    peer_id: PeerId::random(),
+                    total_size,
+                    sent_bytes,
+                    chunks_sent: 0,
+                    total_chunks: 1,
+                    start_time,
+                    status,
+                    connection_attempts: 0,
+                    last_error: None,
+                    estimated_start: None,
+                    estimated_completion: None,
+                    priority: DEFAULT_PRIORITY,
+                    throughput: Default::default(),
+                    finished_at: None,
+                },
+                file,
+                request_id: None,
+                response_receiver: None,
+                cancel_sender: None,
+                completed: Arc::new(Notify::new()),
+            }
+        };
+
+        let mut active_sends = HashMap::new();
+        // Running at ~1000 B/s with 1000 bytes left
+        active_sends.insert("running".to_string(), make_send(TransferStatus::Sending, 11_000, 10_000, now - Duration::from_secs(10)));
+        active_sends.insert("first".to_string(), make_send(TransferStatus::Queued, 2000, 0, now));
+        active_sends.insert("second".to_string(), make_send(TransferStatus::Queued, 2000, 0, now + Duration::from_millis(1)));
+
+        refresh_queue_estimates(&mut active_sends, 1);
+
+        let first = &active_sends["first"].progress;
+        let second = &active_sends["second"].progress;
+        let first_wait = first.estimated_start.unwrap().saturating_duration_since(now).as_secs_f64();
+        let second_wait = second.estimated_start.unwrap().saturating_duration_since(now).as_secs_f64();
+        assert!((first_wait - 1.0).abs() < 0.2);
+        assert!((second_wait - 3.0).abs() < 0.2);
+
+        // ETA for a queued transfer covers the wait plus its own transfer time
+        assert!((second.eta_seconds().unwrap() - 5.0).abs() < 0.2);
+        assert!(second.status_string().starts_with("Queued"));
+
+        // Once the running transfer finishes, the queue moves up
+        active_sends.get_mut("running").unwrap().progress.status = TransferStatus::Completed;
+        active_sends.get_mut("first").unwrap().progress.status = TransferStatus::Sending;
+        active_sends.get_mut("first").unwrap().progress.sent_bytes = 1000;
+        active_sends.get_mut("first").unwrap().progress.start_time = now - Duration::from_secs(1);
+        refresh_queue_estimates(&mut active_sends, 1);
+        let second_wait = active_sends["second"].progress.estimated_start.unwrap()
+            .saturating_duration_since(now).as_secs_f64();
+        assert!(second_wait < 1.5);
+    }
+
+    fn stuck_send(temp_file: &NamedTempFile) -> ActiveSend {
+        ActiveSend {
+            progress: SendProgress {
+                transfer_id: "stuck".to_string(),
+                file_path: PathBuf::from("test.txt"),
+                peer_id: PeerId::random(),
+                total_size: 100,
+                sent_bytes: 10,
+                chunks_sent: 1,
+                total_chunks: 10,
+                start_time: Instant::now(),
+                status: TransferStatus::Sending,
+                connection_attempts: 1,
+                last_error: None,
+                estimated_start: None,
+                estimated_completion: None,
+                priority: DEFAULT_PRIORITY,
+                throughput: Default::default(),
+                finished_at: None,
+            },
+            file: SendSource::File(File::from_std(temp_file.reopen().unwrap())),
+            request_id: None,
+            response_receiver: None,
+            cancel_sender: None,
+            completed: Arc::new(Notify::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_for_send_refuses_files_being_written() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), b"complete").unwrap();
+
+        // An unlocked, unchanging file opens normally
+        assert!(open_for_send(temp_file.path(), Duration::from_millis(10)).await.is_ok());
+        assert!(ensure_stable(temp_file.as_file(), temp_file.path(), Duration::from_millis(10)).await.is_ok());
+
+        // A writer holding an exclusive lock is detected where locks are supported
+        let writer = std::fs::File::options().append(true).open(temp_file.path()).unwrap();
+        if writer.try_lock().is_ok() {
+            let err = open_for_send(temp_file.path(), Duration::from_millis(10)).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<FileIOError>(), Some(FileIOError::FileLocked { .. })));
+            writer.unlock().unwrap();
+        }
+
+        // The stability check catches writers that don't lock, even where
+        // locking is supported
+        let path = temp_file.path().to_path_buf();
+        let grow = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            std::io::Write::write_all(&mut &writer, b" more").unwrap();
+        });
+        let err = open_for_send(&path, Duration::from_millis(100)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<FileIOError>(), Some(FileIOError::FileLocked { .. })));
+        grow.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_bytes_source() {
+        let mut sender = FileSender::new(None).await.unwrap();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        assert!(sender.send_bytes(PeerId::random(), addr, b"data".to_vec(), " ", None, None).await.is_err());
+
+        // Buffers are read chunk by chunk like files
+        let mut source = SendSource::Bytes(std::io::Cursor::new(vec![7u8; MAX_CHUNK_SIZE + 10]));
+        let mut buffer = vec![0u8; MAX_CHUNK_SIZE];
+        assert_eq!(read_full(&mut source, &mut buffer).await.unwrap(), MAX_CHUNK_SIZE);
+        assert_eq!(read_full(&mut source, &mut buffer).await.unwrap(), 10);
+        assert_eq!(read_full(&mut source, &mut buffer).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_outbound_failures_choose_retry_strategy() {
+        use request_response::OutboundFailure;
+
+        let peer = PeerId::random();
+        let classify = |failure| outbound_failure_error(peer, Multiaddr::empty(), &failure);
+
+        let timeout = classify(OutboundFailure::Timeout);
+        assert!(matches!(timeout.downcast_ref::<TimeoutError>(), Some(TimeoutError::Operation { .. })));
+        assert!(is_retryable(&timeout));
+
+        let dial = classify(OutboundFailure::DialFailure);
+        assert!(matches!(dial.downcast_ref::<NetworkError>(), Some(NetworkError::ConnectionFailed { .. })));
+        assert!(is_retryable(&dial));
+
+        let closed = classify(OutboundFailure::ConnectionClosed);
+        assert!(matches!(closed.downcast_ref::<ProtocolError>(), Some(ProtocolError::StreamClosed { .. })));
+        assert!(is_retryable(&closed));
+
+        // A peer without our protocol fails fast
+        let unsupported = classify(OutboundFailure::UnsupportedProtocols);
+        assert!(matches!(
+            unsupported.downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::UnsupportedVersion { .. })
+        ));
+        assert!(!is_retryable(&unsupported));
+    }
+
+    #[tokio::test]
+    async fn test_dial_failures_report_dialed_address() {
+        let mut sender = FileSender::new(None).await.unwrap();
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        assert_eq!(sender.dialed_addr(&peer), Multiaddr::empty());
+
+        sender.dial(peer, addr.clone()).unwrap();
+        let error = outbound_failure_error(peer, sender.dialed_addr(&peer), &request_response::OutboundFailure::DialFailure);
+        match error.downcast_ref::<NetworkError>() {
+            Some(NetworkError::ConnectionFailed { address, .. }) => assert_eq!(*address, addr),
+            other => panic!("expected ConnectionFailed, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replies_routed_per_request() {
+        let mut sender = FileSender::new(None).await.unwrap();
+        let (lost_peer, other_peer) = (PeerId::random(), PeerId::random());
+        let (_, mut lost) = sender.send_tracked(&lost_peer, FileTransferRequest::status_query("a".to_string(), None));
+        let (_, mut other) = sender.send_tracked(&other_peer, FileTransferRequest::status_query("b".to_string(), None));
+        assert_eq!(sender.pending_replies.len(), 2);
+
+        // Closing one peer's last connection fails only the requests sent to it
+        sender.route_event(SwarmEvent::ConnectionClosed {
+            peer_id: lost_peer,
+            connection_id: libp2p::swarm::ConnectionId::new_unchecked(1),
+            endpoint: ConnectedPoint::Dialer {
+                address: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+                role_override: libp2p::core::Endpoint::Dialer,
+                port_use: libp2p::core::transport::PortUse::Reuse,
+            },
+            num_established: 0,
+            cause: None,
+        });
+        let err = lost.try_recv().unwrap().unwrap_err();
+        assert!(matches!(err.downcast_ref::<ProtocolError>(), Some(ProtocolError::StreamClosed { .. })));
+        assert!(other.try_recv().is_err());
+        assert_eq!(sender.pending_replies.len(), 1);
+
+        // Waiting doesn't hold the lock, so the sender stays usable meanwhile
+        let sender = Arc::new(Mutex::new(&mut sender));
+        let waiting = FileSender::wait_for_reply(sender.clone(), other);
+        pin_mut!(waiting);
+        assert!(timeout(Duration::from_millis(50), &mut waiting).await.is_err());
+        assert!(timeout(Duration::from_millis(50), sender.lock()).await.is_ok());
+    }
+
+    #[test]
+    fn test_lost_response_is_recovered_not_resent() {
+        let peer = PeerId::random();
+        let lost: anyhow::Error = ProtocolError::ResponseLost {
+            peer_id: peer,
+            transfer_id: "t1".to_string(),
+        }.into();
+        assert!(is_response_lost(&lost));
+        assert!(is_retryable(&lost));
+
+        // A connection closed before the file was sent is a plain retry
+        let closed: anyhow::Error = ProtocolError::StreamClosed { peer_id: peer }.into();
+        assert!(!is_response_lost(&closed));
+
+        let query = FileTransferRequest::status_query("t1".to_string(), Some("secret".to_string()));
+        assert_eq!(query.kind, RequestKind::GetTransferStatus);
+        assert_eq!(query.chunk_count, 0);
+        assert_eq!(query.auth_token.as_deref(), Some("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_completion_timeout() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let sender = FileSender::new(None).await.unwrap();
+        sender.active_sends.write().await.insert("stuck".to_string(), stuck_send(&temp_file));
+
+        let err = sender
+            .wait_for_completion_timeout("stuck", Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(TimeoutError::Operation { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_terminal_status_wakes_waiters_once() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let sender = FileSender::new(None).await.unwrap();
+
+        for status in [
+            TransferStatus::Completed,
+            TransferStatus::Failed("peer went away".to_string()),
+            TransferStatus::Cancelled,
+        ] {
+            let send = stuck_send(&temp_file);
+            let completed = send.completed.clone();
+            sender.active_sends.write().await.insert("stuck".to_string(), send);
+
+            let sender = &sender;
+            let waiters = futures::future::join(
+                sender.wait_for_completion_timeout("stuck", Duration::from_secs(5)),
+                sender.wait_for_completion_timeout("stuck", Duration::from_secs(5)),
+            );
+            let finish = async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let mut active_sends = sender.active_sends.write().await;
+                let send = active_sends.get_mut("stuck").unwrap();
+                send.set_status(TransferStatus::Sending);
+                send.set_status(status.clone());
+                // Further updates after finishing do not notify again
+                send.set_status(status.clone());
+            };
+            let ((first, second), ()) = futures::future::join(waiters, finish).await;
+            let (first, second) = (first.unwrap(), second.unwrap());
+            assert_eq!(first.success, matches!(status, TransferStatus::Completed));
+            assert_eq!(first.error, second.error);
+
+            // No stored permit is left behind for later waiters
+            let late = completed.notified();
+            assert!(timeout(Duration::from_millis(20), late).await.is_err());
+        }
+    }
 }
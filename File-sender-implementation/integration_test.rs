@@ -21,6 +21,9 @@ mod integration_tests {
             max_delay: Duration::from_secs(5),
             backoff_multiplier: 1.5,
             connection_timeout: Duration::from_secs(10),
+            dial_concurrency: 5,
+            low_reputation_attempts: 2,
+            recover_lost_responses: true,
         };
 
         let sender_with_config = FileSender::new(Some(custom_config)).await;
@@ -45,6 +48,10 @@ mod integration_tests {
             status: TransferStatus::Sending,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: file_sender::DEFAULT_PRIORITY,
+            finished_at: None,
         };
 
         // Test percentage calculation
@@ -79,6 +86,10 @@ mod integration_tests {
             status: TransferStatus::Connecting,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: file_sender::DEFAULT_PRIORITY,
+            finished_at: None,
         };
 
         // Test status string representations
@@ -165,6 +176,10 @@ mod integration_tests {
             status: TransferStatus::Sending,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: file_sender::DEFAULT_PRIORITY,
+            finished_at: None,
         };
 
         // Test progress formatting
@@ -192,6 +207,9 @@ mod integration_tests {
             max_delay: Duration::from_secs(2),
             backoff_multiplier: 2.0,
             connection_timeout: Duration::from_secs(5),
+            dial_concurrency: 5,
+            low_reputation_attempts: 2,
+            recover_lost_responses: true,
         };
 
         // Test backoff calculation simulation
@@ -19,6 +19,9 @@ pub async fn example_simple_send() -> Result<()> {
         max_delay: Duration::from_secs(10),
         backoff_multiplier: 1.5,
         connection_timeout: Duration::from_secs(15),
+        dial_concurrency: 5,
+        low_reputation_attempts: 2,
+        recover_lost_responses: true,
     };
 
     let mut sender = FileSender::new(Some(retry_config)).await?;
@@ -163,6 +166,9 @@ pub async fn example_resilient_send() -> Result<()> {
         max_delay: Duration::from_secs(60),
         backoff_multiplier: 2.0,
         connection_timeout: Duration::from_secs(5),
+        dial_concurrency: 5,
+        low_reputation_attempts: 2,
+        recover_lost_responses: true,
     };
 
     let mut sender = FileSender::new(Some(retry_config)).await?;
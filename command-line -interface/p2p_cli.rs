@@ -1,10 +1,20 @@
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use ipnet::IpNet;
 use libp2p::Multiaddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use tracing::{debug, error, info};
 
+use crate::error_handling::ConfigurationError;
+
+/// Environment variable used for `--listen` when the flag is absent
+pub const ENV_LISTEN: &str = "P2P_LISTEN";
+/// Environment variable used for `--target` when the flag is absent
+pub const ENV_PEER: &str = "P2P_PEER";
+/// Environment variable used for `--output` when the flag is absent
+pub const ENV_OUTPUT_DIR: &str = "P2P_OUTPUT_DIR";
+
 /// CLI arguments for P2P file converter
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -95,6 +105,293 @@ pub struct CliArgs {
         help = "Maximum file size to accept in megabytes"
     )]
     pub max_file_size_mb: u64,
+
+    /// Write a JSON shutdown report to this path on exit
+    #[arg(
+        long = "shutdown-report",
+        value_name = "FILE",
+        help = "Write a JSON summary of the session to FILE on exit"
+    )]
+    pub shutdown_report: Option<PathBuf>,
+
+    /// Transfer history log location
+    #[arg(
+        long = "history-file",
+        value_name = "FILE",
+        help = "JSONL transfer history log (default: <OUTPUT_DIR>/transfer_history.jsonl)"
+    )]
+    pub history_file: Option<PathBuf>,
+
+    /// Per-transfer debug logs
+    #[arg(
+        long = "transfer-logs",
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = "logs",
+        help = "Write each incoming transfer's log lines to DIR/<transfer_id>.log (default DIR: logs)"
+    )]
+    pub transfer_logs: Option<PathBuf>,
+
+    /// Keep per-transfer logs of successful transfers
+    #[arg(
+        long = "keep-transfer-logs",
+        requires = "transfer_logs",
+        help = "Keep per-transfer logs for successful transfers too, not just failed ones"
+    )]
+    pub keep_transfer_logs: bool,
+
+    /// Spool directory for resuming incoming transfers
+    #[arg(
+        long = "resume-dir",
+        value_name = "DIR",
+        help = "Keep chunks of incoming transfers in DIR so senders can resume them after the receiver restarts"
+    )]
+    pub resume_dir: Option<PathBuf>,
+
+    /// Age at which spooled transfers are given up on
+    #[arg(
+        long = "resume-max-age",
+        value_name = "SECONDS",
+        default_value_t = 86400,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "resume_dir",
+        help = "Delete spooled transfers untouched for SECONDS at startup instead of resuming them"
+    )]
+    pub resume_max_age_secs: u64,
+
+    /// Disable transport encryption (trusted networks only)
+    #[arg(
+        long = "insecure-plaintext",
+        help = "Use unencrypted plaintext transport instead of Noise; both peers must agree"
+    )]
+    pub insecure_plaintext: bool,
+
+    /// Fsync received files before acknowledging them
+    #[arg(
+        long = "fsync",
+        help = "Flush received files to disk before reporting success (slower, survives power loss)"
+    )]
+    pub fsync: bool,
+
+    /// Permission bits for saved files
+    #[arg(
+        long = "file-mode",
+        value_name = "OCTAL",
+        default_value = "644",
+        value_parser = validators::parse_file_mode,
+        help = "Permission bits for received and converted files, in octal (Unix only)"
+    )]
+    pub file_mode: u32,
+
+    /// Owner for saved files
+    #[arg(
+        long = "file-uid",
+        value_name = "UID",
+        help = "Give received and converted files to this user ID (Unix only; usually needs root)"
+    )]
+    pub file_uid: Option<u32>,
+
+    /// Group for saved files
+    #[arg(
+        long = "file-gid",
+        value_name = "GID",
+        help = "Give received and converted files to this group ID (Unix only)"
+    )]
+    pub file_gid: Option<u32>,
+
+    /// Cap on chunk data buffered across all transfers (in MB)
+    #[arg(
+        long = "memory-budget",
+        value_name = "SIZE_MB",
+        default_value_t = 256,
+        help = "Maximum chunk data held in memory across all transfers, in megabytes"
+    )]
+    pub memory_budget_mb: u64,
+
+    /// Free system memory needed before a conversion starts (in MB)
+    #[arg(
+        long = "min-free-memory",
+        value_name = "SIZE_MB",
+        default_value_t = 0,
+        help = "Defer conversions while free system memory is below this many megabytes (0 disables)"
+    )]
+    pub min_free_memory_mb: u64,
+
+    /// Keep only converted output
+    #[arg(
+        long = "replace-original",
+        help = "Save only the converted file; the original is saved only if conversion fails"
+    )]
+    pub replace_original: bool,
+
+    /// Report failed conversions as failed transfers
+    #[arg(
+        long = "require-conversion",
+        help = "Fail the transfer when its conversion fails. \
+                By default a failed conversion is a warning and the transfer succeeds with the original saved"
+    )]
+    pub require_conversion: bool,
+
+    /// Delete the received file when its conversion fails
+    #[arg(
+        long = "discard-failed-originals",
+        requires = "require_conversion",
+        help = "Don't keep the received file when its conversion fails"
+    )]
+    pub discard_failed_originals: bool,
+
+    /// Node-wide upload cap
+    #[arg(
+        long = "max-upload-rate",
+        value_name = "KB_PER_SEC",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Limit outgoing transfer data across all peers to this many KB/s"
+    )]
+    pub max_upload_kbps: Option<u64>,
+
+    /// Node-wide download cap
+    #[arg(
+        long = "max-download-rate",
+        value_name = "KB_PER_SEC",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Limit incoming transfer data across all peers to this many KB/s"
+    )]
+    pub max_download_kbps: Option<u64>,
+
+    /// Per-peer cap
+    #[arg(
+        long = "max-peer-rate",
+        value_name = "KB_PER_SEC",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Limit transfer data to or from any one peer to this many KB/s; the tighter of this and the node-wide cap applies"
+    )]
+    pub max_peer_kbps: Option<u64>,
+
+    /// Shared secret for transfers
+    #[arg(
+        long = "auth-token",
+        value_name = "TOKEN",
+        help = "Shared secret: receivers reject transfers without it, senders include it in requests"
+    )]
+    pub auth_token: Option<String>,
+
+    /// Networks allowed to connect to the receiver
+    #[arg(
+        long = "allow-source",
+        value_name = "CIDR",
+        help = "Only accept connections from this IPv4 or IPv6 network (repeatable; loopback is always allowed unless --deny-loopback)"
+    )]
+    pub allowed_sources: Vec<IpNet>,
+
+    /// Apply the source allowlist to loopback connections too
+    #[arg(
+        long = "deny-loopback",
+        requires = "allowed_sources",
+        help = "Refuse loopback connections not covered by --allow-source"
+    )]
+    pub deny_loopback: bool,
+
+    /// Conversion for received files whose sender named no target
+    #[arg(
+        long = "default-target",
+        value_name = "FORMAT",
+        help = "Convert received files to FORMAT when the sender doesn't ask for a format (senders can still pass 'none')"
+    )]
+    pub default_target_format: Option<String>,
+
+    /// Directory for cached conversion outputs
+    #[arg(
+        long = "conversion-cache",
+        value_name = "DIR",
+        help = "Cache converted outputs in DIR and reuse them for identical inputs"
+    )]
+    pub conversion_cache: Option<PathBuf>,
+
+    /// PDF layout used for conversions
+    #[arg(
+        long = "pdf-config",
+        value_name = "FILE",
+        help = "Read PDF layout (title, font_size, margins, line_spacing, page_size, font_family) from a TOML file"
+    )]
+    pub pdf_config: Option<PathBuf>,
+
+    /// Unix socket serving the local control API
+    #[arg(
+        long = "control-socket",
+        value_name = "PATH",
+        help = "Serve the control API (status, stats, cancel) on a Unix socket at PATH, e.g. /run/p2p-converter.sock"
+    )]
+    pub control_socket: Option<PathBuf>,
+
+    /// Scheduling priority of the file being sent
+    #[arg(
+        long = "priority",
+        value_name = "0-9",
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u8).range(0..=9),
+        help = "Transfer priority from 0 (bulk) to 9 (urgent); higher priorities are queued first"
+    )]
+    pub priority: u8,
+
+    /// Ask before accepting each incoming transfer
+    #[arg(
+        long = "accept-prompt",
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Prompt to accept or reject each incoming transfer, rejecting it after SECONDS without an answer"
+    )]
+    pub accept_prompt: Option<u64>,
+
+    /// Relay converted files on to another peer
+    #[arg(
+        long = "forward-to",
+        value_name = "MULTIADDR",
+        help = "Send each converted file on to this peer (address must end in /p2p/<peer id>); a failed forward fails the incoming transfer"
+    )]
+    pub forward_to: Option<ValidatedMultiaddr>,
+
+    /// Listen again after every listener closes
+    #[arg(
+        long = "relisten",
+        help = "If the node stops listening (e.g. the network interface went down), keep trying to listen again with backoff"
+    )]
+    pub relisten: bool,
+
+    /// Age at which converted outputs are deleted
+    #[arg(
+        long = "converted-ttl",
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Delete converted files SECONDS after they were converted (originals are kept)"
+    )]
+    pub converted_ttl_secs: Option<u64>,
+
+    /// Cap on transfers a single peer can have running
+    #[arg(
+        long = "max-transfers-per-peer",
+        value_name = "N",
+        value_parser = clap::value_parser!(usize).range(1..),
+        help = "Receive at most N transfers at once from any one peer, so one peer can't take every slot"
+    )]
+    pub max_transfers_per_peer: Option<usize>,
+
+    /// How long finished transfers stay listed
+    #[arg(
+        long = "completed-retention",
+        value_name = "SECONDS",
+        default_value_t = 300,
+        help = "Forget finished transfers SECONDS after they finish"
+    )]
+    pub completed_retention_secs: u64,
+
+    /// Cap on finished transfers kept in memory
+    #[arg(
+        long = "max-completed",
+        value_name = "N",
+        default_value_t = 1000,
+        help = "Keep at most N finished transfers, forgetting the oldest first"
+    )]
+    pub max_completed_transfers: usize,
 }
 
 /// Log level enumeration
@@ -266,11 +563,57 @@ pub enum AppMode {
 impl CliArgs {
     /// Parse CLI arguments and determine application mode
     pub fn parse_args() -> Result<(Self, AppMode)> {
-        let args = Self::parse();
+        let matches = Self::command().get_matches();
+        let mut args = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        args.apply_env_overrides(
+            |id| explicitly_given(&matches, id),
+            |key| std::env::var(key).ok(),
+        )?;
         let mode = args.determine_mode()?;
         Ok((args, mode))
     }
 
+    /// Fill in `--listen`, `--target` and `--output` from `P2P_LISTEN`,
+    /// `P2P_PEER` and `P2P_OUTPUT_DIR` when the flag was not given.
+    ///
+    /// Flags on the command line always win. Values loaded from a config file
+    /// must be applied before this so the environment can override them.
+    /// Empty variables are treated as unset.
+    pub fn apply_env_overrides(
+        &mut self,
+        given_on_cli: impl Fn(&str) -> bool,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> std::result::Result<(), ConfigurationError> {
+        let lookup = |id: &str, key: &str| {
+            if given_on_cli(id) {
+                return None;
+            }
+            env(key).filter(|value| !value.trim().is_empty())
+        };
+
+        if let Some(value) = lookup("listen_address", ENV_LISTEN) {
+            self.listen_address = validators::validate_multiaddr(value.trim())
+                .map_err(|reason| env_error(ENV_LISTEN, &value, reason))?;
+            debug!("Listen address taken from {}", ENV_LISTEN);
+        }
+
+        if let Some(value) = lookup("target_peer", ENV_PEER) {
+            self.target_peer = Some(
+                validators::validate_multiaddr(value.trim())
+                    .map_err(|reason| env_error(ENV_PEER, &value, reason))?,
+            );
+            debug!("Target peer taken from {}", ENV_PEER);
+        }
+
+        if let Some(value) = lookup("output_dir", ENV_OUTPUT_DIR) {
+            self.output_dir = validators::validate_output_dir(value.trim())
+                .map_err(|reason| env_error(ENV_OUTPUT_DIR, &value, reason))?;
+            debug!("Output directory taken from {}", ENV_OUTPUT_DIR);
+        }
+
+        Ok(())
+    }
+
     /// Determine application mode from parsed arguments
     pub fn determine_mode(&self) -> Result<AppMode> {
         match (&self.target_peer, &self.file_path) {
@@ -322,23 +665,47 @@ impl CliArgs {
 
     /// Initialize logging based on CLI arguments
     pub fn setup_logging(&self) -> Result<()> {
+        self.setup_logging_with_writer(std::io::stdout)
+    }
+
+    /// Initialize logging, writing log lines through `writer`; used to keep
+    /// logs from breaking up progress bars
+    pub fn setup_logging_with_writer<W>(&self, writer: W) -> Result<()>
+    where
+        W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+    {
         let level = if self.verbose {
             "debug"
         } else {
             self.log_level.as_str()
         };
 
-        tracing_subscriber::fmt()
-            .with_env_filter(
+        use tracing_subscriber::prelude::*;
+
+        // The transfer log layer is left out entirely unless asked for
+        let transfer_logs = self.transfer_logs.as_ref()
+            .map(|dir| {
+                crate::p2p_stream_handler::TransferLogLayer::new(dir)
+                    .with_context(|| format!("Failed to create transfer log directory: {}", dir.display()))
+            })
+            .transpose()?;
+
+        tracing_subscriber::registry()
+            .with(
                 tracing_subscriber::EnvFilter::try_from_default_env()
                     .unwrap_or_else(|_| {
                         tracing_subscriber::EnvFilter::new(format!("{}={},libp2p=info", 
                             env!("CARGO_PKG_NAME").replace('-', "_"), level))
                     })
             )
-            .with_target(false)
-            .with_thread_ids(true)
-            .with_level(true)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_target(false)
+                    .with_thread_ids(true)
+                    .with_level(true)
+                    .with_writer(writer)
+            )
+            .with(transfer_logs)
             .init();
 
         Ok(())
@@ -373,6 +740,42 @@ impl CliArgs {
             ));
         }
 
+        if let Some(ref format) = self.default_target_format {
+            if !["pdf", "txt"].contains(&format.to_lowercase().as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Unsupported default target format '{}' (expected pdf or txt)",
+                    format
+                ));
+            }
+        }
+
+        if let Some(ref forward_to) = self.forward_to {
+            if self.target_peer.is_some() {
+                return Err(anyhow::anyhow!("--forward-to only applies to receivers"));
+            }
+            if !forward_to.iter().any(|p| matches!(p, libp2p::multiaddr::Protocol::P2p(_))) {
+                return Err(anyhow::anyhow!(
+                    "Forward address must include the peer ID (/p2p/...): {}",
+                    forward_to.0
+                ));
+            }
+        }
+
+        if self.memory_budget_mb == 0 {
+            return Err(anyhow::anyhow!(
+                "Memory budget must be greater than 0 MB"
+            ));
+        }
+
+        // Received files are buffered whole, so the largest one must fit
+        if self.memory_budget_mb < self.max_file_size_mb {
+            return Err(anyhow::anyhow!(
+                "Memory budget ({} MB) is smaller than the maximum file size ({} MB)",
+                self.memory_budget_mb,
+                self.max_file_size_mb
+            ));
+        }
+
         Ok(())
     }
 
@@ -407,6 +810,18 @@ impl CliArgs {
     }
 }
 
+/// Whether `id` was set on the command line rather than by its default
+fn explicitly_given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+fn env_error(key: &str, value: &str, reason: String) -> ConfigurationError {
+    ConfigurationError::ValidationFailed {
+        section: key.to_string(),
+        reason: format!("invalid value '{}': {}", value, reason),
+    }
+}
+
 /// Custom validation functions for use with clap value_parser
 pub mod validators {
     use super::*;
@@ -447,6 +862,16 @@ pub mod validators {
 
         Ok(path)
     }
+
+    /// Parse octal permission bits such as `644` or `0o640`. Setuid, setgid
+    /// and sticky bits are refused: received files are data, never programs.
+    pub fn parse_file_mode(mode: &str) -> std::result::Result<u32, String> {
+        let digits = mode.trim_start_matches("0o");
+        match u32::from_str_radix(digits, 8) {
+            Ok(bits) if bits <= 0o777 => Ok(bits),
+            _ => Err(format!("Invalid file mode '{}': expected octal bits from 000 to 777, such as 644", mode)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -479,6 +904,16 @@ mod tests {
         assert_eq!(LogLevel::Info.as_str(), "info");
     }
 
+    #[test]
+    fn test_parse_file_mode() {
+        assert_eq!(validators::parse_file_mode("644"), Ok(0o644));
+        assert_eq!(validators::parse_file_mode("0o640"), Ok(0o640));
+        assert!(validators::parse_file_mode("0o2770").is_err());
+        assert!(validators::parse_file_mode("4755").is_err());
+        assert!(validators::parse_file_mode("888").is_err());
+        assert!(validators::parse_file_mode("17777").is_err());
+    }
+
     #[test]
     fn test_app_mode_receiver() {
         let args = CliArgs {
@@ -489,6 +924,40 @@ mod tests {
             verbose: false,
             log_level: LogLevel::Info,
             max_file_size_mb: 100,
+            shutdown_report: None,
+            history_file: None,
+            transfer_logs: None,
+            keep_transfer_logs: false,
+            resume_dir: None,
+            resume_max_age_secs: 86400,
+            insecure_plaintext: false,
+            fsync: false,
+            file_mode: 0o644,
+            file_uid: None,
+            file_gid: None,
+            memory_budget_mb: 256,
+            min_free_memory_mb: 0,
+            max_upload_kbps: None,
+            max_download_kbps: None,
+            max_peer_kbps: None,
+            auth_token: None,
+            allowed_sources: Vec::new(),
+            deny_loopback: false,
+            replace_original: false,
+            require_conversion: false,
+            discard_failed_originals: false,
+            default_target_format: None,
+            conversion_cache: None,
+            pdf_config: None,
+            control_socket: None,
+            priority: 4,
+            accept_prompt: None,
+            forward_to: None,
+            relisten: false,
+            converted_ttl_secs: None,
+            max_transfers_per_peer: None,
+            completed_retention_secs: 300,
+            max_completed_transfers: 1000,
         };
 
         // Create test directory
@@ -500,6 +969,43 @@ mod tests {
         // Clean up
         std::fs::remove_dir_all("./test_output").ok();
     }
+
+    #[test]
+    fn test_env_overrides_respect_cli_precedence() {
+        let out = std::env::temp_dir().join("p2p_env_output");
+        let env = |key: &str| match key {
+            ENV_LISTEN => Some("/ip4/127.0.0.1/tcp/9000".to_string()),
+            ENV_PEER => Some("".to_string()),
+            ENV_OUTPUT_DIR => Some(out.display().to_string()),
+            _ => None,
+        };
+
+        // Flag given: env ignored for listen, still used for output
+        let matches = CliArgs::command()
+            .try_get_matches_from(["p2p-converter", "--listen", "/ip4/0.0.0.0/tcp/7000"])
+            .unwrap();
+        let mut args = CliArgs::from_arg_matches(&matches).unwrap();
+        args.apply_env_overrides(|id| explicitly_given(&matches, id), env).unwrap();
+        assert_eq!(args.listen_address.0.to_string(), "/ip4/0.0.0.0/tcp/7000");
+        assert_eq!(args.output_dir, out);
+        // Empty variable counts as unset
+        assert!(args.target_peer.is_none());
+
+        // No flags: env replaces the defaults
+        let matches = CliArgs::command().try_get_matches_from(["p2p-converter"]).unwrap();
+        let mut args = CliArgs::from_arg_matches(&matches).unwrap();
+        args.apply_env_overrides(|id| explicitly_given(&matches, id), env).unwrap();
+        assert_eq!(args.listen_address.0.to_string(), "/ip4/127.0.0.1/tcp/9000");
+
+        // Invalid values are reported, naming the variable
+        let err = args
+            .apply_env_overrides(|_| false, |key| (key == ENV_PEER).then(|| "/tcp/80".to_string()))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigurationError::ValidationFailed { ref section, .. } if section == ENV_PEER
+        ));
+    }
 }
 
 /// Example usage function
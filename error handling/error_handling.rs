@@ -65,6 +65,116 @@ pub enum P2PError {
     /// Configuration errors
     #[error("Configuration error: {0}")]
     Configuration(#[from] ConfigurationError),
+
+    /// Several input problems reported together
+    #[error("{} validation errors: {}", .0.len(), join_errors(.0))]
+    ValidationErrors(Vec<P2PError>),
+}
+
+impl P2PError {
+    /// Turn collected validation failures into a single result: `Ok` when
+    /// there are none, the error itself when there is one, and
+    /// `ValidationErrors` otherwise
+    pub fn aggregate(mut errors: Vec<P2PError>) -> Result<()> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(P2PError::ValidationErrors(errors)),
+        }
+    }
+
+    /// Broad kind of error, named after the sub-enum it wraps
+    pub fn category(&self) -> &'static str {
+        match self {
+            P2PError::Network(_) => "network",
+            P2PError::Conversion(_) => "conversion",
+            P2PError::FileIO(_) => "file_io",
+            P2PError::Validation(_) | P2PError::ValidationErrors(_) => "validation",
+            P2PError::Protocol(_) => "protocol",
+            P2PError::Timeout(_) => "timeout",
+            P2PError::Resource(_) => "resource",
+            P2PError::Configuration(_) => "configuration",
+        }
+    }
+
+    /// Stable machine-readable code, `<category>.<variant>`. Messages may
+    /// be reworded; codes are not.
+    pub fn code(&self) -> &'static str {
+        match self {
+            P2PError::Network(e) => match e {
+                NetworkError::ConnectionFailed { .. } => "network.connection_failed",
+                NetworkError::ConnectionTimeout { .. } => "network.connection_timeout",
+                NetworkError::PeerUnreachable { .. } => "network.peer_unreachable",
+                NetworkError::Transport { .. } => "network.transport",
+                NetworkError::DnsResolution { .. } => "network.dns_resolution",
+                NetworkError::Interface { .. } => "network.interface",
+                NetworkError::BandwidthLimit { .. } => "network.bandwidth_limit",
+            },
+            P2PError::Conversion(e) => match e {
+                ConversionError::UnsupportedFormat { .. } => "conversion.unsupported_format",
+                ConversionError::PdfGeneration { .. } => "conversion.pdf_generation",
+                ConversionError::TextExtraction { .. } => "conversion.text_extraction",
+                ConversionError::FontLoading { .. } => "conversion.font_loading",
+                ConversionError::InvalidDocument { .. } => "conversion.invalid_document",
+                ConversionError::ConversionTimeout { .. } => "conversion.timeout",
+                ConversionError::MemoryLimit { .. } => "conversion.memory_limit",
+                ConversionError::SameFormat { .. } => "conversion.same_format",
+            },
+            P2PError::FileIO(e) => match e {
+                FileIOError::NotFound { .. } => "file_io.not_found",
+                FileIOError::PermissionDenied { .. } => "file_io.permission_denied",
+                FileIOError::InsufficientSpace { .. } => "file_io.insufficient_space",
+                FileIOError::FileTooLarge { .. } => "file_io.file_too_large",
+                FileIOError::InvalidPath { .. } => "file_io.invalid_path",
+                FileIOError::FileLocked { .. } => "file_io.file_locked",
+                FileIOError::DirectoryCreation { .. } => "file_io.directory_creation",
+                FileIOError::FileCorruption { .. } => "file_io.file_corruption",
+            },
+            P2PError::Validation(e) => match e {
+                ValidationError::InvalidMultiaddr { .. } => "validation.invalid_multiaddr",
+                ValidationError::MissingComponent { .. } => "validation.missing_component",
+                ValidationError::InvalidPeerId { .. } => "validation.invalid_peer_id",
+                ValidationError::InvalidExtension { .. } => "validation.invalid_extension",
+                ValidationError::InvalidConfigValue { .. } => "validation.invalid_config_value",
+                ValidationError::OutOfRange { .. } => "validation.out_of_range",
+                ValidationError::RequiredField { .. } => "validation.required_field",
+            },
+            P2PError::ValidationErrors(_) => "validation.multiple",
+            P2PError::Protocol(e) => match e {
+                ProtocolError::NegotiationFailed { .. } => "protocol.negotiation_failed",
+                ProtocolError::UnsupportedVersion { .. } => "protocol.unsupported_version",
+                ProtocolError::SerializationFailed { .. } => "protocol.serialization_failed",
+                ProtocolError::DeserializationFailed { .. } => "protocol.deserialization_failed",
+                ProtocolError::StreamClosed { .. } => "protocol.stream_closed",
+                ProtocolError::ResponseLost { .. } => "protocol.response_lost",
+                ProtocolError::ResponseTooLarge { .. } => "protocol.response_too_large",
+                ProtocolError::InvalidState { .. } => "protocol.invalid_state",
+                ProtocolError::Unauthorized => "protocol.unauthorized",
+            },
+            P2PError::Timeout(e) => match e {
+                TimeoutError::Operation { .. } => "timeout.operation",
+                TimeoutError::NetworkOperation { .. } => "timeout.network_operation",
+                TimeoutError::FileOperation { .. } => "timeout.file_operation",
+                TimeoutError::UserInput { .. } => "timeout.user_input",
+            },
+            P2PError::Resource(e) => match e {
+                ResourceError::LimitExceeded { .. } => "resource.limit_exceeded",
+                ResourceError::CleanupFailed { .. } => "resource.cleanup_failed",
+                ResourceError::LeakDetected { .. } => "resource.leak_detected",
+                ResourceError::Unavailable { .. } => "resource.unavailable",
+            },
+            P2PError::Configuration(e) => match e {
+                ConfigurationError::FileNotFound { .. } => "configuration.file_not_found",
+                ConfigurationError::InvalidFormat { .. } => "configuration.invalid_format",
+                ConfigurationError::MissingRequired { .. } => "configuration.missing_required",
+                ConfigurationError::ValidationFailed { .. } => "configuration.validation_failed",
+            },
+        }
+    }
+}
+
+fn join_errors(errors: &[P2PError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
 }
 
 /// Network-specific error types
@@ -142,6 +252,10 @@ pub enum ConversionError {
     /// Memory limit exceeded during conversion
     #[error("Memory limit exceeded during conversion: {used}/{limit} MB")]
     MemoryLimit { used: u64, limit: u64 },
+
+    /// Target format is the format the file is already in
+    #[error("Nothing to convert: '{filename}' is already {format}")]
+    SameFormat { filename: String, format: String },
 }
 
 /// File I/O error types
@@ -255,9 +369,22 @@ pub enum ProtocolError {
     #[error("Stream closed unexpectedly with {peer_id}")]
     StreamClosed { peer_id: PeerId },
 
+    /// The file was sent but the connection closed before the response;
+    /// the receiver may still have processed it
+    #[error("Connection to {peer_id} closed before the response to transfer {transfer_id} arrived")]
+    ResponseLost { peer_id: PeerId, transfer_id: String },
+
+    /// A response would be larger than the receiving side reads
+    #[error("Response of {size} bytes exceeds the {limit} byte limit of the requesting peer")]
+    ResponseTooLarge { size: String, limit: u64 },
+
     /// Protocol state error
     #[error("Invalid protocol state: expected {expected}, current {current}")]
     InvalidState { expected: String, current: String },
+
+    /// Request lacked valid credentials; deliberately says nothing more
+    #[error("Unauthorized")]
+    Unauthorized,
 }
 
 /// Timeout error types
@@ -376,6 +503,8 @@ pub mod validation {
     use super::*;
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
     use regex::Regex;
+    use futures::future::join_all;
+    use tokio::sync::Semaphore;
 
     /// Multiaddr validator
     pub struct MultiAddrValidator {
@@ -527,6 +656,50 @@ pub mod validation {
             }
             Ok(None)
         }
+
+        /// Load a list of peer multiaddrs, one per line.
+        ///
+        /// Blank lines and `#` comments are ignored, invalid lines are logged
+        /// and skipped, and duplicates are removed (first occurrence wins).
+        pub async fn load_peer_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Multiaddr>> {
+            let path = path.as_ref();
+            let content = fs::read_to_string(path).await
+                .map_err(|e| match e.kind() {
+                    ErrorKind::NotFound => P2PError::FileIO(FileIOError::NotFound {
+                        path: path.to_path_buf(),
+                    }),
+                    ErrorKind::PermissionDenied => P2PError::FileIO(FileIOError::PermissionDenied {
+                        path: path.to_path_buf(),
+                        operation: "read".to_string(),
+                    }),
+                    _ => P2PError::FileIO(FileIOError::InvalidPath {
+                        path: path.to_path_buf(),
+                        reason: e.to_string(),
+                    }),
+                })?;
+
+            let mut peers: Vec<Multiaddr> = Vec::new();
+
+            for (line_number, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                match self.validate(line) {
+                    Ok(addr) if peers.contains(&addr) => {
+                        debug!("Skipping duplicate peer {} at {}:{}", addr, path.display(), line_number + 1);
+                    }
+                    Ok(addr) => peers.push(addr),
+                    Err(e) => {
+                        warn!("Skipping invalid peer at {}:{}: {}", path.display(), line_number + 1, e);
+                    }
+                }
+            }
+
+            info!("Loaded {} peers from {}", peers.len(), path.display());
+            Ok(peers)
+        }
     }
 
     impl Default for MultiAddrValidator {
@@ -535,6 +708,9 @@ pub mod validation {
         }
     }
 
+    /// Longest filename accepted for received files, in bytes
+    pub const MAX_FILENAME_LENGTH: usize = 255;
+
     /// File path validator
     pub struct FilePathValidator {
         max_path_length: usize,
@@ -542,6 +718,7 @@ pub mod validation {
         forbidden_patterns: Vec<Regex>,
         check_existence: bool,
         check_permissions: bool,
+        probe_open: bool,
     }
 
     impl FilePathValidator {
@@ -558,6 +735,7 @@ pub mod validation {
                 ],
                 check_existence: true,
                 check_permissions: true,
+                probe_open: true,
             }
         }
 
@@ -571,6 +749,25 @@ pub mod validation {
             self
         }
 
+        /// Judge readability from the permission bits instead of opening the
+        /// file. Opening is slow on network filesystems, but the bits miss
+        /// ACLs and ownership, so an unreadable file may only fail once the
+        /// transfer actually reads it.
+        pub fn trust_metadata(mut self) -> Self {
+            self.probe_open = false;
+            self
+        }
+
+        /// Validate many paths with at most `concurrency` checks in flight.
+        /// Results come back in the order of `paths`.
+        pub async fn validate_all<P: AsRef<Path>>(&self, paths: &[P], concurrency: usize) -> Vec<Result<PathBuf>> {
+            let permits = Semaphore::new(concurrency.max(1));
+            join_all(paths.iter().map(|path| async {
+                let _permit = permits.acquire().await.expect("semaphore is never closed");
+                self.validate(path).await
+            })).await
+        }
+
         /// Validate file path format and accessibility
         pub async fn validate<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
             let path = path.as_ref();
@@ -642,7 +839,57 @@ pub mod validation {
             Ok(path.to_path_buf())
         }
 
+        /// Turn a filename chosen by a remote peer into a safe name for
+        /// joining onto an output directory.
+        ///
+        /// Directory components are dropped, control characters and forbidden
+        /// patterns are replaced with `_`, and the name is truncated to
+        /// [`MAX_FILENAME_LENGTH`] bytes keeping its extension. Names that are
+        /// empty or pure traversal (`.`, `..`) are rejected.
+        pub fn sanitize_filename(&self, filename: &str) -> std::result::Result<String, FileIOError> {
+            let invalid = |reason: &str| FileIOError::InvalidPath {
+                path: PathBuf::from(filename),
+                reason: reason.to_string(),
+            };
+
+            let base = filename
+                .rsplit(|c| c == '/' || c == '\\')
+                .next()
+                .unwrap_or_default()
+                .trim();
+            if base.is_empty() || base == "." || base == ".." {
+                return Err(invalid("Filename has no usable final component"));
+            }
+
+            let mut name: String = base
+                .chars()
+                .map(|c| if c.is_control() { '_' } else { c })
+                .collect();
+            for pattern in &self.forbidden_patterns {
+                name = pattern.replace_all(&name, "_").into_owned();
+            }
+
+            // Leading dots would make the file hidden on Unix
+            let name = name.trim_start_matches('.').trim_end_matches(['.', ' ']);
+            if name.is_empty() || name.chars().all(|c| c == '_') {
+                return Err(invalid("Filename is empty after sanitization"));
+            }
+
+            Ok(truncate_filename(name, MAX_FILENAME_LENGTH))
+        }
+
         async fn validate_permissions(&self, path: &Path, metadata: &Metadata) -> Result<()> {
+            if !self.probe_open {
+                return if metadata_readable(metadata) {
+                    Ok(())
+                } else {
+                    Err(P2PError::FileIO(FileIOError::PermissionDenied {
+                        path: path.to_path_buf(),
+                        operation: "read".to_string(),
+                    }))
+                };
+            }
+
             // Check if file is readable
             match fs::File::open(path).await {
                 Ok(_) => {},
@@ -689,6 +936,39 @@ pub mod validation {
         }
     }
 
+    /// Whether any read bit is set. Without Unix modes there is nothing to
+    /// go on, so the file is assumed readable.
+    fn metadata_readable(metadata: &Metadata) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.permissions().mode() & 0o444 != 0
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            true
+        }
+    }
+
+    /// Cut `name` to at most `max` bytes on a char boundary, keeping a short
+    /// extension intact
+    fn truncate_filename(name: &str, max: usize) -> String {
+        if name.len() <= max {
+            return name.to_string();
+        }
+
+        let (stem, ext) = match name.rfind('.') {
+            Some(dot) if dot > 0 && name.len() - dot <= 16 => name.split_at(dot),
+            _ => (name, ""),
+        };
+        let mut end = max - ext.len();
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}{}", &stem[..end], ext)
+    }
+
     /// File type validator using magic numbers and heuristics
     pub struct FileTypeValidator {
         strict_mode: bool,
@@ -832,6 +1112,7 @@ pub mod timeouts {
         default_network_timeout: Duration,
         default_file_timeout: Duration,
         default_conversion_timeout: Duration,
+        max_conversion_timeout: Duration,
         max_retries: usize,
     }
 
@@ -841,6 +1122,7 @@ pub mod timeouts {
                 default_network_timeout: Duration::from_secs(30),
                 default_file_timeout: Duration::from_secs(60),
                 default_conversion_timeout: Duration::from_secs(120),
+                max_conversion_timeout: Duration::from_secs(600),
                 max_retries: 3,
             }
         }
@@ -860,6 +1142,21 @@ pub mod timeouts {
             self
         }
 
+        /// Upper bound on any conversion timeout, including ones asked for
+        /// by peers
+        pub fn with_max_conversion_timeout(mut self, timeout: Duration) -> Self {
+            self.max_conversion_timeout = timeout;
+            self
+        }
+
+        /// Timeout for one conversion: `requested` if given, else the
+        /// default, never more than the maximum
+        pub fn conversion_timeout(&self, requested: Option<Duration>) -> Duration {
+            requested
+                .unwrap_or(self.default_conversion_timeout)
+                .min(self.max_conversion_timeout)
+        }
+
         /// Execute network operation with timeout and retries
         pub async fn execute_network_operation<F, Fut, T>(
             &self,
@@ -945,12 +1242,28 @@ pub mod timeouts {
             F: FnOnce() -> Fut,
             Fut: std::future::Future<Output = Result<T>>,
         {
-            match timeout(self.default_conversion_timeout, operation()).await {
+            self.execute_conversion_operation_within(operation_name, None, operation).await
+        }
+
+        /// Execute conversion operation with a caller-requested timeout,
+        /// clamped by [`TimeoutManager::conversion_timeout`]
+        pub async fn execute_conversion_operation_within<F, Fut, T>(
+            &self,
+            operation_name: &str,
+            requested: Option<Duration>,
+            operation: F,
+        ) -> Result<T>
+        where
+            F: FnOnce() -> Fut,
+            Fut: std::future::Future<Output = Result<T>>,
+        {
+            let limit = self.conversion_timeout(requested);
+            match timeout(limit, operation()).await {
                 Ok(result) => result,
-                Err(_) => Err(P2PError::Timeout(TimeoutError::Operation {
-                    operation: operation_name.to_string(),
-                    duration: self.default_conversion_timeout,
-                })),
+                Err(_) => {
+                    warn!("Conversion operation '{}' timed out after {:?}", operation_name, limit);
+                    Err(P2PError::Conversion(ConversionError::ConversionTimeout { duration: limit }))
+                }
             }
         }
     }
@@ -966,14 +1279,39 @@ pub mod timeouts {
 pub mod recovery {
     use super::*;
 
+    /// Exponential backoff between retries, shared by everything that
+    /// retries so delays can't drift apart between code paths
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Backoff {
+        /// Delay before the first retry
+        pub initial: Duration,
+        /// Upper bound on any delay
+        pub max: Duration,
+        /// Growth factor per retry; values below 1.0 are treated as 1.0
+        pub multiplier: f64,
+    }
+
+    impl Backoff {
+        pub fn new(initial: Duration, max: Duration, multiplier: f64) -> Self {
+            Self { initial, max, multiplier }
+        }
+
+        /// Delay before retry number `retry` (1-based):
+        /// `initial * multiplier^(retry - 1)`, capped at `max`
+        pub fn delay(&self, retry: u32) -> Duration {
+            let exponent = retry.saturating_sub(1).min(64) as i32;
+            let secs = self.initial.as_secs_f64() * self.multiplier.max(1.0).powi(exponent);
+            Duration::from_secs_f64(secs.min(self.max.as_secs_f64()))
+        }
+    }
+
     /// Recovery strategy for different types of failures
     #[derive(Debug, Clone)]
     pub enum RecoveryStrategy {
         /// Retry with exponential backoff
         RetryWithBackoff {
             max_attempts: usize,
-            initial_delay: Duration,
-            max_delay: Duration,
+            backoff: Backoff,
         },
         /// Fallback to alternative method
         Fallback { alternative: String },
@@ -1003,20 +1341,17 @@ pub mod recovery {
             // Default strategies
             strategies.insert("network_connection".to_string(), RecoveryStrategy::RetryWithBackoff {
                 max_attempts: 5,
-                initial_delay: Duration::from_millis(500),
-                max_delay: Duration::from_secs(30),
+                backoff: Backoff::new(Duration::from_millis(500), Duration::from_secs(30), 1.5),
             });
 
             strategies.insert("file_conversion".to_string(), RecoveryStrategy::RetryWithBackoff {
                 max_attempts: 3,
-                initial_delay: Duration::from_secs(1),
-                max_delay: Duration::from_secs(10),
+                backoff: Backoff::new(Duration::from_secs(1), Duration::from_secs(10), 1.5),
             });
 
             strategies.insert("file_io".to_string(), RecoveryStrategy::RetryWithBackoff {
                 max_attempts: 2,
-                initial_delay: Duration::from_millis(100),
-                max_delay: Duration::from_secs(5),
+                backoff: Backoff::new(Duration::from_millis(100), Duration::from_secs(5), 1.5),
             });
 
             Self {
@@ -1039,8 +1374,8 @@ pub mod recovery {
             let strategy = self.determine_strategy(error);
 
             match strategy {
-                RecoveryStrategy::RetryWithBackoff { max_attempts, initial_delay, max_delay } => {
-                    self.retry_with_backoff(operation_id, max_attempts, initial_delay, max_delay, operation).await
+                RecoveryStrategy::RetryWithBackoff { max_attempts, backoff } => {
+                    self.retry_with_backoff(operation_id, max_attempts, backoff, operation).await
                 }
                 RecoveryStrategy::Fallback { alternative } => {
                     warn!("Attempting fallback strategy: {}", alternative);
@@ -1062,15 +1397,13 @@ pub mod recovery {
             &self,
             operation_id: &str,
             max_attempts: usize,
-            initial_delay: Duration,
-            max_delay: Duration,
+            backoff: Backoff,
             operation: F,
         ) -> Result<T>
         where
             F: Fn() -> Fut,
             Fut: std::future::Future<Output = Result<T>>,
         {
-            let mut current_delay = initial_delay;
             let mut last_error = None;
 
             for attempt in 1..=max_attempts {
@@ -1097,15 +1430,10 @@ pub mod recovery {
                         self.active_recoveries.write().await.insert(operation_id.to_string(), recovery_state);
 
                         if attempt < max_attempts {
+                            let delay = backoff.delay(attempt as u32);
                             warn!("Operation {} failed on attempt {}, retrying in {:?}", 
-                                  operation_id, attempt, current_delay);
-                            sleep(current_delay).await;
-
-                            // Exponential backoff
-                            current_delay = std::cmp::min(
-                                Duration::from_millis((current_delay.as_millis() as f64 * 1.5) as u64),
-                                max_delay
-                            );
+                                  operation_id, attempt, delay);
+                            sleep(delay).await;
                         }
                     }
                 }
@@ -1203,28 +1531,84 @@ pub mod cleanup {
         }
     }
 
+    /// Remove `key` from a shared map from a drop guard, where awaiting the
+    /// lock is not possible; falls back to a spawned task if the lock is
+    /// busy. `on_removed` is given the removed entry, if there was one.
+    pub fn discard_entry<K, V, F>(map: Arc<RwLock<HashMap<K, V>>>, key: K, on_removed: F)
+    where
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        F: FnOnce(V) + Send + 'static,
+    {
+        match map.try_write() {
+            Ok(mut entries) => {
+                if let Some(entry) = entries.remove(&key) {
+                    on_removed(entry);
+                }
+            }
+            Err(_) => {
+                if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                    runtime.spawn(async move {
+                        if let Some(entry) = map.write().await.remove(&key) {
+                            on_removed(entry);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// Tracked resources, behind a synchronous lock so drop guards can
+    /// update it even when the owning task is aborted
+    type ResourceRegistry = Arc<std::sync::Mutex<HashMap<String, String>>>;
+
+    /// Lock the registry, recovering from a panic in another holder
+    fn lock_registry(registry: &ResourceRegistry) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        registry.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     /// Cleanup manager for tracking and managing resources
     pub struct CleanupManager {
-        active_resources: Arc<RwLock<HashMap<String, String>>>,
+        active_resources: ResourceRegistry,
         cleanup_callbacks: Arc<RwLock<HashMap<String, Box<dyn Fn() + Send + Sync>>>>,
     }
 
     impl CleanupManager {
         pub fn new() -> Self {
             Self {
-                active_resources: Arc::new(RwLock::new(HashMap::new())),
+                active_resources: Arc::new(std::sync::Mutex::new(HashMap::new())),
                 cleanup_callbacks: Arc::new(RwLock::new(HashMap::new())),
             }
         }
 
         /// Register a resource for tracking
         pub async fn register_resource(&self, id: String, description: String) {
-            self.active_resources.write().await.insert(id, description);
+            lock_registry(&self.active_resources).insert(id, description);
         }
 
         /// Unregister a resource
         pub async fn unregister_resource(&self, id: &str) {
-            self.active_resources.write().await.remove(id);
+            lock_registry(&self.active_resources).remove(id);
+        }
+
+        /// Register a resource and return a guard that runs `cleanup` and
+        /// unregisters it when dropped.
+        ///
+        /// Dropping happens even when the owning task is aborted, so an
+        /// interrupted transfer cannot leave its entry behind. Call
+        /// [`ResourceGuard::take`] on success to keep the resource, then
+        /// [`unregister_resource`](Self::unregister_resource) it as usual.
+        pub fn guard<F>(&self, id: String, description: String, cleanup: F) -> ResourceGuard<String>
+        where
+            F: FnOnce() + Send + 'static,
+        {
+            lock_registry(&self.active_resources).insert(id.clone(), description);
+            let registry = self.active_resources.clone();
+            let name = id.clone();
+            ResourceGuard::new(id, name, move |id| {
+                cleanup();
+                lock_registry(&registry).remove(&id);
+            })
         }
 
         /// Register cleanup callback for a resource
@@ -1260,18 +1644,18 @@ pub mod cleanup {
                 debug!("Cleaned up resource: {}", id);
             }
 
-            self.active_resources.write().await.clear();
+            lock_registry(&self.active_resources).clear();
             failed_cleanups
         }
 
         /// Get list of active resources
         pub async fn get_active_resources(&self) -> HashMap<String, String> {
-            self.active_resources.read().await.clone()
+            lock_registry(&self.active_resources).clone()
         }
 
         /// Check for resource leaks
         pub async fn check_leaks(&self) -> Vec<String> {
-            let active = self.active_resources.read().await;
+            let active = lock_registry(&self.active_resources);
             if !active.is_empty() {
                 warn!("Potential resource leaks detected: {} active resources", active.len());
                 active.keys().cloned().collect()
@@ -1319,6 +1703,10 @@ pub mod display {
 
         /// Format error for end user display
         pub fn format_error(&self, error: &P2PError) -> String {
+            if let P2PError::ValidationErrors(errors) = error {
+                return self.format_errors(errors);
+            }
+
             let mut message = String::new();
 
             // Main error message
@@ -1331,8 +1719,41 @@ pub mod display {
 
             // Recovery suggestions if enabled
             if self.show_recovery_suggestions {
-                if let Some(suggestion) = self.get_recovery_suggestion(error) {
-                    message.push_str(&format!("\n\nSuggestion: {}", suggestion));
+                message.push_str(&format!("\n\nSuggestion: {}", self.get_recovery_suggestion(error)));
+            }
+
+            message
+        }
+
+        /// The error as `{code, category, message, suggestion}` for scripts
+        /// and log pipelines. Several errors reported together also carry
+        /// each of them under `errors`.
+        pub fn format_error_json(&self, error: &P2PError) -> serde_json::Value {
+            let mut json = serde_json::json!({
+                "code": error.code(),
+                "category": error.category(),
+                "message": self.format_main_message(error),
+                "suggestion": self.get_recovery_suggestion(error),
+            });
+            if let P2PError::ValidationErrors(errors) = error {
+                json["errors"] = errors.iter().map(|e| self.format_error_json(e)).collect();
+            }
+            json
+        }
+
+        /// Numbered summary of several errors, each with its own suggestion
+        fn format_errors(&self, errors: &[P2PError]) -> String {
+            let mut message = format!("Found {} problems:", errors.len());
+
+            for (i, error) in errors.iter().enumerate() {
+                message.push_str(&format!("\n  {}. {}", i + 1, self.format_main_message(error)));
+
+                if self.show_technical_details {
+                    message.push_str(&format!("\n     Technical details: {}", error));
+                }
+
+                if self.show_recovery_suggestions {
+                    message.push_str(&format!("\n     Suggestion: {}", self.get_recovery_suggestion(error)));
                 }
             }
 
@@ -1353,6 +1774,9 @@ pub mod display {
                 P2PError::FileIO(FileIOError::PermissionDenied { path, operation }) => {
                     format!("Permission denied: cannot {} file {}", operation, path.display())
                 }
+                P2PError::FileIO(FileIOError::DirectoryCreation { path, reason }) => {
+                    format!("Can't create directory {}: {}", path.display(), reason)
+                }
                 P2PError::FileIO(FileIOError::FileTooLarge { size, max_size }) => {
                     format!("File is too large: {:.1} MB exceeds maximum {:.1} MB", 
                            *size as f64 / 1_000_000.0, *max_size as f64 / 1_000_000.0)
@@ -1367,35 +1791,92 @@ pub mod display {
                 P2PError::Timeout(TimeoutError::Operation { operation, duration }) => {
                     format!("Operation '{}' timed out after {:?}", operation, duration)
                 }
+                P2PError::ValidationErrors(errors) => format!("Found {} problems", errors.len()),
                 _ => error.to_string(),
             }
         }
 
-        fn get_recovery_suggestion(&self, error: &P2PError) -> Option<String> {
-            match error {
+        /// What to try next. Variants without advice of their own get their
+        /// category's, so every error comes with one.
+        fn get_recovery_suggestion(&self, error: &P2PError) -> String {
+            let suggestion = match error {
                 P2PError::Network(NetworkError::ConnectionFailed { .. }) => {
-                    Some("Check the peer address and ensure the peer is running and accessible".to_string())
+                    "Check the peer address and ensure the peer is running and accessible"
                 }
                 P2PError::Network(NetworkError::ConnectionTimeout { .. }) => {
-                    Some("Check your network connection and try again. The peer may be overloaded".to_string())
+                    "Check your network connection and try again. The peer may be overloaded"
+                }
+                P2PError::Network(NetworkError::BandwidthLimit { .. }) => {
+                    "Wait for running transfers to finish or raise the bandwidth limit"
+                }
+                P2PError::Network(_) => {
+                    "Check your network connection and that the peer is running, then try again"
+                }
+                P2PError::Conversion(ConversionError::UnsupportedFormat { .. }) => {
+                    "Convert the file to a supported format first, or check file extension"
+                }
+                P2PError::Conversion(ConversionError::SameFormat { .. }) => {
+                    "Send the file without a target format, or pick a different one"
+                }
+                P2PError::Conversion(ConversionError::ConversionTimeout { .. }) => {
+                    "Try a smaller file or allow more time for conversion"
+                }
+                P2PError::Conversion(_) => {
+                    "Check the file opens correctly, or send it without conversion"
                 }
                 P2PError::FileIO(FileIOError::NotFound { .. }) => {
-                    Some("Verify the file path is correct and the file exists".to_string())
+                    "Verify the file path is correct and the file exists"
                 }
                 P2PError::FileIO(FileIOError::PermissionDenied { .. }) => {
-                    Some("Check file permissions or run as administrator/root if necessary".to_string())
+                    "Check file permissions or run as administrator/root if necessary"
+                }
+                P2PError::FileIO(FileIOError::DirectoryCreation { .. }) => {
+                    "Create the directory yourself with permissions for this user, or choose a writable location (e.g. --output/-o)"
                 }
                 P2PError::FileIO(FileIOError::FileTooLarge { .. }) => {
-                    Some("Try splitting the file into smaller parts or increase the size limit".to_string())
+                    "Try splitting the file into smaller parts or increase the size limit"
+                }
+                P2PError::FileIO(FileIOError::InsufficientSpace { .. }) => {
+                    "Free up disk space or choose an output directory on another disk"
+                }
+                P2PError::FileIO(_) => {
+                    "Check the path exists and is accessible to this user"
                 }
                 P2PError::Validation(ValidationError::InvalidMultiaddr { .. }) => {
-                    Some("Ensure the address follows the format: /ip4/127.0.0.1/tcp/8080/p2p/12D3K...".to_string())
+                    "Ensure the address follows the format: /ip4/127.0.0.1/tcp/8080/p2p/12D3K..."
                 }
-                P2PError::Conversion(ConversionError::UnsupportedFormat { .. }) => {
-                    Some("Convert the file to a supported format first, or check file extension".to_string())
+                P2PError::Validation(_) => {
+                    "Check the value against --help and correct it"
                 }
-                _ => None,
-            }
+                P2PError::ValidationErrors(_) => {
+                    "Fix each problem listed and run the command again"
+                }
+                P2PError::Protocol(ProtocolError::Unauthorized) => {
+                    "Check both sides use the same --auth-token"
+                }
+                P2PError::Protocol(ProtocolError::UnsupportedVersion { .. } | ProtocolError::NegotiationFailed { .. }) => {
+                    "Upgrade both peers to the same release"
+                }
+                P2PError::Protocol(ProtocolError::ResponseLost { .. }) => {
+                    "Check the transfer's status on the receiver before sending it again"
+                }
+                P2PError::Protocol(_) => {
+                    "Make sure both peers run compatible releases, then retry the transfer"
+                }
+                P2PError::Timeout(_) => {
+                    "Try again; if it keeps happening, check the peer's load or raise the timeout"
+                }
+                P2PError::Resource(_) => {
+                    "Wait for running transfers to finish, or raise the limit"
+                }
+                P2PError::Configuration(ConfigurationError::FileNotFound { .. }) => {
+                    "Check the configuration file path, or run without it to use the defaults"
+                }
+                P2PError::Configuration(_) => {
+                    "Correct the configuration setting named in the error"
+                }
+            };
+            suggestion.to_string()
         }
     }
 
@@ -1425,6 +1906,25 @@ mod tests {
         assert!(validator.validate(invalid_addr).is_err());
     }
 
+    #[tokio::test]
+    async fn test_load_peer_file() {
+        let validator = validation::MultiAddrValidator::new();
+
+        let mut peer_file = NamedTempFile::new().unwrap();
+        writeln!(peer_file, "# known peers").unwrap();
+        writeln!(peer_file, "/ip4/127.0.0.1/tcp/8080/p2p/12D3KooWBmwkafWE2fqfzS96VoTZgpGp6aJsF4SJ6eAR5AHXCXAZ").unwrap();
+        writeln!(peer_file).unwrap();
+        writeln!(peer_file, "not-a-multiaddr").unwrap();
+        writeln!(peer_file, "  /ip4/10.0.0.2/tcp/9000  ").unwrap();
+        writeln!(peer_file, "/ip4/127.0.0.1/tcp/8080/p2p/12D3KooWBmwkafWE2fqfzS96VoTZgpGp6aJsF4SJ6eAR5AHXCXAZ").unwrap();
+
+        let peers = validator.load_peer_file(peer_file.path()).await.unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[1].to_string(), "/ip4/10.0.0.2/tcp/9000");
+
+        assert!(validator.load_peer_file("/nonexistent/peers.txt").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_file_path_validation() {
         let validator = validation::FilePathValidator::new().skip_existence_check();
@@ -1439,6 +1939,56 @@ mod tests {
         assert!(validator.validate("../etc/passwd").await.is_err());
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_validate_all_trusting_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let readable = temp_dir.path().join("readable.txt");
+        let locked = temp_dir.path().join("locked.txt");
+        std::fs::write(&readable, b"hello").unwrap();
+        std::fs::write(&locked, b"hello").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o200)).unwrap();
+        let missing = temp_dir.path().join("missing.txt");
+
+        let validator = validation::FilePathValidator::new().trust_metadata();
+        let results = validator.validate_all(&[&readable, &missing, &locked, &readable], 2).await;
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), &readable);
+        assert!(matches!(results[1], Err(P2PError::FileIO(FileIOError::NotFound { .. }))));
+        assert!(matches!(results[2], Err(P2PError::FileIO(FileIOError::PermissionDenied { .. }))));
+        assert_eq!(results[3].as_ref().unwrap(), &readable);
+
+        // Zero concurrency still makes progress
+        assert_eq!(validator.validate_all(&[&readable], 0).await.len(), 1);
+    }
+
+    #[test]
+    fn test_sanitize_received_filename() {
+        let validator = validation::FilePathValidator::new();
+
+        assert_eq!(validator.sanitize_filename("report.pdf").unwrap(), "report.pdf");
+        assert_eq!(validator.sanitize_filename("../../etc/passwd").unwrap(), "passwd");
+        assert_eq!(validator.sanitize_filename("C:\\temp\\a.txt").unwrap(), "a.txt");
+        assert_eq!(validator.sanitize_filename("what?.txt").unwrap(), "what_.txt");
+        assert_eq!(validator.sanitize_filename("a..b\0.txt").unwrap(), "a_b_.txt");
+        assert_eq!(validator.sanitize_filename(".bashrc").unwrap(), "bashrc");
+
+        let long = format!("{}.pdf", "é".repeat(200));
+        let truncated = validator.sanitize_filename(&long).unwrap();
+        assert!(truncated.len() <= validation::MAX_FILENAME_LENGTH);
+        assert!(truncated.ends_with(".pdf"));
+
+        for bad in ["", "..", "dir/", "a/..", "???"] {
+            assert!(matches!(
+                validator.sanitize_filename(bad),
+                Err(FileIOError::InvalidPath { .. })
+            ), "{:?} should be rejected", bad);
+        }
+    }
+
     #[tokio::test]
     async fn test_timeout_manager() {
         let timeout_manager = timeouts::TimeoutManager::new()
@@ -1458,6 +2008,40 @@ mod tests {
         assert!(matches!(result.unwrap_err(), P2PError::Timeout(_)));
     }
 
+    #[tokio::test]
+    async fn test_conversion_timeout_clamped() {
+        let timeout_manager = timeouts::TimeoutManager::new()
+            .with_conversion_timeout(Duration::from_millis(50))
+            .with_max_conversion_timeout(Duration::from_millis(100));
+
+        assert_eq!(timeout_manager.conversion_timeout(None), Duration::from_millis(50));
+        assert_eq!(timeout_manager.conversion_timeout(Some(Duration::from_millis(10))), Duration::from_millis(10));
+        // A peer can't ask for more than the maximum
+        assert_eq!(timeout_manager.conversion_timeout(Some(Duration::from_secs(3600))), Duration::from_millis(100));
+
+        let result = timeout_manager.execute_conversion_operation_within(
+            "slow_conversion",
+            Some(Duration::from_secs(3600)),
+            || async {
+                sleep(Duration::from_millis(500)).await;
+                Ok::<(), P2PError>(())
+            }
+        ).await;
+        match result {
+            Err(P2PError::Conversion(ConversionError::ConversionTimeout { duration })) => {
+                assert_eq!(duration, Duration::from_millis(100));
+            }
+            other => panic!("expected ConversionTimeout, got {:?}", other),
+        }
+
+        let quick = timeout_manager.execute_conversion_operation_within(
+            "quick_conversion",
+            Some(Duration::from_millis(80)),
+            || async { Ok::<u8, P2PError>(7) }
+        ).await;
+        assert_eq!(quick.unwrap(), 7);
+    }
+
     #[tokio::test]
     async fn test_resource_guard() {
         use cleanup::ResourceGuard;
@@ -1478,6 +2062,34 @@ mod tests {
         assert!(cleanup_called.load(std::sync::atomic::Ordering::SeqCst));
     }
 
+    #[tokio::test]
+    async fn test_guard_cleans_up_aborted_transfer() {
+        let manager = Arc::new(cleanup::CleanupManager::new());
+        let temp_dir = TempDir::new().unwrap();
+        let partial = temp_dir.path().join("upload.txt.part");
+        std::fs::write(&partial, b"half").unwrap();
+
+        let task_manager = manager.clone();
+        let task_partial = partial.clone();
+        let transfer = tokio::spawn(async move {
+            let _guard = task_manager.guard(
+                "transfer:abc".to_string(),
+                "Transfer abc".to_string(),
+                move || { let _ = std::fs::remove_file(&task_partial); },
+            );
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(manager.check_leaks().await, vec!["transfer:abc".to_string()]);
+
+        transfer.abort();
+        let _ = transfer.await;
+
+        assert!(manager.check_leaks().await.is_empty());
+        assert!(!partial.exists());
+    }
+
     #[tokio::test]
     async fn test_error_formatting() {
         let formatter = display::ErrorFormatter::new();
@@ -1489,6 +2101,44 @@ mod tests {
         let formatted = formatter.format_error(&error);
         assert!(formatted.contains("File not found"));
         assert!(formatted.contains("Suggestion"));
+
+        let error = P2PError::FileIO(FileIOError::DirectoryCreation {
+            path: PathBuf::from("/srv/converted"),
+            reason: "Permission denied (os error 13)".to_string(),
+        });
+        let formatted = formatter.format_error(&error);
+        assert!(formatted.starts_with("Can't create directory /srv/converted: Permission denied"));
+        assert!(formatted.contains("--output/-o"));
+    }
+
+    #[test]
+    fn test_error_json_covers_every_category() {
+        let formatter = display::ErrorFormatter::new();
+        let errors = vec![
+            P2PError::Network(NetworkError::Transport { message: "reset".to_string() }),
+            P2PError::Conversion(ConversionError::FontLoading { font_name: "Sans".to_string(), reason: "missing".to_string() }),
+            P2PError::FileIO(FileIOError::FileLocked { path: PathBuf::from("a.txt") }),
+            P2PError::Validation(ValidationError::RequiredField { field: "target".to_string() }),
+            P2PError::Protocol(ProtocolError::SerializationFailed { reason: "bad".to_string() }),
+            P2PError::Timeout(TimeoutError::UserInput { duration: Duration::from_secs(5) }),
+            P2PError::Resource(ResourceError::LeakDetected { resource: "task".to_string(), count: 1 }),
+            P2PError::Configuration(ConfigurationError::MissingRequired { key: "output_dir".to_string() }),
+        ];
+
+        for error in &errors {
+            let json = formatter.format_error_json(error);
+            assert_eq!(json["category"], error.category());
+            assert_eq!(json["code"], error.code());
+            assert!(error.code().starts_with(&format!("{}.", error.category())));
+            assert!(!json["message"].as_str().unwrap().is_empty());
+            assert!(!json["suggestion"].as_str().unwrap().is_empty(), "{}", error.code());
+            assert!(formatter.format_error(error).contains("Suggestion"));
+        }
+
+        let json = formatter.format_error_json(&P2PError::ValidationErrors(errors));
+        assert_eq!(json["code"], "validation.multiple");
+        assert_eq!(json["errors"].as_array().unwrap().len(), 8);
+        assert_eq!(json["errors"][1]["code"], "conversion.font_loading");
     }
 
     #[tokio::test]
@@ -1535,4 +2185,25 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(attempt_count, 3);
     }
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = recovery::Backoff::new(Duration::from_millis(500), Duration::from_secs(3), 2.0);
+        let delays: Vec<_> = (1..=6).map(|retry| backoff.delay(retry)).collect();
+        assert_eq!(delays, vec![
+            Duration::from_millis(500),
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            Duration::from_secs(3),
+            Duration::from_secs(3),
+            Duration::from_secs(3),
+        ]);
+
+        // Huge retry counts stay at the cap instead of overflowing
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(3));
+
+        // A shrinking multiplier would make later retries faster
+        let flat = recovery::Backoff::new(Duration::from_secs(1), Duration::from_secs(5), 0.5);
+        assert_eq!(flat.delay(4), Duration::from_secs(1));
+    }
 }
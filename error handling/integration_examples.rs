@@ -13,7 +13,7 @@ use libp2p::{Multiaddr, PeerId};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::fs;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 /// Enhanced CLI argument parser with comprehensive validation
 pub mod enhanced_cli {
@@ -44,6 +44,14 @@ pub mod enhanced_cli {
         #[arg(long)]
         pub format: Option<String>,
 
+        /// File with known peer multiaddrs to dial at startup (one per line)
+        #[arg(long, value_name = "FILE")]
+        pub peer_file: Option<String>,
+
+        /// Peers loaded from `peer_file` during validation
+        #[arg(skip)]
+        pub known_peers: Vec<Multiaddr>,
+
         /// Maximum file size in MB
         #[arg(long, default_value = "100")]
         pub max_size: u64,
@@ -56,9 +64,9 @@ pub mod enhanced_cli {
     impl ValidatedArgs {
         /// Parse and validate CLI arguments
         pub async fn parse_and_validate() -> Result<ValidatedArgs> {
-            let args = ValidatedArgs::parse();
+            let mut args = ValidatedArgs::parse();
             let validator = ArgumentValidator::new();
-            validator.validate(&args).await?;
+            args.known_peers = validator.validate(&args).await?;
             Ok(args)
         }
     }
@@ -79,18 +87,25 @@ pub mod enhanced_cli {
             }
         }
 
-        pub async fn validate(&self, args: &ValidatedArgs) -> Result<()> {
+        /// Check every argument, reporting all problems at once rather than
+        /// stopping at the first. Returns the peers read from `--peer-file`,
+        /// so the file is only loaded once.
+        pub async fn validate(&self, args: &ValidatedArgs) -> Result<Vec<Multiaddr>> {
+            let mut errors = Vec::new();
+            let mut known_peers = Vec::new();
+
             // Validate target multiaddr if provided
             if let Some(ref target) = args.target {
-                let multiaddr = self.timeout_manager.execute_network_operation(
+                match self.timeout_manager.execute_network_operation(
                     "validate_multiaddr",
                     None,
                     || async {
                         self.multiaddr_validator.validate(target)
                     }
-                ).await?;
-
-                info!("✅ Valid target address: {}", multiaddr);
+                ).await {
+                    Ok(multiaddr) => info!("✅ Valid target address: {}", multiaddr),
+                    Err(e) => errors.push(e),
+                }
             }
 
             // Validate listen address if provided
@@ -98,23 +113,44 @@ pub mod enhanced_cli {
                 let listen_validator = MultiAddrValidator::new()
                     .with_required_protocols(vec!["ip4".to_string(), "tcp".to_string()]);
 
-                let multiaddr = listen_validator.validate(listen)?;
-                info!("✅ Valid listen address: {}", multiaddr);
+                match listen_validator.validate(listen) {
+                    Ok(multiaddr) => info!("✅ Valid listen address: {}", multiaddr),
+                    Err(e) => errors.push(e),
+                }
+            }
+
+            // Peer file must be readable; bad lines inside it are skipped
+            if let Some(ref peer_file) = args.peer_file {
+                match self.multiaddr_validator.load_peer_file(peer_file).await {
+                    Ok(peers) => {
+                        info!("✅ Peer file {}: {} valid peers", peer_file, peers.len());
+                        known_peers = peers;
+                    }
+                    Err(e) => errors.push(e),
+                }
             }
 
             // Validate file path if provided
             if let Some(ref file_path) = args.file {
-                let validated_path = self.file_validator.validate(file_path).await?;
-
-                // Check file size
-                let file_size = self.file_validator.validate_size(&validated_path, args.max_size * 1_000_000).await?;
-                info!("✅ Valid file: {} ({} bytes)", validated_path.display(), file_size);
+                match self.file_validator.validate(file_path).await {
+                    Ok(validated_path) => {
+                        // Check file size
+                        match self.file_validator.validate_size(&validated_path, args.max_size * 1_000_000).await {
+                            Ok(file_size) => info!("✅ Valid file: {} ({} bytes)", validated_path.display(), file_size),
+                            Err(e) => errors.push(e),
+                        }
+                    }
+                    Err(e) => errors.push(e),
+                }
             }
 
             // Validate output directory
-            self.validate_output_directory(&args.output_dir).await?;
+            if let Err(e) = self.validate_output_directory(&args.output_dir).await {
+                errors.push(e);
+            }
 
-            Ok(())
+            P2PError::aggregate(errors)?;
+            Ok(known_peers)
         }
 
         async fn validate_output_directory(&self, dir: &str) -> Result<()> {
@@ -376,6 +412,27 @@ pub mod enhanced_networking {
             Ok(())
         }
 
+        /// Dial every known peer, logging failures without aborting startup.
+        /// Returns the number of peers that connected.
+        pub async fn connect_to_known_peers(&self, peers: &[Multiaddr]) -> usize {
+            let mut connected = 0;
+
+            for addr in peers {
+                match self.connect_to_peer_with_recovery(&addr.to_string()).await {
+                    Ok((peer_id, _)) => {
+                        debug!("Connected to known peer {}", peer_id);
+                        connected += 1;
+                    }
+                    Err(e) => {
+                        warn!("⚠️  Could not connect to known peer {}: {}", addr, self.format_network_error(&e));
+                    }
+                }
+            }
+
+            info!("🔗 Connected to {}/{} known peers", connected, peers.len());
+            connected
+        }
+
         /// Send file with comprehensive error handling and progress tracking
         pub async fn send_file_with_monitoring<P: AsRef<Path>>(
             &self,
@@ -400,6 +457,9 @@ pub mod enhanced_networking {
                 max_delay: Duration::from_secs(30),
                 backoff_multiplier: 2.0,
                 connection_timeout: Duration::from_secs(15),
+                dial_concurrency: 5,
+                low_reputation_attempts: 2,
+                recover_lost_responses: true,
             };
 
             let mut sender = FileSender::new(Some(retry_config)).await
@@ -491,6 +551,11 @@ pub mod enhanced_application {
             // Register application-level cleanup
             self.setup_cleanup_handlers().await?;
 
+            // Dial known peers from --peer-file before doing anything else
+            if !self.args.known_peers.is_empty() {
+                self.network_manager.connect_to_known_peers(&self.args.known_peers).await;
+            }
+
             let result = match (&self.args.target, &self.args.file) {
                 (Some(target), Some(file)) => {
                     self.run_sender_mode(target, file).await
@@ -657,6 +722,8 @@ mod integration_tests {
             listen: None,
             output_dir: "./test_output".to_string(),
             format: None,
+            peer_file: None,
+            known_peers: Vec::new(),
             max_size: 100,
             verbose: false,
         };
@@ -665,6 +732,34 @@ mod integration_tests {
         assert!(validator.validate(&args).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_validation_reports_all_errors() {
+        let validator = enhanced_cli::ArgumentValidator::new();
+        let temp_dir = TempDir::new().unwrap();
+
+        let args = enhanced_cli::ValidatedArgs {
+            target: Some("not-a-multiaddr".to_string()),
+            file: Some(temp_dir.path().join("missing.txt").display().to_string()),
+            listen: None,
+            output_dir: temp_dir.path().join("out").display().to_string(),
+            format: None,
+            peer_file: None,
+            known_peers: Vec::new(),
+            max_size: 100,
+            verbose: false,
+        };
+
+        let error = validator.validate(&args).await.unwrap_err();
+        match &error {
+            P2PError::ValidationErrors(errors) => assert_eq!(errors.len(), 2),
+            other => panic!("expected aggregated errors, got {:?}", other),
+        }
+
+        let summary = ErrorFormatter::new().format_error(&error);
+        assert!(summary.starts_with("Found 2 problems:"));
+        assert!(summary.contains("\n  1. ") && summary.contains("\n  2. "));
+    }
+
     #[tokio::test]
     async fn test_enhanced_conversion() {
         let converter = enhanced_conversion::EnhancedFileConverter::new();
@@ -0,0 +1,213 @@
+//! Throughput benchmarks for the chunked transfer path, and for small files
+//! sent inside their request instead.
+//!
+//! The sender benchmark drives `FileSender::send_file_chunks` over a temp
+//! file with no peer attached, with fixed and adaptive chunk sizing, so it
+//! includes the loop's simulated per-chunk network delay. Each group uses a
+//! fixed seed and a long measurement window so a 10% change stands out from
+//! run-to-run noise.
+//!
+//! Run with `cargo bench --bench transfer_benchmarks`.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libp2p::PeerId;
+use p2p_file_converter::file_converter::{FileConverter, PdfConfig};
+use p2p_file_converter::file_sender::FileSender;
+use p2p_file_converter::p2p_stream_handler::{
+    ActiveTransfer, FileChunk, FileTransferRequest, ProtocolVersion, RequestKind,
+    DEFAULT_MAX_INLINE_RESULT, DEFAULT_MAX_RESPONSE_SIZE, DEFAULT_PRIORITY,
+};
+use tempfile::NamedTempFile;
+
+/// Size of the payload pushed through each benchmark
+const PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+const CHUNK_SIZES: &[usize] = &[16 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+/// File sizes around `DEFAULT_INLINE_TRANSFER_SIZE`
+const SMALL_FILE_SIZES: &[usize] = &[256, 4 * 1024, 64 * 1024, 256 * 1024];
+
+/// Deterministic, incompressible-looking test data
+fn payload(len: usize) -> Vec<u8> {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn request(file_size: usize, chunk_size: usize) -> FileTransferRequest {
+    FileTransferRequest {
+        transfer_id: "bench".to_string(),
+        filename: "bench.bin".to_string(),
+        file_size: file_size as u64,
+        file_type: "binary".to_string(),
+        target_format: None,
+        return_result: false,
+        chunk_count: file_size.div_ceil(chunk_size),
+        metadata: HashMap::new(),
+        protocol_version: ProtocolVersion::V1_1,
+        priority: DEFAULT_PRIORITY,
+        is_archive: false,
+        max_inline_result: DEFAULT_MAX_INLINE_RESULT,
+        max_response_size: DEFAULT_MAX_RESPONSE_SIZE as u64,
+        conversion_timeout_ms: None,
+        auth_token: None,
+        kind: RequestKind::Transfer,
+        adaptive_chunks: false,
+        inline_data: None,
+    }
+}
+
+/// A transfer whose only chunk has arrived
+fn received(request: FileTransferRequest, data: Vec<u8>) -> ActiveTransfer {
+    ActiveTransfer {
+        request,
+        total_received: data.len() as u64,
+        received_chunks: HashMap::from([(0, data)]),
+        start_time: Instant::now(),
+        peer_id: PeerId::random(),
+        response_channel: None,
+        memory: Vec::new(),
+        final_chunk: Some(0),
+    }
+}
+
+fn bench_send_chunks(c: &mut Criterion) {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(&payload(PAYLOAD_SIZE)).unwrap();
+    file.flush().unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sender = runtime.block_on(FileSender::new(None)).unwrap();
+
+    let mut group = c.benchmark_group("send_file_chunks");
+    group.throughput(Throughput::Bytes(PAYLOAD_SIZE as u64));
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(10));
+
+    for (name, adaptive) in [("fixed", false), ("adaptive", true)] {
+        sender.set_adaptive_chunks(adaptive);
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(runtime.block_on(sender.bench_send_chunks(file.path())).unwrap()));
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_assemble_file(c: &mut Criterion) {
+    let data = payload(PAYLOAD_SIZE);
+
+    let mut group = c.benchmark_group("assemble_file");
+    group.throughput(Throughput::Bytes(PAYLOAD_SIZE as u64));
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(10));
+
+    for &chunk_size in CHUNK_SIZES {
+        let transfer = ActiveTransfer {
+            request: request(data.len(), chunk_size),
+            received_chunks: data
+                .chunks(chunk_size)
+                .map(<[u8]>::to_vec)
+                .enumerate()
+                .collect(),
+            total_received: data.len() as u64,
+            start_time: Instant::now(),
+            peer_id: PeerId::random(),
+            response_channel: None,
+            memory: Vec::new(),
+            final_chunk: None,
+        };
+
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &transfer, |b, transfer| {
+            b.iter(|| black_box(transfer.assemble_file().unwrap()));
+        });
+    }
+
+    group.finish();
+}
+
+/// Receiver-side work for one small file: decode the offer, the request
+/// and its single chunk, then assemble; or, inline, decode the request and
+/// assemble. Doesn't count the offer's round trip, which inline skips too.
+fn bench_small_files(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_file_transfer");
+    group.sample_size(50);
+    group.measurement_time(Duration::from_secs(5));
+
+    for &size in SMALL_FILE_SIZES {
+        let data = payload(size);
+        group.throughput(Throughput::Bytes(size as u64));
+
+        let chunked_request = request(size, size);
+        let offer = bincode::serialize(&FileTransferRequest { kind: RequestKind::Offer, ..chunked_request.clone() }).unwrap();
+        let chunked_request = bincode::serialize(&chunked_request).unwrap();
+        let chunk = bincode::serialize(&FileChunk {
+            transfer_id: "bench".to_string(),
+            chunk_index: 0,
+            data: data.clone(),
+            is_final: true,
+        }).unwrap();
+        group.bench_function(BenchmarkId::new("chunked", size), |b| {
+            b.iter(|| {
+                black_box(bincode::deserialize::<FileTransferRequest>(&offer).unwrap());
+                let request: FileTransferRequest = bincode::deserialize(&chunked_request).unwrap();
+                let chunk: FileChunk = bincode::deserialize(&chunk).unwrap();
+                black_box(received(request, chunk.data).assemble_file().unwrap())
+            });
+        });
+
+        let inline_request = bincode::serialize(&FileTransferRequest {
+            inline_data: Some(data.clone()),
+            ..request(size, size)
+        }).unwrap();
+        group.bench_function(BenchmarkId::new("inline", size), |b| {
+            b.iter(|| {
+                let mut request: FileTransferRequest = bincode::deserialize(&inline_request).unwrap();
+                let data = request.inline_data.take().unwrap();
+                black_box(received(request, data).assemble_file().unwrap())
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_text_to_pdf(c: &mut Criterion) {
+    let line = "The quick brown fox jumps over the lazy dog. 0123456789\n";
+    let config = PdfConfig::default();
+
+    let mut group = c.benchmark_group("text_to_pdf");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(20));
+
+    for &lines in &[100usize, 1_000, 10_000] {
+        let text = line.repeat(lines);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &text, |b, text| {
+            let mut converter = FileConverter::new();
+            b.iter(|| black_box(converter.text_to_pdf(text, &config).unwrap()));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .warm_up_time(Duration::from_secs(3))
+        .noise_threshold(0.03)
+        .significance_level(0.01);
+    targets = bench_send_chunks, bench_assemble_file, bench_small_files, bench_text_to_pdf
+}
+criterion_main!(benches);
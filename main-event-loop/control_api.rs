@@ -0,0 +1,280 @@
+//! Local control API over a Unix domain socket
+//!
+//! Each connection carries one small HTTP/1.1 request answered with JSON, so
+//! `curl --unix-socket /run/p2p-converter.sock http://localhost/status` works.
+//! Access control is the socket file's permissions: it is created `0600`, so
+//! only the user running the node can connect.
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::{
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+
+/// Suggested socket path for a node run as a system daemon
+pub const DEFAULT_CONTROL_SOCKET: &str = "/run/p2p-converter.sock";
+
+/// Largest request head accepted; control requests carry no body
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// A parsed control request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlRequest {
+    /// HTTP method, upper case
+    pub method: String,
+    /// Request path without any query string
+    pub path: String,
+}
+
+/// Status code and JSON body sent back for a control request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// JSON body
+    pub body: Value,
+}
+
+impl ControlResponse {
+    /// Successful response carrying `body`
+    pub fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    /// Failure with `{"error": message}` as the body
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self { status, body: json!({ "error": message.into() }) }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Answers control requests; the application decides what the endpoints do
+pub type ControlHandler = Arc<dyn Fn(ControlRequest) -> BoxFuture<'static, ControlResponse> + Send + Sync>;
+
+/// Control API listening on a Unix socket. The socket file is removed when
+/// the server is dropped.
+pub struct ControlServer {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Create the socket at `path`, owner-only, and start serving requests
+    pub fn bind(path: &Path, handler: ControlHandler) -> Result<Self> {
+        remove_stale_socket(path)?;
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+        if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)) {
+            let _ = std::fs::remove_file(path);
+            return Err(e).with_context(|| format!("Failed to restrict control socket {}", path.display()));
+        }
+        info!("🔌 Control API listening on {}", path.display());
+
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let handler = Arc::clone(&handler);
+                        tokio::spawn(async move {
+                            if let Err(e) = serve_connection(stream, handler).await {
+                                debug!("Control connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Control socket stopped accepting connections: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { path: path.to_path_buf(), task })
+    }
+
+    /// Path of the socket file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => debug!("Removed control socket {}", self.path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove control socket {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Remove a socket left behind by a node that didn't shut down cleanly.
+/// Refuses to replace anything that isn't a socket, or a socket another
+/// node is still serving.
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to inspect {}", path.display())),
+    };
+
+    if !metadata.file_type().is_socket() {
+        anyhow::bail!("{} exists and is not a socket; not replacing it", path.display());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("Control socket {} is already in use by another process", path.display());
+    }
+
+    warn!("Removing stale control socket {}", path.display());
+    std::fs::remove_file(path)
+        .with_context(|| format!("Failed to remove stale control socket {}", path.display()))
+}
+
+/// Read one request, answer it and close the connection
+async fn serve_connection(mut stream: UnixStream, handler: ControlHandler) -> Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return write_response(&mut stream, &ControlResponse::error(400, "Request too large")).await;
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+
+    let response = match parse_request(&head) {
+        Some(request) => {
+            debug!("Control request: {} {}", request.method, request.path);
+            handler(request).await
+        }
+        None => ControlResponse::error(400, "Malformed request"),
+    };
+    write_response(&mut stream, &response).await
+}
+
+/// Parse the request line of an HTTP/1.x request head
+fn parse_request(head: &[u8]) -> Option<ControlRequest> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?.to_ascii_uppercase();
+    let target = parts.next()?;
+    if !parts.next()?.starts_with("HTTP/1.") || !target.starts_with('/') {
+        return None;
+    }
+
+    let path = target.split('?').next().unwrap_or(target);
+    Some(ControlRequest { method, path: percent_decode(path)? })
+}
+
+/// Decode `%XX` escapes so filenames with spaces can be addressed. Returns
+/// `None` for bad escapes or non-UTF-8 results.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let hex = std::str::from_utf8(hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+async fn write_response(stream: &mut UnixStream, response: &ControlResponse) -> Result<()> {
+    let body = serde_json::to_vec(&response.body)?;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            parse_request(b"GET /status?verbose=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some(ControlRequest { method: "GET".to_string(), path: "/status".to_string() })
+        );
+        assert_eq!(
+            parse_request(b"DELETE /files/my%20notes.txt HTTP/1.1\r\n\r\n").unwrap().path,
+            "/files/my notes.txt"
+        );
+        assert_eq!(parse_request(b"DELETE /files/bad%2 HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request(b"GET status HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request(b"\r\n\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_lifecycle() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("control.sock");
+        let handler: ControlHandler = Arc::new(|request: ControlRequest| {
+            async move { ControlResponse::ok(json!({ "path": request.path })) }.boxed()
+        });
+
+        let server = ControlServer::bind(&path, handler.clone()).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // A second node can't take over a socket that's being served
+        assert!(ControlServer::bind(&path, handler.clone()).is_err());
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream.write_all(b"GET /stats HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.ends_with("{\"path\":\"/stats\"}"));
+
+        drop(server);
+        assert!(!path.exists());
+
+        // Nothing listens on a leftover socket, so the next bind replaces it
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let server = ControlServer::bind(&path, handler).unwrap();
+        drop(server);
+        assert!(!path.exists());
+    }
+}
@@ -22,6 +22,9 @@ impl InteractiveClient {
             max_delay: std::time::Duration::from_secs(10),
             backoff_multiplier: 1.5,
             connection_timeout: std::time::Duration::from_secs(10),
+            dial_concurrency: 5,
+            low_reputation_attempts: 2,
+            recover_lost_responses: true,
         };
 
         let mut sender = FileSender::new(Some(retry_config)).await?;
@@ -9,10 +9,14 @@
 pub mod cli;
 pub mod config;
 pub mod error;
+pub mod error_handling;
 pub mod file_converter;
 pub mod file_sender;
 pub mod p2p_stream_handler;
 pub mod main_event_loop;
+pub mod progress_metrics_system;
+#[cfg(unix)]
+pub mod control_api;
 
 // Re-export commonly used types
 pub use cli::{CliArgs, AppMode};
@@ -22,7 +26,8 @@ pub use file_converter::{FileConverter, FileType, PdfConfig};
 pub use file_sender::{FileSender, RetryConfig, SendProgress, TransferStatus};
 pub use p2p_stream_handler::{
     FileConversionService, FileConversionConfig, P2PFileNode, 
-    TransferProgress, FileTransferRequest, FileTransferResponse
+    TransferProgress, FileTransferRequest, FileTransferResponse, ProtocolVersion,
+    MemoryBudget, MemoryUsage, TransportConfig, DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE,
 };
 pub use main_event_loop::{P2PFileConverter, ShutdownReason, AppState};
 
@@ -32,7 +37,8 @@ pub mod prelude {
         CliArgs, AppMode, Config, P2PError, Result,
         FileConverter, FileType, PdfConfig,
         FileSender, RetryConfig, SendProgress, TransferStatus,
-        FileConversionService, P2PFileNode, TransferProgress,
+        FileConversionService, P2PFileNode, TransferProgress, ProtocolVersion,
+        DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE,
         P2PFileConverter, ShutdownReason, AppState,
     };
 
@@ -18,9 +18,11 @@ use libp2p::{
     swarm::{SwarmEvent, dial_opts::DialOpts},
     Multiaddr, PeerId, Swarm,
 };
+use serde::Serialize;
 use std::{
     collections::HashMap,
-    path::PathBuf,
+    io::IsTerminal,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -38,13 +40,23 @@ use uuid::Uuid;
 // Import all our components
 use crate::{
     cli::{CliArgs, AppMode},
-    file_converter::{FileConverter, FileType, PdfConfig},
-    file_sender::{FileSender, RetryConfig, SendProgress, SendResult, TransferStatus},
+    error_handling::{cleanup::CleanupManager, display::ErrorFormatter, FileIOError, P2PError},
+    file_converter::{FileConverter, FileType, PdfConfig, TextExtractConfig},
+    file_sender::{
+        CompletedRetention, FileSender, RetryConfig, SendProgress, SendResult, SessionConfig, SessionStatus,
+        TransferStatus,
+    },
+    progress_metrics_system::{AdvancedProgressManager, ProgressBarHandle, ProgressConfig},
     p2p_stream_handler::{
-        FileConversionService, FileConversionConfig, FileTransferRequest, 
-        FileTransferResponse, P2PFileNode, TransferProgress,
+        ApprovalRequest, ConversionCacheConfig, ForwardRequest, FileConversionService, FileConversionConfig, FileTransferRequest, 
+        FileTransferResponse, HistoryConfig, P2PFileNode, ReputationConfig, TransferDirection,
+        MemoryUsage, ProtocolVersion, Security, TransferHistoryEntry, TransferProgress, TransportConfig,
+        BandwidthConfig, OutputPermissions, SourceFilter, DEFAULT_STALL_THRESHOLD, load_pdf_config,
+        DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE,
     },
 };
+#[cfg(unix)]
+use crate::control_api::{ControlHandler, ControlRequest, ControlResponse, ControlServer};
 
 /// Shutdown signal types
 #[derive(Debug, Clone)]
@@ -111,7 +123,7 @@ pub struct AppState {
 }
 
 /// Transfer statistics
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct TransferStats {
     pub files_sent: u64,
     pub files_received: u64,
@@ -122,11 +134,63 @@ pub struct TransferStats {
     pub conversion_count: u64,
 }
 
+impl TransferStats {
+    /// Point-in-time copy of the counters
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Per-field change since `prev`. A counter that went backwards was reset
+    /// (e.g. stats reloaded), so its current value is the change since then.
+    pub fn diff(&self, prev: &TransferStats) -> TransferStats {
+        fn delta(current: u64, prev: u64) -> u64 {
+            if current >= prev { current - prev } else { current }
+        }
+
+        TransferStats {
+            files_sent: delta(self.files_sent, prev.files_sent),
+            files_received: delta(self.files_received, prev.files_received),
+            bytes_sent: delta(self.bytes_sent, prev.bytes_sent),
+            bytes_received: delta(self.bytes_received, prev.bytes_received),
+            successful_transfers: delta(self.successful_transfers, prev.successful_transfers),
+            failed_transfers: delta(self.failed_transfers, prev.failed_transfers),
+            conversion_count: delta(self.conversion_count, prev.conversion_count),
+        }
+    }
+}
+
+/// Final summary emitted when the application exits
+#[derive(Debug, Serialize)]
+pub struct ShutdownReport {
+    /// Why the application shut down
+    pub reason: String,
+    /// Process exit code
+    pub exit_code: i32,
+    /// Total uptime in seconds
+    pub uptime_secs: f64,
+    /// Cumulative transfer statistics
+    pub stats: TransferStats,
+    /// Transfers that were still in flight or cancelled at shutdown
+    pub interrupted_transfers: Vec<String>,
+    /// Resources still registered after cleanup
+    pub leaked_resources: Vec<String>,
+    /// Buffered chunk memory, including the peak over the session
+    pub memory: MemoryUsage,
+}
+
+impl ShutdownReport {
+    /// Whether the application drained without interrupting work or leaking resources
+    pub fn is_clean(&self) -> bool {
+        self.interrupted_transfers.is_empty() && self.leaked_resources.is_empty()
+    }
+}
+
 /// Main P2P file converter application
 pub struct P2PFileConverter {
     /// Application state
     state: Arc<AppState>,
-    /// File sender (for sender mode)
+    /// File sender: the transfer in sender mode, outbound sends in
+    /// receiver mode
     file_sender: Option<FileSender>,
     /// P2P node (for receiver mode)
     p2p_node: Option<P2PFileNode>,
@@ -137,8 +201,15 @@ pub struct P2PFileConverter {
     /// Shutdown sender
     shutdown_tx: mpsc::Sender<ShutdownReason>,
     shutdown_rx: mpsc::Receiver<ShutdownReason>,
-    /// Background tasks
-    background_tasks: Vec<JoinHandle<()>>,
+    /// Background tasks and the names they were registered under
+    background_tasks: Vec<(String, JoinHandle<()>)>,
+    /// Tracks long-lived resources so leaks show up in the shutdown report
+    cleanup_manager: Arc<CleanupManager>,
+    /// Control API socket; removed when dropped at shutdown
+    #[cfg(unix)]
+    control_server: Option<ControlServer>,
+    /// Per-transfer progress bars (sender mode on a terminal)
+    progress_bars: Option<Arc<AdvancedProgressManager>>,
 }
 
 impl P2PFileConverter {
@@ -147,8 +218,16 @@ impl P2PFileConverter {
         // Parse CLI arguments and determine mode
         let (args, mode) = CliArgs::parse_args()?;
 
+        // Senders on a terminal get progress bars; logging then goes through
+        // the bars so lines are printed above them instead of across them
+        let progress_bars = (matches!(mode, AppMode::Sender { .. }) && std::io::stderr().is_terminal())
+            .then(|| Arc::new(AdvancedProgressManager::new(ProgressConfig::default())));
+
         // Setup logging
-        args.setup_logging()?;
+        match progress_bars {
+            Some(ref bars) => args.setup_logging_with_writer(bars.log_writer())?,
+            None => args.setup_logging()?,
+        }
 
         info!("🚀 Starting P2P File Converter");
         args.print_config(&mode);
@@ -173,12 +252,76 @@ impl P2PFileConverter {
         // Create file conversion service
         let conversion_config = FileConversionConfig {
             max_concurrent_transfers: 5,
+            max_transfers_per_peer: args.max_transfers_per_peer,
             output_dir: args.output_dir.clone(),
             auto_convert: true,
             return_results: false,
-            pdf_config: PdfConfig::default(),
+            allowed_target_formats: None,
+            pdf_config: match args.pdf_config {
+                Some(ref path) => load_pdf_config(path)?,
+                None => PdfConfig::default(),
+            },
+            text_config: TextExtractConfig::default(),
+            conversion_limits: Default::default(),
+            reputation: ReputationConfig {
+                persist_path: Some(args.output_dir.join(".peer_reputation.json")),
+                ..ReputationConfig::default()
+            },
+            history: Some(HistoryConfig {
+                path: args.history_file.clone()
+                    .unwrap_or_else(|| args.output_dir.join("transfer_history.jsonl")),
+                ..HistoryConfig::default()
+            }),
+            conversion_cache: args.conversion_cache.clone().map(|dir| ConversionCacheConfig {
+                dir,
+                ..ConversionCacheConfig::default()
+            }),
+            security: if args.insecure_plaintext { Security::Plaintext } else { Security::Noise },
+            fsync_on_complete: args.fsync,
+            output_permissions: OutputPermissions { mode: args.file_mode, uid: args.file_uid, gid: args.file_gid },
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            memory_budget_bytes: args.memory_budget_mb * 1024 * 1024,
+            min_free_memory: args.min_free_memory_mb * 1024 * 1024,
+            replace_original: args.replace_original,
+            peer_target_formats: HashMap::new(),
+            accepted_source_types: None,
+            original_retention: None,
+            converted_retention: None,
+            converted_ttl: args.converted_ttl_secs.map(Duration::from_secs),
+            transport: Default::default(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_concurrent_conversions: 1,
+            extract_archives: false,
+            lossy_utf8: false,
+            verify_output: true,
+            max_inline_result_bytes: 16 * 1024 * 1024,
+            transfer_log_dir: args.transfer_logs.clone(),
+            keep_transfer_logs: args.keep_transfer_logs,
+            resume_dir: args.resume_dir.clone(),
+            resume_max_age: Duration::from_secs(args.resume_max_age_secs),
+            same_format: Default::default(),
+            default_target_format: args.default_target_format.clone(),
+            // Only receivers convert, so only they need the font up front
+            preload_fonts: matches!(mode, AppMode::Receiver { .. }),
+            conversion_timeout: Duration::from_secs(120),
+            max_conversion_timeout: Duration::from_secs(600),
+            handshake_timeout: Duration::from_secs(10),
+            source_filter: SourceFilter { allowed: args.allowed_sources.clone(), allow_loopback: !args.deny_loopback },
+            require_conversion: args.require_conversion,
+            keep_failed_originals: !args.discard_failed_originals,
+            auth_token: args.auth_token.clone(),
+            bandwidth: BandwidthConfig {
+                upload: args.max_upload_kbps.map(|kb| kb * 1024),
+                download: args.max_download_kbps.map(|kb| kb * 1024),
+                per_peer: args.max_peer_kbps.map(|kb| kb * 1024),
+            },
+            max_substreams_per_connection: 8,
+            max_inbound_substreams: 128,
+            forward_to: args.forward_to.as_ref().map(|addr| peer_target(addr)).transpose()?,
+            relisten: args.relisten,
         };
-        let conversion_service = Arc::new(FileConversionService::new(conversion_config)?);
+        let mut conversion_service = Arc::new(FileConversionService::new(conversion_config.clone())?);
 
         // Initialize sender or receiver based on mode
         let (file_sender, p2p_node) = match &mode {
@@ -190,17 +333,48 @@ impl P2PFileConverter {
                     max_delay: Duration::from_secs(30),
                     backoff_multiplier: 2.0,
                     connection_timeout: Duration::from_secs(15),
+                    dial_concurrency: 5,
+                    low_reputation_attempts: 2,
+                    recover_lost_responses: true,
                 };
-                let sender = FileSender::new(Some(retry_config)).await?;
+                // Same socket options as the receiving side, plus port reuse for dialing
+                let transport = TransportConfig {
+                    port_reuse: true,
+                    ..conversion_config.transport.clone()
+                };
+                let mut sender = FileSender::with_transport(Some(retry_config), conversion_config.security, transport).await?;
+                sender.set_reputation_store(conversion_service.reputation_store());
+                sender.set_cleanup_manager(conversion_service.cleanup_manager());
+                sender.set_memory_budget(conversion_service.memory_budget());
+                sender.set_bandwidth(conversion_service.bandwidth());
+                sender.set_auth_token(args.auth_token.clone());
+                sender.set_completed_retention(completed_retention(&args));
                 (Some(sender), None)
             }
             AppMode::Receiver { .. } => {
                 info!("📥 Initializing receiver mode");
+                // Outbound sends (the `send` command, and forwarding on a
+                // relay) go through a sender of their own
+                let transport = TransportConfig {
+                    port_reuse: true,
+                    ..conversion_config.transport.clone()
+                };
+                let mut outbound = FileSender::with_transport(None, conversion_config.security, transport).await?;
+                outbound.set_auth_token(args.auth_token.clone());
                 let node = P2PFileNode::new(conversion_config).await?;
-                (None, Some(node))
+                // Share the node's service so commands act on its inbound transfers
+                conversion_service = node.service();
+                // Outbound chunks count against the same memory and bandwidth
+                // limits as inbound ones
+                outbound.set_memory_budget(conversion_service.memory_budget());
+                outbound.set_bandwidth(conversion_service.bandwidth());
+                (Some(outbound), Some(node))
             }
         };
 
+        // Shared with the sender/service so interrupted transfers show up as leaks
+        let cleanup_manager = conversion_service.cleanup_manager();
+
         Ok(Self {
             state,
             file_sender,
@@ -210,6 +384,10 @@ impl P2PFileConverter {
             shutdown_tx,
             shutdown_rx,
             background_tasks: Vec::new(),
+            cleanup_manager,
+            #[cfg(unix)]
+            control_server: None,
+            progress_bars,
         })
     }
 
@@ -217,6 +395,12 @@ impl P2PFileConverter {
     pub async fn run(&mut self) -> Result<i32> {
         info!("🔄 Starting main event loop");
 
+        // Bind the control socket first so a bad path fails before any work starts
+        #[cfg(unix)]
+        if let Some(path) = self.state.args.control_socket.clone() {
+            self.control_server = Some(ControlServer::bind(&path, self.control_handler())?);
+        }
+
         // Start background tasks
         self.start_background_tasks().await?;
 
@@ -230,14 +414,27 @@ impl P2PFileConverter {
         });
 
         // Run mode-specific initialization
-        match &self.state.mode {
+        let result = match self.state.mode.clone() {
             AppMode::Sender { target_addr, file_path, .. } => {
-                self.run_sender_mode(target_addr.clone(), file_path.clone()).await
+                self.run_sender_mode(target_addr, file_path).await
             }
             AppMode::Receiver { listen_addr, .. } => {
-                self.run_receiver_mode(listen_addr.clone()).await
+                self.run_receiver_mode(listen_addr).await
             }
-        }
+        };
+
+        // Always clean up and report, including on the error path
+        let exit_code = match &result {
+            Ok(code) => *code,
+            Err(e) => {
+                self.state.shutdown_requested.write().await
+                    .get_or_insert(ShutdownReason::Error(e.to_string()));
+                1
+            }
+        };
+        self.cleanup_background_tasks(exit_code).await;
+
+        result
     }
 
     /// Run sender mode - send file and exit
@@ -254,7 +451,13 @@ impl P2PFileConverter {
         // Setup progress callback
         let event_tx = self.event_tx.clone();
         let state = Arc::clone(&self.state);
+        let progress_bars = self.progress_bars.clone();
+        let bar_handles: std::sync::Mutex<HashMap<String, ProgressBarHandle>> = Default::default();
         sender.set_progress_callback(move |progress| {
+            if let Some(ref manager) = progress_bars {
+                update_progress_bar(manager, &mut bar_handles.lock().unwrap(), progress);
+            }
+
             // Update state
             let state = Arc::clone(&state);
             let event_tx = event_tx.clone();
@@ -267,6 +470,10 @@ impl P2PFileConverter {
             });
         });
 
+        // Keep the link to the receiver up between transfers: a dropped
+        // connection is redialed and the transfer resumes instead of failing
+        sender.open_session(peer_id, target_addr.clone(), SessionConfig::default()).await?;
+
         // Start sender event loop in background
         let sender_handle = tokio::spawn(async move {
             if let Err(e) = sender.run().await {
@@ -278,12 +485,14 @@ impl P2PFileConverter {
         sleep(Duration::from_millis(100)).await;
 
         // Initiate file transfer
-        let transfer_id = match sender.send_file(
+        let mut transfer_id = match sender.send_file_prioritized(
             peer_id,
             target_addr.clone(),
             &file_path,
             self.state.args.target_format.clone(),
             false, // Don't return result for CLI mode
+            HashMap::new(),
+            self.state.args.priority,
         ).await {
             Ok(id) => {
                 info!("✅ Transfer initiated: {}", id);
@@ -291,6 +500,7 @@ impl P2PFileConverter {
             }
             Err(e) => {
                 error!("❌ Failed to initiate transfer: {}", e);
+                *self.state.shutdown_requested.write().await = Some(ShutdownReason::Error(e.to_string()));
                 sender_handle.abort();
                 return Ok(1);
             }
@@ -299,6 +509,10 @@ impl P2PFileConverter {
         // Main event loop for sender mode
         let mut exit_code = 0;
         let mut transfer_completed = false;
+        // Kept after a failure so `retry` can re-send it; interactive
+        // sessions stay open instead of exiting on failure
+        let mut last_failed: Option<RetryableSend> = None;
+        let interactive = std::io::stdin().is_terminal();
 
         loop {
             select! {
@@ -306,6 +520,7 @@ impl P2PFileConverter {
                 shutdown_reason = self.shutdown_rx.recv() => {
                     if let Some(reason) = shutdown_reason {
                         info!("🛑 Shutdown requested: {:?}", reason);
+                        *self.state.shutdown_requested.write().await = Some(reason.clone());
                         match reason {
                             ShutdownReason::UserInterrupt => {
                                 warn!("Cancelling transfer due to user interrupt");
@@ -332,7 +547,7 @@ impl P2PFileConverter {
                 line = self.read_user_input() => {
                     if let Some(input) = line {
                         match input.trim() {
-                            "status" => self.print_transfer_status().await,
+                            "status" => self.print_transfer_status(&sender.sessions().await).await,
                             "cancel" => {
                                 info!("🚫 Cancelling transfer by user request");
                                 if let Err(e) = sender.cancel_transfer(&transfer_id).await {
@@ -340,11 +555,51 @@ impl P2PFileConverter {
                                 }
                                 let _ = self.shutdown_tx.send(ShutdownReason::UserCommand).await;
                             }
+                            cmd if cmd.starts_with("cancel ") => {
+                                let candidates = sender.get_all_progress().await
+                                    .into_iter()
+                                    .map(|progress| (progress.transfer_id, progress.file_path))
+                                    .collect();
+                                let targets = select_cancel_targets(cmd["cancel ".len()..].trim(), candidates);
+                                for id in &targets {
+                                    info!("🚫 Cancelling transfer {} by user request", id);
+                                    if let Err(e) = sender.cancel_transfer(id).await {
+                                        warn!("Failed to cancel transfer {}: {}", id, e);
+                                    }
+                                }
+                                if targets.contains(&transfer_id) {
+                                    let _ = self.shutdown_tx.send(ShutdownReason::UserCommand).await;
+                                }
+                            }
+                            "retry" => {
+                                if let Some(failed) = take_retry(&mut last_failed) {
+                                    // Each attempt gets a fresh transfer id
+                                    match sender.send_file_prioritized(
+                                        failed.peer_id,
+                                        failed.addr.clone(),
+                                        &failed.file_path,
+                                        failed.target_format.clone(),
+                                        false,
+                                        HashMap::new(),
+                                        self.state.args.priority,
+                                    ).await {
+                                        Ok(id) => {
+                                            info!("🔁 Retrying {} as transfer {}", failed.file_path.display(), id);
+                                            transfer_id = id;
+                                            transfer_completed = false;
+                                        }
+                                        Err(e) => {
+                                            error!("❌ Failed to re-initiate transfer: {}", e);
+                                            last_failed = Some(failed);
+                                        }
+                                    }
+                                }
+                            }
                             "quit" | "exit" => {
                                 let _ = self.shutdown_tx.send(ShutdownReason::UserCommand).await;
                             }
                             _ => {
-                                info!("Available commands: status, cancel, quit");
+                                info!("Available commands: status, cancel [<file>|<transfer-id>], retry, quit");
                             }
                         }
                     }
@@ -356,13 +611,26 @@ impl P2PFileConverter {
                         match sender.wait_for_completion(&transfer_id).await {
                             Ok(result) => {
                                 transfer_completed = true;
+                                let success = result.success;
+                                let error = result.error.clone();
                                 self.handle_transfer_result(result).await;
 
-                                if result.success {
+                                if success {
+                                    last_failed = None;
                                     let _ = self.shutdown_tx.send(ShutdownReason::TransferComplete).await;
                                 } else {
-                                    let error_msg = result.error.unwrap_or_else(|| "Unknown error".to_string());
-                                    let _ = self.shutdown_tx.send(ShutdownReason::Error(error_msg)).await;
+                                    last_failed = Some(RetryableSend {
+                                        peer_id,
+                                        addr: target_addr.clone(),
+                                        file_path: file_path.clone(),
+                                        target_format: self.state.args.target_format.clone(),
+                                    });
+                                    if interactive {
+                                        info!("Type 'retry' to re-send {} or 'quit' to exit", file_path.display());
+                                    } else {
+                                        let error_msg = error.unwrap_or_else(|| "Unknown error".to_string());
+                                        let _ = self.shutdown_tx.send(ShutdownReason::Error(error_msg)).await;
+                                    }
                                 }
                             }
                             Err(e) => {
@@ -374,9 +642,15 @@ impl P2PFileConverter {
             }
         }
 
-        // Cleanup
+        // Quitting with an un-retried failure still reports it
+        if exit_code == 0 && last_failed.is_some() {
+            exit_code = 1;
+        }
+
+        // Cleanup: aborted transfer tasks drop their tracking entries
+        sender.abort_transfers().await;
         sender_handle.abort();
-        self.cleanup_background_tasks().await;
+        let _ = sender_handle.await;
 
         info!("👋 Sender mode completed with exit code: {}", exit_code);
         Ok(exit_code)
@@ -401,7 +675,22 @@ impl P2PFileConverter {
         sleep(Duration::from_millis(500)).await;
 
         info!("🌐 P2P node listening for incoming connections");
-        info!("📋 Commands: status, peers, stats, quit");
+        info!("📋 Commands: status, peers, stats, cancel <file>, send <file> <addr> [format], quit");
+
+        // Incoming transfers wait here for the operator when prompting is on
+        let prompt_timeout = self.state.args.accept_prompt.map(Duration::from_secs);
+        let mut approvals = prompt_timeout.map(|timeout| self.conversion_service.request_approvals(timeout));
+        let mut pending_approvals: Vec<ApprovalRequest> = Vec::new();
+
+        // Outbound sends run one at a time until the node stops, so they
+        // never take more than one transfer's worth of the node's capacity
+        if let Some(ref target) = self.state.args.forward_to {
+            info!("↪️  Forwarding converted files to {}", target.0);
+        }
+        let (sends_tx, sends_rx) = mpsc::channel(16);
+        let outbound_handle = self.file_sender.take().map(|sender| {
+            tokio::spawn(outbound_sends(sender, self.conversion_service.request_forwards(), sends_rx))
+        });
 
         // Main event loop for receiver mode
         let mut exit_code = 0;
@@ -412,6 +701,7 @@ impl P2PFileConverter {
                 shutdown_reason = self.shutdown_rx.recv() => {
                     if let Some(reason) = shutdown_reason {
                         info!("🛑 Shutdown requested: {:?}", reason);
+                        *self.state.shutdown_requested.write().await = Some(reason.clone());
                         match reason {
                             ShutdownReason::UserInterrupt | ShutdownReason::UserCommand => {
                                 info!("👋 Graceful shutdown initiated");
@@ -427,9 +717,30 @@ impl P2PFileConverter {
                     }
                 }
 
+                // Offer an incoming transfer to the operator
+                Some(request) = next_approval(&mut approvals) => {
+                    print_transfer_offer(&request, prompt_timeout.unwrap_or_default());
+                    pending_approvals.push(request);
+                }
+
                 // Handle user input (interactive commands)
                 line = self.read_user_input() => {
                     if let Some(input) = line {
+                        if answer_approval(&mut pending_approvals, input.trim()) {
+                            continue;
+                        }
+                        if let Some(args) = input.trim().strip_prefix("send ") {
+                            match parse_send_args(args) {
+                                Ok(send) => {
+                                    println!("📤 Queued {} for {}", send.path.display(), send.peer_id);
+                                    if sends_tx.send(send).await.is_err() {
+                                        error!("Outbound sender is not running");
+                                    }
+                                }
+                                Err(e) => println!("❌ {}", e),
+                            }
+                            continue;
+                        }
                         if let Err(e) = self.handle_user_command(input.trim()).await {
                             error!("Command error: {}", e);
                         }
@@ -444,8 +755,18 @@ impl P2PFileConverter {
         }
 
         // Cleanup
+        if approvals.is_some() {
+            self.conversion_service.stop_approvals();
+        }
+        if let Some(handle) = outbound_handle {
+            handle.abort();
+        }
         node_handle.abort();
-        self.cleanup_background_tasks().await;
+        let _ = node_handle.await;
+        let interrupted = self.conversion_service.discard_interrupted_transfers().await;
+        if !interrupted.is_empty() {
+            warn!("Discarded {} incomplete incoming transfer(s)", interrupted.len());
+        }
 
         info!("👋 Receiver mode completed with exit code: {}", exit_code);
         Ok(exit_code)
@@ -455,8 +776,9 @@ impl P2PFileConverter {
     async fn start_background_tasks(&mut self) -> Result<()> {
         info!("🔧 Starting background tasks");
 
-        // Progress monitoring task
+        // Progress monitoring task; progress bars already show the same
         let state = Arc::clone(&self.state);
+        let bars_shown = self.progress_bars.is_some();
         let progress_task = tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(5));
 
@@ -470,7 +792,7 @@ impl P2PFileConverter {
 
                 // Print active transfer progress
                 let transfers = state.active_transfers.read().await;
-                if !transfers.is_empty() {
+                if !transfers.is_empty() && !bars_shown {
                     info!("📊 Active transfers: {}", transfers.len());
                     for (id, progress) in transfers.iter() {
                         info!("  {} -> {:.1}% complete ({} KB/s)", 
@@ -481,12 +803,14 @@ impl P2PFileConverter {
                 }
             }
         });
-        self.background_tasks.push(progress_task);
+        self.track_background_task("progress_task", progress_task).await;
 
         // Statistics collection task
         let state = Arc::clone(&self.state);
         let stats_task = tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(60));
+            let report_every = Duration::from_secs(60);
+            let mut interval = interval(report_every);
+            let mut previous = state.transfer_stats.read().await.snapshot();
 
             loop {
                 interval.tick().await;
@@ -496,18 +820,26 @@ impl P2PFileConverter {
                 }
 
                 // Log periodic statistics
-                let stats = state.transfer_stats.read().await;
+                let stats = state.transfer_stats.read().await.snapshot();
+                let delta = stats.diff(&previous);
                 let uptime = state.start_time.elapsed();
 
                 info!("📈 Statistics (uptime: {:?})", uptime);
+                info!("  In the last {}s: {} files, {} bytes, {} failed",
+                      report_every.as_secs(),
+                      delta.files_sent + delta.files_received,
+                      delta.bytes_sent + delta.bytes_received,
+                      delta.failed_transfers);
                 info!("  Files sent: {}, received: {}", stats.files_sent, stats.files_received);
                 info!("  Bytes sent: {}, received: {}", stats.bytes_sent, stats.bytes_received);
                 info!("  Success rate: {}/{} transfers", 
                       stats.successful_transfers, 
                       stats.successful_transfers + stats.failed_transfers);
+
+                previous = stats;
             }
         });
-        self.background_tasks.push(stats_task);
+        self.track_background_task("stats_task", stats_task).await;
 
         // Peer discovery monitoring
         let state = Arc::clone(&self.state);
@@ -527,12 +859,20 @@ impl P2PFileConverter {
                 }
             }
         });
-        self.background_tasks.push(peer_task);
+        self.track_background_task("peer_task", peer_task).await;
 
         info!("✅ Background tasks started");
         Ok(())
     }
 
+    /// Keep a background task handle and register it with the cleanup manager
+    async fn track_background_task(&mut self, name: &str, handle: JoinHandle<()>) {
+        self.cleanup_manager
+            .register_resource(format!("task:{}", name), format!("Background task {}", name))
+            .await;
+        self.background_tasks.push((name.to_string(), handle));
+    }
+
     /// Read user input asynchronously
     async fn read_user_input(&self) -> Option<String> {
         // Use a separate task to handle blocking stdin read
@@ -559,6 +899,11 @@ impl P2PFileConverter {
                 println!("  status   - Show current status");
                 println!("  peers    - List connected peers");
                 println!("  stats    - Show transfer statistics");
+                println!("  history  - Show recent transfers");
+                println!("  cancel <file|id> - Cancel an incoming transfer (--all for every match)");
+                println!("  stalled [secs] [--cancel] - List transfers idle for secs (default 60), optionally cancelling them");
+                println!("  accept|reject [id] - Answer a transfer prompt (y/n answers the oldest)");
+                println!("  send <file> <addr> [format] - Send a file to another node (addr must end in /p2p/<peer id>)");
                 println!("  quit     - Exit the application");
             }
             "status" => {
@@ -570,6 +915,43 @@ impl P2PFileConverter {
             "stats" => {
                 self.print_statistics().await;
             }
+            "history" => {
+                self.print_history(10).await;
+            }
+            cmd if cmd == "stalled" || cmd.starts_with("stalled ") => {
+                match parse_stalled_args(&cmd["stalled".len()..]) {
+                    Ok((threshold, true)) => {
+                        let cancelled = self.conversion_service.cancel_stalled_transfers(threshold).await;
+                        println!("🚫 Cancelled {} stalled transfer(s)", cancelled.len());
+                        for id in cancelled {
+                            println!("    {}", id);
+                        }
+                    }
+                    Ok((threshold, false)) => {
+                        let stalled = self.conversion_service.stalled_transfers(threshold).await;
+                        if stalled.is_empty() {
+                            println!("📊 No transfers idle for {:?}", threshold);
+                        }
+                        for progress in stalled {
+                            println!("  {}  {}  {:.1}% ({}/{} bytes), idle {:.0}s",
+                                     progress.transfer_id, progress.filename, progress.percentage(),
+                                     progress.transferred, progress.total_size,
+                                     progress.idle_for().as_secs_f64());
+                        }
+                    }
+                    Err(e) => println!("❌ {}", e),
+                }
+            }
+            cmd if cmd.starts_with("cancel ") => {
+                let candidates = self.conversion_service.get_transfer_progress().await
+                    .into_iter()
+                    .map(|progress| (progress.transfer_id, PathBuf::from(progress.filename)))
+                    .collect();
+                for id in select_cancel_targets(cmd["cancel ".len()..].trim(), candidates) {
+                    self.conversion_service.cancel_transfer(&id).await?;
+                    println!("🚫 Cancelled transfer {}", id);
+                }
+            }
             "quit" | "exit" => {
                 let _ = self.shutdown_tx.send(ShutdownReason::UserCommand).await;
             }
@@ -589,9 +971,45 @@ impl P2PFileConverter {
         println!("📊 Application Status:");
         println!("  Mode: {:?}", self.state.mode);
         println!("  Uptime: {:?}", uptime);
+        if self.conversion_service.is_reachable() {
+            println!("  Listening on: {} address(es)", self.conversion_service.listen_addrs().len());
+        } else {
+            println!("  Listening on: nothing (incoming transfers can't reach this node)");
+        }
         println!("  Connected peers: {}", peer_count);
         println!("  Active transfers: {}", transfer_count);
         println!("  Output directory: {}", self.state.args.output_dir.display());
+
+        let memory = self.conversion_service.memory_usage();
+        println!("  Chunk memory: {:.1} MB used, {:.1} MB peak, {:.1} MB limit",
+                 memory.used as f64 / 1024.0 / 1024.0,
+                 memory.peak as f64 / 1024.0 / 1024.0,
+                 memory.limit as f64 / 1024.0 / 1024.0);
+
+        let deferred = self.conversion_service.deferred_conversions();
+        if deferred > 0 {
+            println!("  Deferred conversions: {} (waiting for free memory)", deferred);
+        }
+
+        let peers = self.state.connected_peers.read().await;
+        if !peers.is_empty() {
+            println!("  Peer reputation:");
+            for peer_id in peers.keys() {
+                println!("    {}: {:.2}", peer_id, self.conversion_service.reputation(peer_id).await);
+            }
+        }
+    }
+
+    /// Handler answering control API requests against this application
+    #[cfg(unix)]
+    fn control_handler(&self) -> ControlHandler {
+        let state = Arc::clone(&self.state);
+        let service = Arc::clone(&self.conversion_service);
+        Arc::new(move |request| {
+            let state = Arc::clone(&state);
+            let service = Arc::clone(&service);
+            async move { route_control_request(&state, &service, request).await }.boxed()
+        })
     }
 
     /// Print connected peers
@@ -636,8 +1054,41 @@ impl P2PFileConverter {
         }
     }
 
+    /// Recent finished transfers, newest first
+    pub async fn history(&self, limit: usize) -> Result<Vec<TransferHistoryEntry>> {
+        self.conversion_service.history(limit).await
+    }
+
+    /// Print recent transfer history
+    async fn print_history(&self, limit: usize) {
+        match self.history(limit).await {
+            Ok(entries) if entries.is_empty() => println!("📜 No transfer history"),
+            Ok(entries) => {
+                println!("📜 Recent transfers ({}):", entries.len());
+                for entry in entries {
+                    println!("  {} {:?} {} {} ({} bytes, {}ms) {}",
+                             entry.timestamp,
+                             entry.direction,
+                             entry.filename,
+                             entry.peer_id,
+                             entry.bytes,
+                             entry.duration_ms,
+                             if entry.success { "✅" } else { "❌" });
+                    if let Some(error) = entry.error {
+                        println!("    Error: {}", error);
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to read transfer history: {}", e),
+        }
+    }
+
     /// Print transfer status (sender mode)
-    async fn print_transfer_status(&self) {
+    async fn print_transfer_status(&self, sessions: &[SessionStatus]) {
+        for session in sessions {
+            println!("{}", session_status_line(session));
+        }
+
         let transfers = self.state.active_transfers.read().await;
 
         if transfers.is_empty() {
@@ -651,6 +1102,7 @@ impl P2PFileConverter {
                          progress.percentage(), progress.sent_bytes, progress.total_size);
                 println!("    Speed: {:.1} KB/s", progress.speed_bps() / 1024.0);
                 println!("    Status: {}", progress.status_string());
+                println!("    Priority: {}", progress.priority);
 
                 if let Some(eta) = progress.eta_seconds() {
                     println!("    ETA: {:.0} seconds", eta);
@@ -661,6 +1113,24 @@ impl P2PFileConverter {
 
     /// Handle transfer result
     async fn handle_transfer_result(&self, result: SendResult) {
+        if let Some(progress) = self.state.active_transfers.read().await.get(&result.transfer_id) {
+            self.conversion_service.record_history(TransferHistoryEntry {
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                transfer_id: result.transfer_id.clone(),
+                direction: TransferDirection::Sent,
+                peer_id: progress.peer_id.to_string(),
+                filename: progress.file_path.display().to_string(),
+                bytes: result.bytes_sent,
+                duration_ms: result.duration.as_millis() as u64,
+                success: result.success,
+                error: result.error.clone(),
+                metadata: Default::default(),
+            }).await;
+        }
+
         let mut stats = self.state.transfer_stats.write().await;
 
         if result.success {
@@ -669,13 +1139,19 @@ impl P2PFileConverter {
             stats.files_sent += 1;
 
             info!("✅ Transfer {} completed successfully", result.transfer_id);
-            info!("📊 Sent {} bytes in {:?}", result.bytes_sent, result.duration);
+            info!(
+                "📊 Sent {} bytes in {:?} (avg {:.1} KB/s, peak {:.1} KB/s)",
+                result.bytes_sent, result.duration, result.avg_bps / 1024.0, result.peak_bps / 1024.0
+            );
         } else {
             stats.failed_transfers += 1;
 
             let error_msg = result.error.unwrap_or_else(|| "Unknown error".to_string());
             warn!("❌ Transfer {} failed: {}", result.transfer_id, error_msg);
-            warn!("📊 Partial transfer: {} bytes in {:?}", result.bytes_sent, result.duration);
+            warn!(
+                "📊 Partial transfer: {} bytes in {:?} (avg {:.1} KB/s, peak {:.1} KB/s)",
+                result.bytes_sent, result.duration, result.avg_bps / 1024.0, result.peak_bps / 1024.0
+            );
         }
     }
 
@@ -685,15 +1161,10 @@ impl P2PFileConverter {
 
         // Clean up completed transfers
         let mut transfers = self.state.active_transfers.write().await;
-        let mut to_remove = Vec::new();
-
-        for (id, progress) in transfers.iter() {
-            if matches!(progress.status, TransferStatus::Completed | TransferStatus::Failed(_) | TransferStatus::Cancelled) {
-                if progress.start_time.elapsed() > Duration::from_secs(300) {
-                    to_remove.push(id.clone());
-                }
-            }
-        }
+        let finished = transfers.iter().filter_map(|(id, progress)| {
+            progress.finished_at.map(|finished_at| (id.as_str(), finished_at))
+        });
+        let to_remove = completed_retention(&self.state.args).expired(finished, Instant::now());
 
         for id in to_remove {
             transfers.remove(&id);
@@ -714,22 +1185,520 @@ impl P2PFileConverter {
         Err(anyhow::anyhow!("No peer ID found in multiaddr: {}", addr))
     }
 
-    /// Cleanup background tasks
-    async fn cleanup_background_tasks(&mut self) {
+    /// Cleanup background tasks and emit the shutdown report
+    async fn cleanup_background_tasks(&mut self, exit_code: i32) {
         info!("🧹 Cleaning up background tasks");
 
-        // Signal shutdown to all tasks
-        *self.state.shutdown_requested.write().await = Some(ShutdownReason::UserCommand);
+        // Signal shutdown to all tasks, keeping the original reason if one was recorded
+        let reason = self.state.shutdown_requested.write().await
+            .get_or_insert(ShutdownReason::UserCommand)
+            .clone();
 
         // Wait for tasks to complete or abort them
-        for task in self.background_tasks.drain(..) {
+        let tasks: Vec<_> = self.background_tasks.drain(..).collect();
+        for (_, task) in &tasks {
             task.abort();
         }
 
         // Give tasks time to cleanup
         sleep(Duration::from_millis(100)).await;
 
+        // Dropping the server removes its socket file
+        #[cfg(unix)]
+        drop(self.control_server.take());
+
+        for (name, _) in &tasks {
+            self.cleanup_manager.unregister_resource(&format!("task:{}", name)).await;
+        }
+
         info!("✅ Background tasks cleaned up");
+
+        let report = self.build_shutdown_report(reason, exit_code).await;
+        self.emit_shutdown_report(&report).await;
+    }
+
+    /// Collect final statistics, interrupted transfers and leaked resources
+    async fn build_shutdown_report(&self, reason: ShutdownReason, exit_code: i32) -> ShutdownReport {
+        let stats = self.state.transfer_stats.read().await.clone();
+
+        let mut interrupted_transfers: Vec<String> = self.state.active_transfers
+            .read()
+            .await
+            .iter()
+            .filter(|(_, progress)| {
+                !matches!(progress.status, TransferStatus::Completed | TransferStatus::Failed(_))
+            })
+            .map(|(id, progress)| format!("{} ({})", id, progress.file_path.display()))
+            .collect();
+        interrupted_transfers.sort();
+
+        let mut leaked_resources = self.cleanup_manager.check_leaks().await;
+        leaked_resources.sort();
+
+        ShutdownReport {
+            reason: format!("{:?}", reason),
+            exit_code,
+            uptime_secs: self.state.start_time.elapsed().as_secs_f64(),
+            stats,
+            interrupted_transfers,
+            leaked_resources,
+            memory: self.conversion_service.memory_usage(),
+        }
+    }
+
+    /// Log the shutdown report and write it as JSON if requested
+    async fn emit_shutdown_report(&self, report: &ShutdownReport) {
+        info!("📋 Shutdown report ({}):", if report.is_clean() { "clean" } else { "unclean" });
+        info!("  Reason: {}, exit code: {}", report.reason, report.exit_code);
+        info!("  Uptime: {:.1}s", report.uptime_secs);
+        info!("  Files sent: {}, received: {}", report.stats.files_sent, report.stats.files_received);
+        info!("  Bytes sent: {}, received: {}", report.stats.bytes_sent, report.stats.bytes_received);
+        info!("  Transfers: {} succeeded, {} failed",
+              report.stats.successful_transfers, report.stats.failed_transfers);
+        info!("  Peak chunk memory: {} of {} bytes", report.memory.peak, report.memory.limit);
+
+        for transfer in &report.interrupted_transfers {
+            warn!("  Interrupted transfer: {}", transfer);
+        }
+        for resource in &report.leaked_resources {
+            warn!("  Leaked resource: {}", resource);
+        }
+
+        if let Some(ref path) = self.state.args.shutdown_report {
+            let result = serde_json::to_vec_pretty(report)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| std::fs::write(path, json).map_err(anyhow::Error::from));
+
+            match result {
+                Ok(()) => info!("📝 Shutdown report written to {}", path.display()),
+                Err(e) => error!("Failed to write shutdown report to {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+/// Find the transfers a `cancel` argument refers to.
+///
+/// A transfer id (or unambiguous id prefix) takes precedence; otherwise the
+/// argument is compared with each transfer's file name and full path.
+fn match_transfers(query: &str, candidates: &[(String, PathBuf)]) -> Vec<(String, PathBuf)> {
+    if let Some(exact) = candidates.iter().find(|(id, _)| id == query) {
+        return vec![exact.clone()];
+    }
+
+    let by_id: Vec<_> = candidates.iter()
+        .filter(|(id, _)| query.len() >= 4 && id.starts_with(query))
+        .cloned()
+        .collect();
+    if !by_id.is_empty() {
+        return by_id;
+    }
+
+    candidates.iter()
+        .filter(|(_, path)| {
+            path.file_name().map_or(false, |name| name == query) || path == Path::new(query)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Next transfer offer to show, or never if prompting is off
+async fn next_approval(approvals: &mut Option<mpsc::Receiver<ApprovalRequest>>) -> Option<ApprovalRequest> {
+    match approvals {
+        Some(approvals) => approvals.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Peer and address to dial for `addr`, which must end in `/p2p/<peer id>`
+fn peer_target(addr: &Multiaddr) -> Result<(PeerId, Multiaddr)> {
+    addr.iter()
+        .find_map(|protocol| match protocol {
+            libp2p::multiaddr::Protocol::P2p(peer_id) => Some((peer_id, addr.clone())),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("No peer ID found in address: {}", addr))
+}
+
+/// A file the operator asked a receiver to send with the `send` command
+#[derive(Debug, PartialEq)]
+struct OutboundSend {
+    path: PathBuf,
+    peer_id: PeerId,
+    addr: Multiaddr,
+    target_format: Option<String>,
+}
+
+/// Parse `send <path> <multiaddr> [format]`. The path may contain spaces;
+/// a trailing word that isn't an address is the format.
+fn parse_send_args(args: &str) -> std::result::Result<OutboundSend, String> {
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let target_format = match words.last() {
+        Some(word) if words.len() > 2 && !word.starts_with('/') => words.pop().map(str::to_string),
+        _ => None,
+    };
+    let addr = words.pop()
+        .filter(|_| !words.is_empty())
+        .ok_or_else(|| "Usage: send <file> <multiaddr> [format]".to_string())?;
+    let addr: Multiaddr = addr.parse().map_err(|e| format!("Invalid address '{}': {}", addr, e))?;
+    let (peer_id, addr) = peer_target(&addr).map_err(|e| e.to_string())?;
+
+    Ok(OutboundSend { path: PathBuf::from(words.join(" ")), peer_id, addr, target_format })
+}
+
+/// Send one file and wait for the receiver's verdict
+async fn send_and_wait(
+    sender: &mut FileSender,
+    peer_id: PeerId,
+    addr: Multiaddr,
+    path: &Path,
+    target_format: Option<String>,
+    metadata: HashMap<String, String>,
+) -> std::result::Result<(), String> {
+    match sender.send_file_with_metadata(peer_id, addr, path, target_format, false, metadata).await {
+        Ok(id) => match sender.wait_for_completion(&id).await {
+            Ok(result) if result.success => Ok(()),
+            Ok(result) => Err(result.response
+                .and_then(|response| response.error_message)
+                .unwrap_or_else(|| "receiver reported a failure".to_string())),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Run a receiver's outbound sends one at a time: converted files going on
+/// to the relay target, whose outcome is reported back so the original
+/// sender hears about failures, and files queued with the `send` command.
+///
+/// Forwards go first whenever both are waiting, since an original sender
+/// is blocked on each of them; a queued `send` never delays a forward by
+/// more than the one send already running.
+async fn outbound_sends(
+    mut sender: FileSender,
+    mut forwards: mpsc::Receiver<ForwardRequest>,
+    mut sends: mpsc::Receiver<OutboundSend>,
+) {
+    loop {
+        select! {
+            biased;
+            Some(ForwardRequest { transfer_id, path, peer_id, addr, metadata, outcome }) = forwards.recv() => {
+                let result = send_and_wait(&mut sender, peer_id, addr, &path, None, metadata).await;
+                if let Err(ref e) = result {
+                    warn!("Forwarding {} from transfer {} failed: {}", path.display(), transfer_id, e);
+                }
+                let _ = outcome.send(result);
+            }
+            Some(send) = sends.recv() => {
+                info!("📤 Sending {} to {}", send.path.display(), send.peer_id);
+                match send_and_wait(&mut sender, send.peer_id, send.addr, &send.path, send.target_format, HashMap::new()).await {
+                    Ok(()) => info!("✅ Sent {} to {}", send.path.display(), send.peer_id),
+                    Err(e) => warn!("❌ Sending {} to {} failed: {}", send.path.display(), send.peer_id, e),
+                }
+            }
+            else => break,
+        }
+    }
+}
+
+/// Show an incoming transfer waiting for a decision
+fn print_transfer_offer(request: &ApprovalRequest, timeout: Duration) {
+    let offer = &request.offer;
+    println!(
+        "📨 Incoming transfer {} from {}: {} ({:.1} KB{})",
+        offer.transfer_id,
+        offer.peer_id,
+        offer.filename,
+        offer.file_size as f64 / 1024.0,
+        offer.target_format.as_deref().map(|format| format!(", convert to {}", format)).unwrap_or_default()
+    );
+    println!(
+        "   Accept? [y/n] (or 'accept <id>' / 'reject <id>'; rejected after {}s)",
+        timeout.as_secs()
+    );
+}
+
+/// One status line for a sender session, flagging it when the link is down
+fn session_status_line(session: &SessionStatus) -> String {
+    let icon = if session.state.is_connected() { "🔗" } else { "⚠️" };
+    let mut line = format!(
+        "{} Session {} ({}): {}",
+        icon, session.peer_id, session.address, session.state.status_string()
+    );
+    if session.reconnects > 0 {
+        line.push_str(&format!(", {} reconnect(s)", session.reconnects));
+    }
+    line
+}
+
+/// Answer a pending transfer prompt. `y`/`accept` and `n`/`reject` answer
+/// the oldest prompt; with an id (or filename) they answer that one.
+/// Returns false when `command` is not an answer.
+fn answer_approval(pending: &mut Vec<ApprovalRequest>, command: &str) -> bool {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let (accept, query) = match words.as_slice() {
+        ["y" | "yes" | "accept"] => (true, None),
+        ["n" | "no" | "reject"] => (false, None),
+        ["accept", query] => (true, Some(*query)),
+        ["reject", query] => (false, Some(*query)),
+        _ => return false,
+    };
+
+    // Prompts that timed out on the service side can't be answered any more
+    pending.retain(|request| !request.decision.is_closed());
+
+    let index = match query {
+        None if pending.is_empty() => {
+            println!("No transfers are waiting for approval");
+            return true;
+        }
+        None => 0,
+        Some(query) => match pending.iter().position(|request| {
+            request.offer.transfer_id.starts_with(query) || request.offer.filename == query
+        }) {
+            Some(index) => index,
+            None => {
+                println!("❌ No transfer waiting for approval matches '{}'", query);
+                return true;
+            }
+        },
+    };
+
+    let request = pending.remove(index);
+    let filename = request.offer.filename.clone();
+    if request.decision.send(accept).is_err() {
+        println!("⌛ The prompt for {} already expired", filename);
+    } else if accept {
+        println!("✅ Accepted {}", filename);
+    } else {
+        println!("🚫 Rejected {}", filename);
+    }
+    true
+}
+
+/// Retention limits for finished transfers from the command line
+fn completed_retention(args: &CliArgs) -> CompletedRetention {
+    CompletedRetention {
+        max_age: Duration::from_secs(args.completed_retention_secs),
+        max_count: args.max_completed_transfers,
+    }
+}
+
+/// Parameters of a failed send, kept for the `retry` command
+#[derive(Debug, Clone)]
+struct RetryableSend {
+    peer_id: PeerId,
+    addr: Multiaddr,
+    file_path: PathBuf,
+    target_format: Option<String>,
+}
+
+/// Take the last failed send for `retry`, saying so when there is none
+fn take_retry(last_failed: &mut Option<RetryableSend>) -> Option<RetryableSend> {
+    let failed = last_failed.take();
+    if failed.is_none() {
+        println!("ℹ️  Nothing to retry: no transfer has failed");
+    }
+    failed
+}
+
+/// Parse `stalled [secs] [--cancel]` into the idle threshold and whether
+/// to cancel what matches
+fn parse_stalled_args(args: &str) -> std::result::Result<(Duration, bool), String> {
+    let mut threshold = DEFAULT_STALL_THRESHOLD;
+    let mut cancel = false;
+    for arg in args.split_whitespace() {
+        match arg {
+            "--cancel" => cancel = true,
+            secs => {
+                let secs: u64 = secs.parse()
+                    .map_err(|_| format!("Invalid idle time '{}': expected whole seconds", secs))?;
+                threshold = Duration::from_secs(secs);
+            }
+        }
+    }
+    Ok((threshold, cancel))
+}
+
+/// Serve the control API endpoints:
+/// `GET /status`, `GET /stats`, `POST /transfers/{id}/cancel`, which
+/// cancels an incoming transfer like the `cancel` command, and `GET /files`
+/// and `DELETE /files/{name}` for managing the output directory
+#[cfg(unix)]
+async fn route_control_request(
+    state: &AppState,
+    service: &FileConversionService,
+    request: ControlRequest,
+) -> ControlResponse {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["status"]) => {
+            let outgoing: Vec<serde_json::Value> = state.active_transfers.read().await
+                .iter()
+                .map(|(id, progress)| serde_json::json!({
+                    "transfer_id": id,
+                    "file": progress.file_path.display().to_string(),
+                    "sent_bytes": progress.sent_bytes,
+                    "total_size": progress.total_size,
+                    "status": progress.status_string(),
+                }))
+                .collect();
+            let incoming: Vec<serde_json::Value> = service.get_transfer_progress().await
+                .into_iter()
+                .map(|progress| serde_json::json!({
+                    "transfer_id": progress.transfer_id,
+                    "filename": progress.filename,
+                    "peer_id": progress.peer_id.to_string(),
+                    "transferred": progress.transferred,
+                    "total_size": progress.total_size,
+                }))
+                .collect();
+
+            ControlResponse::ok(serde_json::json!({
+                "mode": format!("{:?}", state.mode),
+                "uptime_secs": state.start_time.elapsed().as_secs_f64(),
+                "connected_peers": state.connected_peers.read().await.len(),
+                "reachable": service.is_reachable(),
+                "listen_addrs": service.listen_addrs().iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+                "outgoing": outgoing,
+                "incoming": incoming,
+                "memory": service.memory_usage(),
+            }))
+        }
+        ("GET", ["stats"]) => {
+            let stats = state.transfer_stats.read().await.snapshot();
+            ControlResponse::ok(serde_json::json!({
+                "uptime_secs": state.start_time.elapsed().as_secs_f64(),
+                "stats": stats,
+            }))
+        }
+        ("POST", ["transfers", id, "cancel"]) => {
+            let known = service.get_transfer_progress().await
+                .iter()
+                .any(|progress| progress.transfer_id == *id);
+            if !known {
+                return ControlResponse::error(404, format!("No active transfer with ID {}", id));
+            }
+            match service.cancel_transfer(id).await {
+                Ok(()) => {
+                    info!("🚫 Cancelled transfer {} via control API", id);
+                    ControlResponse::ok(serde_json::json!({ "cancelled": id }))
+                }
+                Err(e) => ControlResponse::error(500, e.to_string()),
+            }
+        }
+        ("GET", ["files"]) => match service.list_output_files().await {
+            Ok(files) => ControlResponse::ok(serde_json::json!({ "files": files })),
+            Err(e) => ControlResponse::error(500, format!("{:#}", e)),
+        },
+        ("DELETE", ["files", name]) => match service.delete_output_file(name).await {
+            Ok(()) => ControlResponse::ok(serde_json::json!({ "deleted": name })),
+            Err(e) => match e.downcast_ref::<FileIOError>() {
+                Some(FileIOError::NotFound { .. }) => {
+                    ControlResponse::error(404, format!("No file named {}", name))
+                }
+                Some(FileIOError::InvalidPath { .. }) => {
+                    warn!("Refused control API deletion of '{}': {}", name, e);
+                    ControlResponse::error(400, e.to_string())
+                }
+                _ => ControlResponse::error(500, format!("{:#}", e)),
+            },
+        },
+        (_, ["status"]) | (_, ["stats"]) | (_, ["transfers", _, "cancel"]) | (_, ["files"]) | (_, ["files", _]) => {
+            ControlResponse::error(405, format!("{} not allowed on {}", request.method, request.path))
+        }
+        _ => ControlResponse::error(404, format!("Unknown endpoint {}", request.path)),
+    }
+}
+
+/// Create, advance or finish the bar for a transfer. Finished bars stay on
+/// screen with their final message; the handle is dropped so it no longer
+/// counts toward the manager's limit.
+fn update_progress_bar(
+    manager: &AdvancedProgressManager,
+    handles: &mut HashMap<String, ProgressBarHandle>,
+    progress: &SendProgress,
+) {
+    let filename = progress.file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| progress.file_path.display().to_string());
+
+    if !handles.contains_key(&progress.transfer_id) {
+        if progress.status.is_terminal() {
+            return;
+        }
+        match manager.create_progress_bar(&progress.transfer_id, "send", progress.total_size, &filename) {
+            Ok(handle) => {
+                handles.insert(progress.transfer_id.clone(), handle);
+            }
+            Err(e) => {
+                debug!("No progress bar for {}: {}", progress.transfer_id, e);
+                return;
+            }
+        }
+    }
+
+    match progress.status {
+        TransferStatus::Completed => {
+            if let Some(handle) = handles.remove(&progress.transfer_id) {
+                handle.update_progress(progress.total_size, None);
+                handle.finish(Some(&format!("✅ {} sent", filename)));
+            }
+        }
+        TransferStatus::Failed(ref error) => {
+            if let Some(handle) = handles.remove(&progress.transfer_id) {
+                handle.finish_with_error(&format!("{}: {}", filename, error));
+            }
+        }
+        TransferStatus::Cancelled => {
+            if let Some(handle) = handles.remove(&progress.transfer_id) {
+                handle.finish_with_error(&format!("{}: cancelled", filename));
+            }
+        }
+        _ => {
+            if let Some(handle) = handles.get(&progress.transfer_id) {
+                handle.update_progress(progress.sent_bytes, Some(&format!("{} {}", filename, progress.status_string())));
+            }
+        }
+    }
+}
+
+/// Resolve `cancel <file|id>` to the transfer ids to cancel.
+///
+/// Prints an explanation and returns nothing when no transfer matches, or
+/// when several do and `--all` was not given.
+fn select_cancel_targets(arg: &str, candidates: Vec<(String, PathBuf)>) -> Vec<String> {
+    let (cancel_all, query) = match arg.split_once(char::is_whitespace) {
+        Some(("--all", rest)) => (true, rest.trim()),
+        _ => (false, arg),
+    };
+
+    let matches = match_transfers(query, &candidates);
+    match matches.len() {
+        0 => {
+            println!("❌ No active transfer matches '{}'", query);
+            if !candidates.is_empty() {
+                println!("  Active transfers:");
+                for (id, path) in &candidates {
+                    println!("    {}  {}", id, path.display());
+                }
+            }
+            Vec::new()
+        }
+        1 => matches.into_iter().map(|(id, _)| id).collect(),
+        n if cancel_all => {
+            info!("Cancelling all {} transfers matching '{}'", n, query);
+            matches.into_iter().map(|(id, _)| id).collect()
+        }
+        n => {
+            println!("⚠️  '{}' matches {} active transfers:", query, n);
+            for (id, path) in &matches {
+                println!("    {}  {}", id, path.display());
+            }
+            println!("  Use 'cancel <transfer-id>' to pick one, or 'cancel --all {}' to cancel all of them", query);
+            Vec::new()
+        }
     }
 }
 
@@ -745,7 +1714,10 @@ impl From<crate::file_sender::SendProgress> for TransferProgress {
             total_size: send_progress.total_size,
             transferred: send_progress.sent_bytes,
             start_time: send_progress.start_time,
+            // Send progress doesn't record this; treat it as current
+            last_progress_at: Instant::now(),
             peer_id: send_progress.peer_id,
+            priority: send_progress.priority,
         }
     }
 }
@@ -764,15 +1736,38 @@ impl From<TransferProgress> for crate::file_sender::SendProgress {
             status: TransferStatus::Sending, // Default status
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: transfer_progress.priority,
+            throughput: Default::default(), // Not available in TransferProgress
+            finished_at: None,
         }
     }
 }
 
+/// Startup failures the operator can fix, as errors `ErrorFormatter` can
+/// explain with a suggestion
+fn startup_error(error: &anyhow::Error) -> Option<P2PError> {
+    match error.downcast_ref::<FileIOError>() {
+        Some(e @ FileIOError::DirectoryCreation { .. }) => Some(P2PError::FileIO(e.clone())),
+        _ => None,
+    }
+}
+
 /// Main application entry point
 #[tokio::main]
 async fn main() -> Result<()> {
     // Create and run the P2P file converter
-    let mut app = P2PFileConverter::new().await?;
+    let mut app = match P2PFileConverter::new().await {
+        Ok(app) => app,
+        Err(e) => match startup_error(&e) {
+            Some(error) => {
+                eprintln!("{}", ErrorFormatter::new().format_error(&error));
+                std::process::exit(1);
+            }
+            None => return Err(e),
+        },
+    };
     let exit_code = app.run().await?;
 
     std::process::exit(exit_code);
@@ -804,6 +1799,219 @@ mod tests {
         assert_eq!(stats.files_received, 0);
     }
 
+    #[test]
+    fn test_transfer_stats_diff() {
+        let prev = TransferStats {
+            files_sent: 3,
+            bytes_sent: 3000,
+            successful_transfers: 3,
+            ..Default::default()
+        };
+        let mut current = prev.snapshot();
+        current.files_sent += 2;
+        current.bytes_sent += 500;
+        current.successful_transfers += 1;
+        current.failed_transfers += 1;
+
+        let delta = current.diff(&prev);
+        assert_eq!(delta.files_sent, 2);
+        assert_eq!(delta.bytes_sent, 500);
+        assert_eq!(delta.successful_transfers, 1);
+        assert_eq!(delta.failed_transfers, 1);
+        assert_eq!(delta.files_received, 0);
+
+        // After a reset the new totals are the delta, never a wrapped value
+        let reset = TransferStats { files_sent: 1, bytes_sent: 100, ..Default::default() };
+        let delta = reset.diff(&current);
+        assert_eq!(delta.files_sent, 1);
+        assert_eq!(delta.bytes_sent, 100);
+        assert_eq!(delta.successful_transfers, 0);
+    }
+
+    #[test]
+    fn test_take_retry() {
+        let mut last_failed = None;
+        assert!(take_retry(&mut last_failed).is_none());
+
+        last_failed = Some(RetryableSend {
+            peer_id: PeerId::random(),
+            addr: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+            file_path: PathBuf::from("report.txt"),
+            target_format: Some("pdf".to_string()),
+        });
+        let failed = take_retry(&mut last_failed).unwrap();
+        assert_eq!(failed.file_path, PathBuf::from("report.txt"));
+        assert!(last_failed.is_none());
+    }
+
+    #[test]
+    fn test_update_progress_bar() {
+        let manager = AdvancedProgressManager::new(ProgressConfig::default());
+        let mut handles = HashMap::new();
+        let mut progress = SendProgress {
+            transfer_id: "bar-test".to_string(),
+            file_path: PathBuf::from("docs/report.txt"),
+            peer_id: PeerId::random(),
+            total_size: 1000,
+            sent_bytes: 250,
+            chunks_sent: 1,
+            total_chunks: 4,
+            start_time: Instant::now(),
+            status: TransferStatus::Sending,
+            connection_attempts: 1,
+            last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: 4,
+            throughput: Default::default(),
+            finished_at: None,
+        };
+
+        update_progress_bar(&manager, &mut handles, &progress);
+        assert_eq!(handles["bar-test"].get_percentage(), 25.0);
+        assert_eq!(manager.get_progress_summary().total_operations, 1);
+
+        progress.status = TransferStatus::Completed;
+        update_progress_bar(&manager, &mut handles, &progress);
+        assert!(handles.is_empty());
+        assert_eq!(manager.get_progress_summary().total_operations, 0);
+
+        // Updates after the end don't bring the bar back
+        update_progress_bar(&manager, &mut handles, &progress);
+        assert!(handles.is_empty());
+    }
+
+    #[test]
+    fn test_shutdown_report_json() {
+        let report = ShutdownReport {
+            reason: format!("{:?}", ShutdownReason::UserInterrupt),
+            exit_code: 130,
+            uptime_secs: 1.5,
+            stats: TransferStats::default(),
+            interrupted_transfers: vec!["abc (test.txt)".to_string()],
+            leaked_resources: Vec::new(),
+            memory: MemoryUsage { used: 0, peak: 2048, limit: 4096 },
+        };
+
+        assert!(!report.is_clean());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["exit_code"], 130);
+        assert_eq!(json["stats"]["files_sent"], 0);
+        assert_eq!(json["memory"]["peak"], 2048);
+    }
+
+    #[test]
+    fn test_cancel_target_matching() {
+        let candidates = vec![
+            ("a1b2c3d4-0001".to_string(), PathBuf::from("/data/report.txt")),
+            ("e5f6a7b8-0002".to_string(), PathBuf::from("/other/report.txt")),
+            ("c9d0e1f2-0003".to_string(), PathBuf::from("notes.txt")),
+        ];
+
+        assert_eq!(select_cancel_targets("notes.txt", candidates.clone()), vec!["c9d0e1f2-0003"]);
+        assert_eq!(select_cancel_targets("e5f6", candidates.clone()), vec!["e5f6a7b8-0002"]);
+        assert_eq!(select_cancel_targets("/data/report.txt", candidates.clone()), vec!["a1b2c3d4-0001"]);
+
+        // Ambiguous names cancel nothing unless --all is given
+        assert!(select_cancel_targets("report.txt", candidates.clone()).is_empty());
+        assert_eq!(select_cancel_targets("--all report.txt", candidates.clone()).len(), 2);
+
+        assert!(select_cancel_targets("missing.txt", candidates).is_empty());
+    }
+
+    #[test]
+    fn test_parse_stalled_args() {
+        assert_eq!(parse_stalled_args(""), Ok((DEFAULT_STALL_THRESHOLD, false)));
+        assert_eq!(parse_stalled_args(" 30"), Ok((Duration::from_secs(30), false)));
+        assert_eq!(parse_stalled_args(" --cancel 5"), Ok((Duration::from_secs(5), true)));
+        assert!(parse_stalled_args(" soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_send_args() {
+        let peer_id = PeerId::random();
+        let addr: Multiaddr = format!("/ip4/10.0.0.2/tcp/4001/p2p/{}", peer_id).parse().unwrap();
+
+        let send = parse_send_args(&format!("notes.txt {}", addr)).unwrap();
+        assert_eq!(send, OutboundSend {
+            path: PathBuf::from("notes.txt"),
+            peer_id,
+            addr: addr.clone(),
+            target_format: None,
+        });
+
+        let send = parse_send_args(&format!("my notes.txt {} pdf", addr)).unwrap();
+        assert_eq!(send.path, PathBuf::from("my notes.txt"));
+        assert_eq!(send.target_format.as_deref(), Some("pdf"));
+
+        assert!(parse_send_args("notes.txt").is_err());
+        assert!(parse_send_args("notes.txt /ip4/10.0.0.2/tcp/4001").is_err());
+        assert!(parse_send_args(&format!("{}", addr)).is_err());
+    }
+
+    #[test]
+    fn test_answer_approval() {
+        use crate::p2p_stream_handler::TransferOffer;
+        use tokio::sync::oneshot;
+
+        let offer = |id: &str| {
+            let (decision, answer) = oneshot::channel();
+            let request = ApprovalRequest {
+                offer: TransferOffer {
+                    transfer_id: id.to_string(),
+                    peer_id: PeerId::random(),
+                    filename: format!("{}.txt", id),
+                    file_size: 1024,
+                    target_format: None,
+                },
+                decision,
+            };
+            (request, answer)
+        };
+
+        let (first, mut first_answer) = offer("aaaa-1111");
+        let (second, mut second_answer) = offer("bbbb-2222");
+        let mut pending = vec![first, second];
+
+        assert!(!answer_approval(&mut pending, "status"));
+        assert!(answer_approval(&mut pending, "reject bbbb"));
+        assert_eq!(second_answer.try_recv(), Ok(false));
+        assert!(answer_approval(&mut pending, "y"));
+        assert_eq!(first_answer.try_recv(), Ok(true));
+        assert!(pending.is_empty());
+
+        // Answers with nothing pending are consumed, not treated as commands
+        assert!(answer_approval(&mut pending, "n"));
+    }
+
+    #[test]
+    fn test_session_status_line() {
+        use crate::file_sender::SessionState;
+
+        let mut session = SessionStatus {
+            peer_id: PeerId::random(),
+            address: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+            state: SessionState::Connected { since: Instant::now() },
+            reconnects: 0,
+        };
+        let line = session_status_line(&session);
+        assert!(line.starts_with("🔗"), "{}", line);
+        assert!(line.contains("Connected"), "{}", line);
+
+        session.state = SessionState::Reconnecting {
+            since: Instant::now(),
+            attempt: 3,
+            retry_at: Instant::now() + Duration::from_secs(5),
+            last_error: Some("connection refused".to_string()),
+        };
+        session.reconnects = 2;
+        let line = session_status_line(&session);
+        assert!(line.starts_with("⚠️"), "{}", line);
+        assert!(line.contains("reconnect 3"), "{}", line);
+        assert!(line.contains("connection refused"), "{}", line);
+        assert!(line.ends_with("2 reconnect(s)"), "{}", line);
+    }
+
     #[test]
     fn test_event_loop_event_debug() {
         let event = EventLoopEvent::UserInput("test".to_string());
@@ -5,6 +5,7 @@
 use anyhow::Result;
 use p2p_file_converter::prelude::*;
 use std::env;
+use std::time::Duration;
 use tokio::signal;
 
 #[tokio::main]
@@ -31,12 +32,58 @@ async fn main() -> Result<()> {
         auto_convert: true,
         return_results: false,
         max_concurrent_transfers: 3,
+        max_transfers_per_peer: None,
         pdf_config: PdfConfig {
             title: "Received Document".to_string(),
             font_size: 12,
             margins: 20,
             ..Default::default()
         },
+        reputation: Default::default(),
+        text_config: Default::default(),
+        conversion_limits: Default::default(),
+        allowed_target_formats: None,
+        history: None,
+        security: Default::default(),
+        fsync_on_complete: false,
+        output_permissions: Default::default(),
+        protocol_versions: ProtocolVersion::ALL.to_vec(),
+        memory_budget_bytes: 256 * 1024 * 1024,
+        replace_original: false,
+        peer_target_formats: Default::default(),
+        accepted_source_types: None,
+        conversion_cache: None,
+        original_retention: None,
+        converted_retention: None,
+        converted_ttl: None,
+        transport: Default::default(),
+        max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+        max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+        max_concurrent_conversions: 1,
+        extract_archives: false,
+        lossy_utf8: false,
+        verify_output: true,
+        min_free_memory: 0,
+        max_inline_result_bytes: 16 * 1024 * 1024,
+        transfer_log_dir: None,
+        keep_transfer_logs: false,
+        resume_dir: None,
+        resume_max_age: Duration::from_secs(24 * 60 * 60),
+        same_format: Default::default(),
+        default_target_format: None,
+        preload_fonts: false,
+        conversion_timeout: Duration::from_secs(120),
+        max_conversion_timeout: Duration::from_secs(600),
+        handshake_timeout: Duration::from_secs(10),
+        source_filter: Default::default(),
+        require_conversion: false,
+        keep_failed_originals: true,
+        auth_token: None,
+        bandwidth: Default::default(),
+        max_substreams_per_connection: 8,
+        max_inbound_substreams: 128,
+        forward_to: None,
+        relisten: false,
     };
 
     // Create P2P node
@@ -45,6 +45,9 @@ async fn main() -> Result<()> {
         max_delay: std::time::Duration::from_secs(30),
         backoff_multiplier: 2.0,
         connection_timeout: std::time::Duration::from_secs(15),
+        dial_concurrency: 5,
+        low_reputation_attempts: 2,
+        recover_lost_responses: true,
     };
 
     let mut sender = FileSender::new(Some(retry_config)).await?;
@@ -52,6 +55,9 @@ async fn main() -> Result<()> {
     // Set up progress callback
     sender.set_progress_callback(|progress| {
         match &progress.status {
+            TransferStatus::Queued => {
+                println!("🕒 {}", progress.status_string());
+            }
             TransferStatus::Connecting => {
                 println!("🔄 Connecting to peer (attempt {})...", progress.connection_attempts);
             }
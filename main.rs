@@ -3,10 +3,13 @@ use clap::{Arg, Command};
 use futures::prelude::*;
 use libp2p::{
     identify, mdns, noise,
-    swarm::{NetworkBehaviour, SwarmEvent},
+    swarm::{behaviour::toggle::Toggle, dial_opts::DialOpts, NetworkBehaviour, SwarmEvent},
     tcp, yamux, Multiaddr, PeerId, Swarm, Transport,
 };
+use p2p_file_converter::error_handling::validation::MultiAddrValidator;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
+use std::time::{Duration, Instant};
 use tokio::io::{self, AsyncBufReadExt};
 use tracing::{debug, error, info, warn};
 
@@ -15,7 +18,7 @@ use tracing::{debug, error, info, warn};
 struct P2PBehaviour {
     identify: identify::Behaviour,
     mdns: mdns::tokio::Behaviour,
-    ping: libp2p::ping::Behaviour,
+    ping: Toggle<libp2p::ping::Behaviour>,
 }
 
 /// Configuration for the P2P file converter
@@ -23,6 +26,28 @@ struct P2PBehaviour {
 struct Config {
     listen_addr: Multiaddr,
     peer_id: PeerId,
+    /// Dial peers found via mDNS automatically; when off they are only listed
+    auto_dial: bool,
+    /// Maximum number of discovered peers being dialed at once
+    max_pending_dials: usize,
+    /// How long a dial may hold a slot before the next peer is tried
+    dial_timeout: Duration,
+    /// Wait before re-dialing a discovered peer after its first failed
+    /// dial; doubles with each further failure
+    dial_backoff: Duration,
+    /// Longest wait between dials of a peer that keeps failing
+    max_dial_backoff: Duration,
+    /// Deployment tag or hostname appended to the identify agent version
+    agent_version_suffix: Option<String>,
+    /// Ping connected peers; turning it off saves wakeups on battery or
+    /// metered links
+    ping_enabled: bool,
+    /// Time between pings to each peer
+    ping_interval: Duration,
+    /// How long a ping may go unanswered before it counts as failed
+    ping_timeout: Duration,
+    /// How long a connection with no open streams is kept before closing
+    idle_connection_timeout: Duration,
 }
 
 impl Default for Config {
@@ -33,14 +58,233 @@ impl Default for Config {
         Self {
             listen_addr: "/ip4/0.0.0.0/tcp/0".parse().unwrap(),
             peer_id: local_peer_id,
+            auto_dial: true,
+            max_pending_dials: 8,
+            dial_timeout: Duration::from_secs(10),
+            dial_backoff: DEFAULT_DIAL_BACKOFF,
+            max_dial_backoff: DEFAULT_MAX_DIAL_BACKOFF,
+            agent_version_suffix: None,
+            ping_enabled: true,
+            ping_interval: Duration::from_secs(15),
+            ping_timeout: Duration::from_secs(20),
+            idle_connection_timeout: Duration::from_secs(60),
         }
     }
 }
 
+/// Shortest idle timeout used when ping is off. Without pings nothing
+/// exchanges traffic between transfers, so a short timeout would close
+/// connections peers are about to use.
+const MIN_IDLE_TIMEOUT_WITHOUT_PING: Duration = Duration::from_secs(300);
+
+impl Config {
+    /// Idle timeout for the swarm, lengthened when ping is disabled
+    fn idle_timeout(&self) -> Duration {
+        if self.ping_enabled {
+            self.idle_connection_timeout
+        } else {
+            self.idle_connection_timeout.max(MIN_IDLE_TIMEOUT_WITHOUT_PING)
+        }
+    }
+
+    /// Ping behaviour for these settings; disabled behaves as if absent
+    fn ping_behaviour(&self) -> Toggle<libp2p::ping::Behaviour> {
+        let ping = self.ping_enabled.then(|| {
+            libp2p::ping::Behaviour::new(
+                libp2p::ping::Config::new()
+                    .with_interval(self.ping_interval)
+                    .with_timeout(self.ping_timeout),
+            )
+        });
+        Toggle::from(ping)
+    }
+}
+
+/// Agent version advertised over identify when no suffix is configured
+const AGENT_VERSION: &str = concat!("p2p-file-converter/", env!("CARGO_PKG_VERSION"));
+
+/// Longest suffix accepted; identify is exchanged on every connection
+const MAX_AGENT_VERSION_SUFFIX_LEN: usize = 64;
+
+/// Build the identify agent version, e.g. `p2p-file-converter/0.1.0 (eu-west-3)`.
+///
+/// The suffix may only contain ASCII letters, digits, `.`, `-` and `_` so it
+/// stays readable in logs and can't spoof the surrounding format.
+fn agent_version(suffix: Option<&str>) -> Result<String> {
+    let Some(suffix) = suffix.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Ok(AGENT_VERSION.to_string());
+    };
+
+    if suffix.len() > MAX_AGENT_VERSION_SUFFIX_LEN {
+        return Err(anyhow::anyhow!(
+            "Agent version suffix is {} bytes (max: {})",
+            suffix.len(),
+            MAX_AGENT_VERSION_SUFFIX_LEN
+        ));
+    }
+    if let Some(c) = suffix.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))) {
+        return Err(anyhow::anyhow!("Agent version suffix contains invalid character {:?}", c));
+    }
+
+    Ok(format!("{} ({})", AGENT_VERSION, suffix))
+}
+
+/// Default wait before re-dialing a peer whose dial failed
+const DEFAULT_DIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Default cap on the wait between dials of a failing peer
+const DEFAULT_MAX_DIAL_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Failed dials of one peer and when it may be dialed again
+#[derive(Debug, Clone, Copy)]
+struct DialBackoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+/// Discovered peers waiting to be dialed, with a cap on concurrent dials so a
+/// large LAN doesn't trigger a dial storm.
+///
+/// A peer whose dial fails waits `backoff` before it is dialed again,
+/// doubling with each further failure up to `max_backoff`. mDNS announcing
+/// the peer again doesn't shorten the wait; a connection resets it, as
+/// does going a full `max_backoff` past the retry time without failing again.
+#[derive(Debug)]
+struct DialQueue {
+    max_pending: usize,
+    timeout: Duration,
+    backoff: Duration,
+    max_backoff: Duration,
+    /// Discovered peers not yet dialed, in discovery order
+    waiting: VecDeque<PeerId>,
+    /// Known addresses of waiting peers
+    addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Dials in flight and when they started
+    pending: HashMap<PeerId, Instant>,
+    /// Peers whose last dial failed
+    failed: HashMap<PeerId, DialBackoff>,
+}
+
+impl DialQueue {
+    fn new(max_pending: usize, timeout: Duration) -> Self {
+        Self {
+            max_pending: max_pending.max(1),
+            timeout,
+            backoff: DEFAULT_DIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_DIAL_BACKOFF,
+            waiting: VecDeque::new(),
+            addresses: HashMap::new(),
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    /// Wait `initial` before re-dialing a failed peer, doubling per failure
+    /// up to `max`
+    fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Record a discovered address
+    fn discovered(&mut self, peer_id: PeerId, addr: Multiaddr) {
+        if self.pending.contains_key(&peer_id) {
+            return;
+        }
+        let addrs = self.addresses.entry(peer_id).or_default();
+        if addrs.is_empty() {
+            self.waiting.push_back(peer_id);
+        }
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+
+    /// Forget a peer that is no longer announced or no longer needs dialing
+    fn remove(&mut self, peer_id: &PeerId) {
+        self.addresses.remove(peer_id);
+        self.waiting.retain(|p| p != peer_id);
+    }
+
+    /// A dial finished, successfully or not, freeing its slot
+    fn finished(&mut self, peer_id: &PeerId) {
+        self.pending.remove(peer_id);
+    }
+
+    /// A dial failed: free its slot and hold the peer back for longer than
+    /// after its previous failure. Returns when it may be dialed again.
+    fn failed(&mut self, peer_id: &PeerId, now: Instant) -> Instant {
+        self.finished(peer_id);
+        let failures = self.failed.get(peer_id).map_or(0, |backoff| backoff.failures) + 1;
+        let delay = self.backoff
+            .saturating_mul(2u32.saturating_pow(failures - 1))
+            .min(self.max_backoff);
+        let retry_at = now + delay;
+        self.failed.insert(*peer_id, DialBackoff { failures, retry_at });
+        retry_at
+    }
+
+    /// Connected to a peer: it no longer needs dialing and its backoff starts over
+    fn connected(&mut self, peer_id: &PeerId) {
+        self.finished(peer_id);
+        self.remove(peer_id);
+        self.failed.remove(peer_id);
+    }
+
+    /// Whether a failed peer is still waiting out its backoff
+    fn backing_off(&self, peer_id: &PeerId, now: Instant) -> bool {
+        self.failed.get(peer_id).is_some_and(|backoff| now < backoff.retry_at)
+    }
+
+    /// Take a specific waiting peer for an on-demand dial
+    fn take(&mut self, peer_id: &PeerId, now: Instant) -> Option<Vec<Multiaddr>> {
+        let addrs = self.addresses.remove(peer_id)?;
+        self.waiting.retain(|p| p != peer_id);
+        self.pending.insert(*peer_id, now);
+        Some(addrs)
+    }
+
+    /// Peers to dial now, within the concurrency limit. Dials older than the
+    /// timeout stop counting against the limit, and peers backing off keep
+    /// their place in the queue.
+    fn next_dials(&mut self, now: Instant) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let timeout = self.timeout;
+        self.pending.retain(|_, started| now.duration_since(*started) < timeout);
+        // A peer that last failed more than a full backoff ago starts over
+        let max_backoff = self.max_backoff;
+        self.failed.retain(|_, backoff| now.saturating_duration_since(backoff.retry_at) < max_backoff);
+
+        let mut dials = Vec::new();
+        let mut deferred = Vec::new();
+        while self.pending.len() < self.max_pending {
+            let Some(peer_id) = self.waiting.pop_front() else { break };
+            if self.backing_off(&peer_id, now) {
+                deferred.push(peer_id);
+                continue;
+            }
+            if let Some(addrs) = self.addresses.remove(&peer_id) {
+                self.pending.insert(peer_id, now);
+                dials.push((peer_id, addrs));
+            }
+        }
+        for peer_id in deferred.into_iter().rev() {
+            self.waiting.push_front(peer_id);
+        }
+        dials
+    }
+
+    /// Discovered peers that have not been dialed
+    fn waiting(&self) -> impl Iterator<Item = (&PeerId, &Vec<Multiaddr>)> {
+        self.waiting.iter().filter_map(|p| self.addresses.get(p).map(|a| (p, a)))
+    }
+}
+
 /// Main application structure
 struct P2PFileConverter {
     swarm: Swarm<P2PBehaviour>,
     config: Config,
+    dial_queue: DialQueue,
 }
 
 impl P2PFileConverter {
@@ -61,18 +305,30 @@ impl P2PFileConverter {
 
         // Create network behaviour
         let behaviour = P2PBehaviour {
-            identify: identify::Behaviour::new(identify::Config::new(
-                "/p2p-file-converter/1.0.0".to_string(),
-                local_key.public(),
-            )),
+            identify: identify::Behaviour::new(
+                identify::Config::new("/p2p-file-converter/1.0.0".to_string(), local_key.public())
+                    .with_agent_version(agent_version(config.agent_version_suffix.as_deref())?),
+            ),
             mdns: mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)?,
-            ping: libp2p::ping::Behaviour::new(libp2p::ping::Config::new()),
+            ping: config.ping_behaviour(),
         };
 
-        // Create swarm
-        let swarm = Swarm::with_tokio_executor(transport, behaviour, local_peer_id);
+        if !config.ping_enabled {
+            info!("Ping disabled; idle connections close after {:?}", config.idle_timeout());
+        }
 
-        Ok(Self { swarm, config })
+        // Create swarm
+        let swarm = Swarm::new(
+            transport,
+            behaviour,
+            local_peer_id,
+            libp2p::swarm::Config::with_tokio_executor()
+                .with_idle_connection_timeout(config.idle_timeout()),
+        );
+
+        let dial_queue = DialQueue::new(config.max_pending_dials, config.dial_timeout)
+            .with_backoff(config.dial_backoff, config.max_dial_backoff);
+        Ok(Self { swarm, config, dial_queue })
     }
 
     /// Start listening for connections
@@ -87,6 +343,7 @@ impl P2PFileConverter {
         info!("Starting P2P File Converter event loop");
 
         let mut stdin = io::BufReader::new(io::stdin()).lines();
+        let mut dial_tick = tokio::time::interval(Duration::from_secs(1));
 
         loop {
             tokio::select! {
@@ -95,6 +352,10 @@ impl P2PFileConverter {
                         self.handle_user_input(line.trim()).await?;
                     }
                 }
+                _ = dial_tick.tick() => {
+                    // Frees slots held by timed-out dials
+                    self.dial_discovered();
+                }
                 event = self.swarm.select_next_some() => {
                     self.handle_swarm_event(event).await?;
                 }
@@ -115,6 +376,22 @@ impl P2PFileConverter {
                 info!("Shutting down...");
                 return Err(anyhow::anyhow!("User requested shutdown"));
             }
+            "discovered" => {
+                info!("Discovered peers not dialed:");
+                for (peer_id, addrs) in self.dial_queue.waiting() {
+                    info!("  {} ({} addresses)", peer_id, addrs.len());
+                }
+            }
+            _ if input.starts_with("dial-peer ") => {
+                let peer = input.trim_start_matches("dial-peer ").trim();
+                match peer.parse::<PeerId>() {
+                    Ok(peer_id) => match self.dial_queue.take(&peer_id, Instant::now()) {
+                        Some(addrs) => self.dial_peer(peer_id, addrs),
+                        None => warn!("Peer {} has not been discovered or is already being dialed", peer_id),
+                    },
+                    Err(e) => error!("Invalid peer ID '{}': {}", peer, e),
+                }
+            }
             _ if input.starts_with("connect ") => {
                 let addr = input.trim_start_matches("connect ");
                 match addr.parse::<Multiaddr>() {
@@ -132,20 +409,56 @@ impl P2PFileConverter {
             }
             _ => {
                 warn!("Unknown command: {}", input);
-                info!("Available commands: peers, connect <multiaddr>, quit/exit");
+                info!("Available commands: peers, discovered, dial-peer <peer_id>, connect <multiaddr>, quit/exit");
             }
         }
         Ok(())
     }
 
+    /// Dial queued discovered peers, up to the concurrency limit
+    fn dial_discovered(&mut self) {
+        if !self.config.auto_dial {
+            return;
+        }
+        for (peer_id, addrs) in self.dial_queue.next_dials(Instant::now()) {
+            self.dial_peer(peer_id, addrs);
+        }
+    }
+
+    /// Dial a discovered peer on all of its known addresses
+    fn dial_peer(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>) {
+        let opts = DialOpts::peer_id(peer_id).addresses(addrs).build();
+        if let Err(e) = self.swarm.dial(opts) {
+            debug!("Failed to dial discovered peer {}: {}", peer_id, e);
+            self.dial_queue.failed(&peer_id, Instant::now());
+        }
+    }
+
     /// Handle swarm events
     async fn handle_swarm_event(&mut self, event: SwarmEvent<P2PBehaviourEvent>) -> Result<()> {
         match event {
             SwarmEvent::NewListenAddr { address, .. } => {
                 info!("Listening on {}", address);
             }
+            SwarmEvent::ListenerError { listener_id, error } => {
+                error!("Listener {:?} failed: {}", listener_id, error);
+            }
+            SwarmEvent::ListenerClosed { addresses, reason, .. } => {
+                error!("Listener on {:?} closed: {:?}", addresses, reason);
+            }
             SwarmEvent::ConnectionEstablished { peer_id, .. } => {
                 info!("Connected to peer: {}", peer_id);
+                self.dial_queue.connected(&peer_id);
+                self.dial_discovered();
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
+                let now = Instant::now();
+                let retry_at = self.dial_queue.failed(&peer_id, now);
+                debug!(
+                    "Failed to connect to {}: {}; not dialing it again for {:?}",
+                    peer_id, error, retry_at.duration_since(now)
+                );
+                self.dial_discovered();
             }
             SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
                 info!("Connection to peer {} closed: {:?}", peer_id, cause);
@@ -153,14 +466,16 @@ impl P2PFileConverter {
             SwarmEvent::Behaviour(P2PBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
                 for (peer_id, multiaddr) in list {
                     debug!("Discovered peer {} at {}", peer_id, multiaddr);
-                    if let Err(e) = self.swarm.dial(multiaddr.clone()) {
-                        debug!("Failed to dial discovered peer {}: {}", peer_id, e);
+                    if !self.swarm.is_connected(&peer_id) {
+                        self.dial_queue.discovered(peer_id, multiaddr);
                     }
                 }
+                self.dial_discovered();
             }
             SwarmEvent::Behaviour(P2PBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
                 for (peer_id, _multiaddr) in list {
                     debug!("Peer {} expired from mDNS", peer_id);
+                    self.dial_queue.remove(&peer_id);
                 }
             }
             SwarmEvent::Behaviour(P2PBehaviourEvent::Identify(identify::Event::Received { 
@@ -266,6 +581,78 @@ fn parse_args() -> Command {
                 .value_name("MULTIADDR")
                 .help("Address of a peer to connect to")
         )
+        .arg(
+            Arg::new("peer-file")
+                .long("peer-file")
+                .value_name("FILE")
+                .help("File with known peer multiaddrs to dial at startup (one per line)")
+        )
+        .arg(
+            Arg::new("no-auto-dial")
+                .long("no-auto-dial")
+                .action(clap::ArgAction::SetTrue)
+                .help("Only list peers found via mDNS; dial them with 'dial-peer'")
+        )
+        .arg(
+            Arg::new("max-dials")
+                .long("max-dials")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("8")
+                .help("Maximum number of discovered peers dialed at once")
+        )
+        .arg(
+            Arg::new("dial-backoff")
+                .long("dial-backoff")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("5")
+                .help("Seconds before re-dialing a discovered peer that refused a connection; doubles per failure")
+        )
+        .arg(
+            Arg::new("max-dial-backoff")
+                .long("max-dial-backoff")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("300")
+                .help("Longest wait between dials of a discovered peer that keeps failing")
+        )
+        .arg(
+            Arg::new("agent-suffix")
+                .long("agent-suffix")
+                .value_name("TAG")
+                .help("Deployment tag or hostname added to the agent version peers see")
+        )
+        .arg(
+            Arg::new("no-ping")
+                .long("no-ping")
+                .action(clap::ArgAction::SetTrue)
+                .help("Don't ping connected peers (idle timeout is raised to at least 5 minutes)")
+        )
+        .arg(
+            Arg::new("ping-interval")
+                .long("ping-interval")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("15")
+                .help("Seconds between pings to each peer")
+        )
+        .arg(
+            Arg::new("ping-timeout")
+                .long("ping-timeout")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("20")
+                .help("Seconds before an unanswered ping fails")
+        )
+        .arg(
+            Arg::new("idle-timeout")
+                .long("idle-timeout")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("60")
+                .help("Seconds an unused connection stays open")
+        )
 }
 
 #[tokio::main]
@@ -286,6 +673,28 @@ async fn main() -> Result<()> {
             .map_err(|e| anyhow::anyhow!("Invalid listen address: {}", e))?;
     }
 
+    config.auto_dial = !matches.get_flag("no-auto-dial");
+    if let Some(max_dials) = matches.get_one::<usize>("max-dials") {
+        config.max_pending_dials = *max_dials;
+    }
+    if let Some(secs) = matches.get_one::<u64>("dial-backoff") {
+        config.dial_backoff = Duration::from_secs(*secs);
+    }
+    if let Some(secs) = matches.get_one::<u64>("max-dial-backoff") {
+        config.max_dial_backoff = Duration::from_secs(*secs);
+    }
+    config.agent_version_suffix = matches.get_one::<String>("agent-suffix").cloned();
+    config.ping_enabled = !matches.get_flag("no-ping");
+    if let Some(secs) = matches.get_one::<u64>("ping-interval") {
+        config.ping_interval = Duration::from_secs(*secs);
+    }
+    if let Some(secs) = matches.get_one::<u64>("ping-timeout") {
+        config.ping_timeout = Duration::from_secs(*secs);
+    }
+    if let Some(secs) = matches.get_one::<u64>("idle-timeout") {
+        config.idle_connection_timeout = Duration::from_secs(*secs);
+    }
+
     // Initialize P2P file converter
     let mut converter = P2PFileConverter::new(config).await?;
 
@@ -301,8 +710,19 @@ async fn main() -> Result<()> {
         converter.swarm.dial(multiaddr)?;
     }
 
+    // Dial known peers from --peer-file
+    if let Some(path) = matches.get_one::<String>("peer-file") {
+        let peers = MultiAddrValidator::new().load_peer_file(path).await?;
+        info!("Loaded {} peers from {}", peers.len(), path);
+        for addr in peers {
+            if let Err(e) = converter.swarm.dial(addr.clone()) {
+                warn!("Failed to dial known peer {}: {}", addr, e);
+            }
+        }
+    }
+
     info!("P2P File Converter started successfully!");
-    info!("Commands: peers, connect <multiaddr>, quit/exit");
+    info!("Commands: peers, discovered, dial-peer <peer_id>, connect <multiaddr>, quit/exit");
 
     // Run the main event loop
     if let Err(e) = converter.run().await {
@@ -326,9 +746,107 @@ mod tests {
         assert_eq!(config.listen_addr.to_string(), "/ip4/0.0.0.0/tcp/0");
     }
 
+    #[test]
+    fn test_agent_version_suffix() {
+        assert_eq!(agent_version(None).unwrap(), AGENT_VERSION);
+        assert_eq!(agent_version(Some("  ")).unwrap(), AGENT_VERSION);
+        assert_eq!(
+            agent_version(Some("node-7.eu_west")).unwrap(),
+            format!("{} (node-7.eu_west)", AGENT_VERSION)
+        );
+
+        assert!(agent_version(Some(&"x".repeat(MAX_AGENT_VERSION_SUFFIX_LEN + 1))).is_err());
+        assert!(agent_version(Some("a) (b")).is_err());
+    }
+
+    #[test]
+    fn test_ping_toggle_keeps_connections_open() {
+        let config = Config::default();
+        assert!(config.ping_behaviour().is_enabled());
+        assert_eq!(config.idle_timeout(), Duration::from_secs(60));
+
+        let config = Config { ping_enabled: false, ..Config::default() };
+        assert!(!config.ping_behaviour().is_enabled());
+        assert_eq!(config.idle_timeout(), MIN_IDLE_TIMEOUT_WITHOUT_PING);
+
+        let config = Config {
+            ping_enabled: false,
+            idle_connection_timeout: Duration::from_secs(900),
+            ..Config::default()
+        };
+        assert_eq!(config.idle_timeout(), Duration::from_secs(900));
+
+        let matches = parse_args().try_get_matches_from(["p2p-file-converter", "--no-ping"]).unwrap();
+        assert!(matches.get_flag("no-ping"));
+        assert!(parse_args().try_get_matches_from(["p2p-file-converter", "--ping-interval", "0"]).is_err());
+    }
+
     #[test]
     fn test_parse_args() {
         let cmd = parse_args();
         assert_eq!(cmd.get_name(), "p2p-file-converter");
     }
+
+    #[test]
+    fn test_dial_queue_limits_concurrent_dials() {
+        let mut queue = DialQueue::new(2, Duration::from_secs(10));
+        let addr: Multiaddr = "/ip4/192.168.1.10/tcp/4001".parse().unwrap();
+        let peers: Vec<PeerId> = (0..5).map(|_| PeerId::random()).collect();
+        for peer in &peers {
+            queue.discovered(*peer, addr.clone());
+            // Duplicate announcements don't queue a peer twice
+            queue.discovered(*peer, addr.clone());
+        }
+
+        let start = Instant::now();
+        let first = queue.next_dials(start);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].1.len(), 1);
+        assert!(queue.next_dials(start).is_empty());
+        assert_eq!(queue.waiting().count(), 3);
+
+        // A finished dial frees a slot
+        queue.finished(&peers[0]);
+        assert_eq!(queue.next_dials(start).len(), 1);
+
+        // On-demand dials bypass the queue order
+        assert!(queue.take(&peers[4], start).is_some());
+        assert!(queue.take(&peers[4], start).is_none());
+        assert_eq!(queue.waiting().count(), 1);
+
+        // Timed-out dials stop holding slots
+        assert_eq!(queue.next_dials(start + Duration::from_secs(11)).len(), 1);
+        assert_eq!(queue.waiting().count(), 0);
+    }
+
+    #[test]
+    fn test_dial_backoff_after_failures() {
+        let mut queue = DialQueue::new(4, Duration::from_secs(10))
+            .with_backoff(Duration::from_secs(5), Duration::from_secs(20));
+        let addr: Multiaddr = "/ip4/192.168.1.20/tcp/4001".parse().unwrap();
+        let peer = PeerId::random();
+        let start = Instant::now();
+
+        queue.discovered(peer, addr.clone());
+        assert_eq!(queue.next_dials(start).len(), 1);
+        assert_eq!(queue.failed(&peer, start), start + Duration::from_secs(5));
+
+        // Expiring and being rediscovered doesn't cut the wait short
+        queue.remove(&peer);
+        queue.discovered(peer, addr.clone());
+        assert!(queue.next_dials(start + Duration::from_secs(4)).is_empty());
+        assert_eq!(queue.waiting().count(), 1);
+
+        // Each failure doubles the wait, up to the cap
+        let retry = start + Duration::from_secs(5);
+        assert_eq!(queue.next_dials(retry).len(), 1);
+        assert_eq!(queue.failed(&peer, retry), retry + Duration::from_secs(10));
+        assert_eq!(queue.failed(&peer, retry), retry + Duration::from_secs(20));
+        assert_eq!(queue.failed(&peer, retry), retry + Duration::from_secs(20));
+
+        // A connection resets it
+        queue.connected(&peer);
+        queue.discovered(peer, addr);
+        assert_eq!(queue.next_dials(retry).len(), 1);
+    }
 }
@@ -64,7 +64,7 @@ async fn main() -> Result<()> {
 
         // Convert based on type
         match file_type {
-            FileType::Text => {
+            FileType::Text | FileType::Code => {
                 let output_path = file_path.with_extension("pdf");
 
                 match converter.text_file_to_pdf(&file_path, &output_path, &pdf_config) {
@@ -92,6 +92,24 @@ async fn main() -> Result<()> {
                 }
             }
 
+            FileType::Image => {
+                let output_path = file_path.with_extension("pdf");
+
+                let converted = std::fs::read(&file_path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|data| converter.images_to_pdf(vec![data], &pdf_config))
+                    .and_then(|pdf| Ok(std::fs::write(&output_path, pdf)?));
+                match converted {
+                    Ok(()) => {
+                        info!("✅ Converted image {} to PDF", file_name);
+                        conversion_count += 1;
+                    }
+                    Err(e) => {
+                        info!("❌ Failed to convert image {} to PDF: {}", file_name, e);
+                    }
+                }
+            }
+
             FileType::Unknown => {
                 info!("⚠️  Unknown file type for {}, skipping", file_name);
             }
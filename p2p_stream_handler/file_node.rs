@@ -28,6 +28,10 @@ struct Args {
     #[arg(long, default_value = "5")]
     max_transfers: usize,
 
+    /// Maximum concurrent transfers from a single peer
+    #[arg(long)]
+    max_transfers_per_peer: Option<usize>,
+
     /// Enable auto-conversion of received files
     #[arg(long)]
     auto_convert: bool,
@@ -66,6 +70,7 @@ async fn main() -> Result<()> {
     } else {
         FileConversionConfig {
             max_concurrent_transfers: args.max_transfers,
+            max_transfers_per_peer: args.max_transfers_per_peer,
             output_dir: args.output_dir,
             auto_convert: args.auto_convert,
             return_results: args.return_results,
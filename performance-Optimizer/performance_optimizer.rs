@@ -606,6 +606,15 @@ mod fuzz_tests {
                     return_result: false,
                     chunk_count: 1,
                     metadata: input.metadata.iter().cloned().collect(),
+                    protocol_version: Default::default(),
+                    is_archive: false,
+                    max_inline_result: 16 * 1024 * 1024,
+                    max_response_size: 256 * 1024 * 1024,
+                    conversion_timeout_ms: None,
+                    auth_token: None,
+                    kind: Default::default(),
+                    adaptive_chunks: false,
+                    inline_data: None,
                 };
 
                 let validator = MessageValidator::new();
@@ -30,6 +30,8 @@ pub struct AdvancedProgressManager {
     multi_progress: Arc<MultiProgress>,
     active_bars: Arc<RwLock<HashMap<String, ProgressBarInfo>>>,
     config: ProgressConfig,
+    /// Bar style compiled once from the configured template
+    style: ProgressStyle,
 }
 
 #[derive(Debug, Clone)]
@@ -70,6 +72,32 @@ impl Default for ProgressConfig {
     }
 }
 
+/// Template used when colors are off, and when the configured one is invalid
+const PLAIN_PROGRESS_TEMPLATE: &str = "[{elapsed_precise}] {bar:40} {pos:>7}/{len:7} {msg} ({eta})";
+
+impl ProgressConfig {
+    /// Compile the bar style this configuration describes, failing with the
+    /// template and indicatif's reason if the template is invalid
+    pub fn style(&self) -> Result<ProgressStyle> {
+        let style = if self.enable_colors {
+            ProgressStyle::default_bar()
+                .template(&self.progress_template)
+                .with_context(|| format!("Invalid progress bar template '{}'", self.progress_template))?
+                .progress_chars("█▉▊▋▌▍▎▏  ")
+        } else {
+            ProgressStyle::default_bar()
+                .template(PLAIN_PROGRESS_TEMPLATE)?
+                .progress_chars("##-")
+        };
+        Ok(style)
+    }
+
+    /// Check the template compiles
+    pub fn validate(&self) -> Result<()> {
+        self.style().map(|_| ())
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ProgressBarInfo {
     progress_bar: ProgressBar,
@@ -93,10 +121,21 @@ impl AdvancedProgressManager {
 
         multi_progress.set_draw_target(draw_target);
 
+        // A bad template would otherwise only fail when the first transfer
+        // starts; report it now and keep showing progress with the default
+        let style = config.style().unwrap_or_else(|e| {
+            warn!("{:#}; using the default progress bar template", e);
+            ProgressStyle::default_bar()
+                .template(PLAIN_PROGRESS_TEMPLATE)
+                .expect("default progress template is valid")
+                .progress_chars("##-")
+        });
+
         Self {
             multi_progress: Arc::new(multi_progress),
             active_bars: Arc::new(RwLock::new(HashMap::new())),
             config,
+            style,
         }
     }
 
@@ -121,18 +160,7 @@ impl AdvancedProgressManager {
 
         let progress_bar = self.multi_progress.add(ProgressBar::new(total_size));
 
-        // Set progress bar style
-        let style = if self.config.enable_colors {
-            ProgressStyle::default_bar()
-                .template(&self.config.progress_template)?
-                .progress_chars("█▉▊▋▌▍▎▏  ")
-        } else {
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {bar:40} {pos:>7}/{len:7} {msg} ({eta})")?
-                .progress_chars("##-")
-        };
-
-        progress_bar.set_style(style);
+        progress_bar.set_style(self.style.clone());
         progress_bar.set_message(message.to_string());
 
         let bar_info = ProgressBarInfo {
@@ -159,6 +187,14 @@ impl AdvancedProgressManager {
         })
     }
 
+    /// Writer for log output that hides the bars while a line is written, so
+    /// logs and bars sharing a terminal don't overwrite each other
+    pub fn log_writer(&self) -> ProgressLogWriter {
+        ProgressLogWriter {
+            multi_progress: Arc::clone(&self.multi_progress),
+        }
+    }
+
     /// Get progress statistics for all operations
     pub fn get_progress_summary(&self) -> ProgressSummary {
         let active_bars = self.active_bars.read().unwrap();
@@ -278,6 +314,31 @@ impl Drop for ProgressBarHandle {
     }
 }
 
+/// Log writer returned by [`AdvancedProgressManager::log_writer`]; writes to
+/// stdout with the bars suspended
+#[derive(Clone)]
+pub struct ProgressLogWriter {
+    multi_progress: Arc<MultiProgress>,
+}
+
+impl std::io::Write for ProgressLogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.multi_progress.suspend(|| std::io::stdout().write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for ProgressLogWriter {
+    type Writer = ProgressLogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ProgressSummary {
     pub total_operations: usize,
@@ -794,6 +855,23 @@ mod tests {
         assert_eq!(summary.total_operations, 1);
     }
 
+    #[test]
+    fn test_invalid_template_falls_back() {
+        let config = ProgressConfig {
+            progress_template: "{spinner} {bar:40 {msg}".to_string(),
+            ..ProgressConfig::default()
+        };
+        let err = config.validate().unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid progress bar template"));
+        assert!(ProgressConfig::default().validate().is_ok());
+
+        // The manager still hands out bars, using the default template
+        let manager = AdvancedProgressManager::new(config);
+        let handle = manager.create_progress_bar("typo", "file_transfer", 100, "typo").unwrap();
+        handle.update_progress(50, None);
+        assert_eq!(handle.get_percentage(), 50.0);
+    }
+
     #[tokio::test]
     async fn test_metrics_collector() {
         let config = MetricsConfig {
@@ -404,6 +404,12 @@ impl P2PSwarmManager {
             SwarmEvent::NewListenAddr { address, .. } => {
                 info!("New listen address: {}", address);
             }
+            SwarmEvent::ListenerError { listener_id, error } => {
+                error!("Listener {:?} failed: {}", listener_id, error);
+            }
+            SwarmEvent::ListenerClosed { addresses, reason, .. } => {
+                error!("Listener on {:?} closed: {:?}", addresses, reason);
+            }
             SwarmEvent::ConnectionEstablished {
                 peer_id, endpoint, ..
             } => {
@@ -21,7 +21,10 @@ use crate::{
     error_handling::{P2PError, Result as P2PResult, validation::*},
     file_converter::{FileConverter, FileType, PdfConfig},
     file_sender::{FileSender, RetryConfig, SendProgress, TransferStatus},
-    p2p_stream_handler::{FileConversionService, FileConversionConfig, P2PFileNode},
+    p2p_stream_handler::{
+        FileConversionService, FileConversionConfig, P2PFileNode, ProtocolVersion,
+        DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE,
+    },
     main_event_loop::{P2PFileConverter, ShutdownReason},
 };
 
@@ -495,6 +498,9 @@ mod networking_tests {
             max_delay: Duration::from_secs(5),
             backoff_multiplier: 1.5,
             connection_timeout: Duration::from_secs(10),
+            dial_concurrency: 5,
+            low_reputation_attempts: 2,
+            recover_lost_responses: true,
         };
 
         let result = FileSender::new(Some(retry_config)).await;
@@ -509,10 +515,56 @@ mod networking_tests {
     async fn test_p2p_node_creation() {
         let config = FileConversionConfig {
             max_concurrent_transfers: 3,
+            max_transfers_per_peer: None,
             output_dir: PathBuf::from("./test_output"),
             auto_convert: true,
             return_results: false,
             pdf_config: PdfConfig::default(),
+            reputation: Default::default(),
+            text_config: Default::default(),
+            conversion_limits: Default::default(),
+            allowed_target_formats: None,
+            history: None,
+            security: Default::default(),
+            fsync_on_complete: false,
+            output_permissions: Default::default(),
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            memory_budget_bytes: 256 * 1024 * 1024,
+            replace_original: false,
+            peer_target_formats: HashMap::new(),
+            accepted_source_types: None,
+            conversion_cache: None,
+            original_retention: None,
+            converted_retention: None,
+            converted_ttl: None,
+            transport: Default::default(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_concurrent_conversions: 1,
+            extract_archives: false,
+            lossy_utf8: false,
+            verify_output: true,
+            min_free_memory: 0,
+            max_inline_result_bytes: 16 * 1024 * 1024,
+            transfer_log_dir: None,
+            keep_transfer_logs: false,
+            resume_dir: None,
+            resume_max_age: Duration::from_secs(24 * 60 * 60),
+            same_format: Default::default(),
+            default_target_format: None,
+            preload_fonts: false,
+            conversion_timeout: Duration::from_secs(120),
+            max_conversion_timeout: Duration::from_secs(600),
+            handshake_timeout: Duration::from_secs(10),
+            source_filter: Default::default(),
+            require_conversion: false,
+            keep_failed_originals: true,
+            auth_token: None,
+            bandwidth: Default::default(),
+            max_substreams_per_connection: 8,
+            max_inbound_substreams: 128,
+            forward_to: None,
+            relisten: false,
         };
 
         let result = P2PFileNode::new(config).await;
@@ -523,10 +575,56 @@ mod networking_tests {
     async fn test_file_conversion_service() {
         let config = FileConversionConfig {
             max_concurrent_transfers: 2,
+            max_transfers_per_peer: None,
             output_dir: PathBuf::from("./test_output"),
             auto_convert: false,
             return_results: true,
             pdf_config: PdfConfig::default(),
+            reputation: Default::default(),
+            text_config: Default::default(),
+            conversion_limits: Default::default(),
+            allowed_target_formats: None,
+            history: None,
+            security: Default::default(),
+            fsync_on_complete: false,
+            output_permissions: Default::default(),
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            memory_budget_bytes: 256 * 1024 * 1024,
+            replace_original: false,
+            peer_target_formats: HashMap::new(),
+            accepted_source_types: None,
+            conversion_cache: None,
+            original_retention: None,
+            converted_retention: None,
+            converted_ttl: None,
+            transport: Default::default(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_concurrent_conversions: 1,
+            extract_archives: false,
+            lossy_utf8: false,
+            verify_output: true,
+            min_free_memory: 0,
+            max_inline_result_bytes: 16 * 1024 * 1024,
+            transfer_log_dir: None,
+            keep_transfer_logs: false,
+            resume_dir: None,
+            resume_max_age: Duration::from_secs(24 * 60 * 60),
+            same_format: Default::default(),
+            default_target_format: None,
+            preload_fonts: false,
+            conversion_timeout: Duration::from_secs(120),
+            max_conversion_timeout: Duration::from_secs(600),
+            handshake_timeout: Duration::from_secs(10),
+            source_filter: Default::default(),
+            require_conversion: false,
+            keep_failed_originals: true,
+            auth_token: None,
+            bandwidth: Default::default(),
+            max_substreams_per_connection: 8,
+            max_inbound_substreams: 128,
+            forward_to: None,
+            relisten: false,
         };
 
         let result = FileConversionService::new(config);
@@ -554,6 +652,11 @@ mod networking_tests {
             status: TransferStatus::Sending,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: crate::file_sender::DEFAULT_PRIORITY,
+            throughput: Default::default(),
+            finished_at: None,
         };
 
         callback(&progress);
@@ -591,6 +694,11 @@ mod networking_tests {
             status: TransferStatus::Connecting,
             connection_attempts: 1,
             last_error: None,
+            estimated_start: None,
+            estimated_completion: None,
+            priority: crate::file_sender::DEFAULT_PRIORITY,
+            throughput: Default::default(),
+            finished_at: None,
         };
 
         for (i, status) in statuses.iter().enumerate() {
@@ -630,18 +738,110 @@ mod e2e_tests {
         // Create two P2P nodes
         let receiver_config = FileConversionConfig {
             max_concurrent_transfers: 2,
+            max_transfers_per_peer: None,
             output_dir: temp_dir.path().join("received"),
             auto_convert: false,
             return_results: true,
             pdf_config: PdfConfig::default(),
+            reputation: Default::default(),
+            text_config: Default::default(),
+            conversion_limits: Default::default(),
+            allowed_target_formats: None,
+            history: None,
+            security: Default::default(),
+            fsync_on_complete: false,
+            output_permissions: Default::default(),
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            memory_budget_bytes: 256 * 1024 * 1024,
+            replace_original: false,
+            peer_target_formats: HashMap::new(),
+            accepted_source_types: None,
+            conversion_cache: None,
+            original_retention: None,
+            converted_retention: None,
+            converted_ttl: None,
+            transport: Default::default(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_concurrent_conversions: 1,
+            extract_archives: false,
+            lossy_utf8: false,
+            verify_output: true,
+            min_free_memory: 0,
+            max_inline_result_bytes: 16 * 1024 * 1024,
+            transfer_log_dir: None,
+            keep_transfer_logs: false,
+            resume_dir: None,
+            resume_max_age: Duration::from_secs(24 * 60 * 60),
+            same_format: Default::default(),
+            default_target_format: None,
+            preload_fonts: false,
+            conversion_timeout: Duration::from_secs(120),
+            max_conversion_timeout: Duration::from_secs(600),
+            handshake_timeout: Duration::from_secs(10),
+            source_filter: Default::default(),
+            require_conversion: false,
+            keep_failed_originals: true,
+            auth_token: None,
+            bandwidth: Default::default(),
+            max_substreams_per_connection: 8,
+            max_inbound_substreams: 128,
+            forward_to: None,
+            relisten: false,
         };
 
         let sender_config = FileConversionConfig {
             max_concurrent_transfers: 2,
+            max_transfers_per_peer: None,
             output_dir: temp_dir.path().join("temp"),
             auto_convert: false,
             return_results: false,
             pdf_config: PdfConfig::default(),
+            reputation: Default::default(),
+            text_config: Default::default(),
+            conversion_limits: Default::default(),
+            allowed_target_formats: None,
+            history: None,
+            security: Default::default(),
+            fsync_on_complete: false,
+            output_permissions: Default::default(),
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            memory_budget_bytes: 256 * 1024 * 1024,
+            replace_original: false,
+            peer_target_formats: HashMap::new(),
+            accepted_source_types: None,
+            conversion_cache: None,
+            original_retention: None,
+            converted_retention: None,
+            converted_ttl: None,
+            transport: Default::default(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_concurrent_conversions: 1,
+            extract_archives: false,
+            lossy_utf8: false,
+            verify_output: true,
+            min_free_memory: 0,
+            max_inline_result_bytes: 16 * 1024 * 1024,
+            transfer_log_dir: None,
+            keep_transfer_logs: false,
+            resume_dir: None,
+            resume_max_age: Duration::from_secs(24 * 60 * 60),
+            same_format: Default::default(),
+            default_target_format: None,
+            preload_fonts: false,
+            conversion_timeout: Duration::from_secs(120),
+            max_conversion_timeout: Duration::from_secs(600),
+            handshake_timeout: Duration::from_secs(10),
+            source_filter: Default::default(),
+            require_conversion: false,
+            keep_failed_originals: true,
+            auth_token: None,
+            bandwidth: Default::default(),
+            max_substreams_per_connection: 8,
+            max_inbound_substreams: 128,
+            forward_to: None,
+            relisten: false,
         };
 
         let receiver_result = P2PFileNode::new(receiver_config).await;
@@ -682,6 +882,9 @@ mod e2e_tests {
             max_delay: Duration::from_secs(5),
             backoff_multiplier: 1.5,
             connection_timeout: Duration::from_secs(5),
+            dial_concurrency: 5,
+            low_reputation_attempts: 2,
+            recover_lost_responses: true,
         };
 
         let sender_result = FileSender::new(Some(retry_config)).await;
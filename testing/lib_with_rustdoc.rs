@@ -67,6 +67,9 @@
 //!         max_delay: Duration::from_secs(30),
 //!         backoff_multiplier: 2.0,
 //!         connection_timeout: Duration::from_secs(15),
+//!         dial_concurrency: 5,
+//!         low_reputation_attempts: 2,
+//!         recover_lost_responses: true,
 //!     };
 //!     
 //!     let mut sender = FileSender::new(Some(retry_config)).await?;